@@ -2,7 +2,7 @@
 
 extern crate test;
 
-use mockito::Server;
+use mockito::{Matcher, Server};
 use std::fmt::Display;
 use std::io::{BufRead, BufReader, Read, Write};
 use std::net::TcpStream;
@@ -74,3 +74,23 @@ fn bench_match_simple_mock(b: &mut Bencher) {
         assert!(status_line.starts_with("HTTP/1.1 200"));
     })
 }
+
+#[bench]
+fn bench_match_regex_mock(b: &mut Bencher) {
+    let mut s = Server::new();
+
+    let _m = s
+        .mock("GET", Matcher::Regex(r"^/users/\d+$".to_string()))
+        .match_header("authorization", Matcher::Regex("^Bearer .+$".to_string()))
+        .with_body("test")
+        .create();
+
+    b.iter(|| {
+        let (status_line, _, _) = request(
+            &s.host_with_port(),
+            "GET /users/1",
+            "authorization: Bearer token\r\n",
+        );
+        assert!(status_line.starts_with("HTTP/1.1 200"));
+    })
+}
@@ -0,0 +1,188 @@
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+
+///
+/// State shared between a connection and the requests it serves.
+///
+#[derive(Clone, Debug)]
+pub(crate) struct Connection {
+    interim: Arc<Mutex<Vec<u8>>>,
+    requests: Arc<AtomicUsize>,
+    in_flight: Arc<AtomicUsize>,
+    reset: Arc<AtomicBool>,
+    connected_at: Instant,
+    last_activity: Arc<Mutex<Instant>>,
+    local_addr: Option<SocketAddr>,
+    remote_addr: Option<SocketAddr>,
+}
+
+impl Connection {
+    pub(crate) fn new(local_addr: Option<SocketAddr>, remote_addr: Option<SocketAddr>) -> Self {
+        let connected_at = Instant::now();
+
+        Connection {
+            interim: Arc::default(),
+            requests: Arc::default(),
+            in_flight: Arc::default(),
+            reset: Arc::default(),
+            connected_at,
+            last_activity: Arc::new(Mutex::new(connected_at)),
+            local_addr,
+            remote_addr,
+        }
+    }
+
+    /// The local address of the connection
+    pub(crate) fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    /// The address of the client
+    pub(crate) fn remote_addr(&self) -> Option<SocketAddr> {
+        self.remote_addr
+    }
+
+    /// The time elapsed since the connection was accepted
+    pub(crate) fn elapsed(&self) -> Duration {
+        self.connected_at.elapsed()
+    }
+
+    /// Returns the zero-based index of the next request served on this connection
+    pub(crate) fn next_request_index(&self) -> usize {
+        self.requests.fetch_add(1, Ordering::SeqCst)
+    }
+
+    /// Marks the connection to be closed with a TCP reset once it's dropped
+    pub(crate) fn reset(&self) {
+        self.reset.store(true, Ordering::SeqCst);
+    }
+
+    /// Marks a request as being served until the returned guard is dropped
+    pub(crate) fn start_request(&self) -> InFlightRequest {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlightRequest(self.clone())
+    }
+
+    /// Resolves once no request was served and no data was read or written for the given
+    /// duration
+    pub(crate) async fn idle(&self, timeout: Duration) {
+        loop {
+            if self.in_flight.load(Ordering::SeqCst) > 0 {
+                tokio::time::sleep(timeout).await;
+                continue;
+            }
+            let idle_for = self.last_activity.lock().unwrap().elapsed();
+            if idle_for >= timeout {
+                return;
+            }
+            tokio::time::sleep(timeout - idle_for).await;
+        }
+    }
+
+    fn touch(&self) {
+        *self.last_activity.lock().unwrap() = Instant::now();
+    }
+
+    /// Queues raw bytes to be written before the next response on this connection
+    pub(crate) fn write_interim(&self, bytes: &[u8]) {
+        self.interim.lock().unwrap().extend_from_slice(bytes);
+    }
+}
+
+///
+/// Keeps the idle timer of a connection from running while a request is being served.
+///
+#[derive(Debug)]
+pub(crate) struct InFlightRequest(Connection);
+
+impl Drop for InFlightRequest {
+    fn drop(&mut self) {
+        // the connection is idle from now on
+        self.0.touch();
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+///
+/// Wraps the IO of a connection, allowing raw bytes to be written ahead of the responses
+/// produced by hyper.
+///
+pub(crate) struct ConnectionIo {
+    inner: TcpStream,
+    connection: Connection,
+}
+
+impl ConnectionIo {
+    pub(crate) fn new(inner: TcpStream, connection: Connection) -> Self {
+        ConnectionIo { inner, connection }
+    }
+
+    fn poll_write_interim(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let interim = self.connection.interim.clone();
+        let mut interim = interim.lock().unwrap();
+
+        while !interim.is_empty() {
+            let written = ready!(Pin::new(&mut self.inner).poll_write(cx, &interim))?;
+            if written == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            interim.drain(..written);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for ConnectionIo {
+    fn drop(&mut self) {
+        if self.connection.reset.load(Ordering::SeqCst) {
+            // a zero linger makes the socket send RST instead of FIN when closed
+            #[allow(deprecated)]
+            let _ = self.inner.set_linger(Some(Duration::ZERO));
+        }
+    }
+}
+
+impl AsyncRead for ConnectionIo {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let result = ready!(Pin::new(&mut this.inner).poll_read(cx, buf));
+        this.connection.touch();
+        Poll::Ready(result)
+    }
+}
+
+impl AsyncWrite for ConnectionIo {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_interim(cx))?;
+        let result = ready!(Pin::new(&mut this.inner).poll_write(cx, buf));
+        this.connection.touch();
+        Poll::Ready(result)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_interim(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
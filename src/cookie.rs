@@ -0,0 +1,154 @@
+use std::fmt;
+use std::time::Duration;
+
+///
+/// The `SameSite` attribute of a cookie. See `CookieBuilder::same_site`.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SameSite {
+    /// `SameSite=Strict`
+    Strict,
+    /// `SameSite=Lax`
+    Lax,
+    /// `SameSite=None`
+    None,
+}
+
+impl fmt::Display for SameSite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SameSite::Strict => f.write_str("Strict"),
+            SameSite::Lax => f.write_str("Lax"),
+            SameSite::None => f.write_str("None"),
+        }
+    }
+}
+
+///
+/// Builds the value of a `Set-Cookie` response header. See `Mock::with_cookie_builder`.
+///
+/// The attributes are rendered in the following order, whenever they are set:
+/// `Path`, `Domain`, `Max-Age`, `Secure`, `HttpOnly` and `SameSite`.
+///
+/// ## Example
+///
+/// ```
+/// use mockito::{CookieBuilder, SameSite};
+/// use std::time::Duration;
+///
+/// let cookie = CookieBuilder::new("session", "abc123")
+///     .path("/")
+///     .max_age(Duration::from_secs(3600))
+///     .http_only(true)
+///     .same_site(SameSite::Lax);
+///
+/// assert_eq!(
+///     "session=abc123; Path=/; Max-Age=3600; HttpOnly; SameSite=Lax",
+///     cookie.to_string()
+/// );
+/// ```
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CookieBuilder {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    max_age: Option<Duration>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+}
+
+impl CookieBuilder {
+    ///
+    /// Creates a cookie with the given name and value and no attributes.
+    ///
+    pub fn new(name: &str, value: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            value: value.to_string(),
+            path: None,
+            domain: None,
+            max_age: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+        }
+    }
+
+    ///
+    /// Sets the `Path` attribute.
+    ///
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = Some(path.to_string());
+        self
+    }
+
+    ///
+    /// Sets the `Domain` attribute.
+    ///
+    pub fn domain(mut self, domain: &str) -> Self {
+        self.domain = Some(domain.to_string());
+        self
+    }
+
+    ///
+    /// Sets the `Max-Age` attribute, rounded down to whole seconds.
+    ///
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    ///
+    /// Sets the `Secure` attribute.
+    ///
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    ///
+    /// Sets the `HttpOnly` attribute.
+    ///
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    ///
+    /// Sets the `SameSite` attribute.
+    ///
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+}
+
+impl fmt::Display for CookieBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+
+        if let Some(ref path) = self.path {
+            write!(f, "; Path={}", path)?;
+        }
+        if let Some(ref domain) = self.domain {
+            write!(f, "; Domain={}", domain)?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        if self.secure {
+            f.write_str("; Secure")?;
+        }
+        if self.http_only {
+            f.write_str("; HttpOnly")?;
+        }
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={}", same_site)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+use crate::Request;
+use http::header::ORIGIN;
+use std::time::Duration;
+
+///
+/// The CORS policy applied by `Server::enable_cors`. Provides a default implementation which
+/// allows any origin and header, along with the common methods.
+///
+/// ## Example
+///
+/// ```
+/// let cors = mockito::CorsConfig {
+///     allow_origins: vec!["https://example.com".to_string()],
+///     ..Default::default()
+/// };
+/// ```
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CorsConfig {
+    /// The origins allowed to make requests, or `*` for any origin (defaults to `*`)
+    pub allow_origins: Vec<String>,
+    /// The methods allowed in preflight responses (defaults to `GET`, `POST`, `PUT`, `PATCH`
+    /// and `DELETE`)
+    pub allow_methods: Vec<String>,
+    /// The request headers allowed in preflight responses, or `*` for any header
+    /// (defaults to `*`)
+    pub allow_headers: Vec<String>,
+    /// How long preflight responses may be cached by the browser (defaults to `None`)
+    pub max_age: Option<Duration>,
+}
+
+impl CorsConfig {
+    pub(crate) fn allows_any_origin(&self) -> bool {
+        self.allow_origins.iter().any(|origin| origin == "*")
+    }
+
+    /// The value of the `Access-Control-Allow-Origin` header: the origin of the request when
+    /// it's allowed, otherwise the first allowed origin, which browsers will reject
+    pub(crate) fn allow_origin(&self, request: &Request) -> String {
+        if self.allows_any_origin() {
+            return "*".to_string();
+        }
+
+        let origin = request
+            .header(ORIGIN)
+            .first()
+            .and_then(|value| value.to_str().ok())
+            .filter(|origin| self.allow_origins.iter().any(|allowed| allowed == origin));
+
+        match origin {
+            Some(origin) => origin.to_string(),
+            None => self.allow_origins.first().cloned().unwrap_or_default(),
+        }
+    }
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        let allow_origins = vec!["*".to_string()];
+        let allow_methods = ["GET", "POST", "PUT", "PATCH", "DELETE"]
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        let allow_headers = vec!["*".to_string()];
+        let max_age = None;
+
+        CorsConfig {
+            allow_origins,
+            allow_methods,
+            allow_headers,
+            max_age,
+        }
+    }
+}
@@ -1,5 +1,6 @@
+use crate::diff;
 use std::error::Error as ErrorTrait;
-use std::fmt::Display;
+use std::fmt::{self, Display};
 
 ///
 /// Contains information about an error occurence
@@ -64,6 +65,14 @@ pub enum ErrorKind {
     FileNotFound,
     /// Invalid header name
     InvalidHeaderName,
+    /// The server pool can't be configured
+    InvalidPoolConfiguration,
+    /// Invalid request path
+    InvalidPath,
+    /// Invalid regular expression
+    InvalidRegex,
+    /// The probability is not a number
+    InvalidProbability,
 }
 
 impl ErrorKind {
@@ -78,6 +87,84 @@ impl ErrorKind {
             ErrorKind::ResponseBodyFailure => "failed to write the response body",
             ErrorKind::FileNotFound => "file not found",
             ErrorKind::InvalidHeaderName => "invalid header name",
+            ErrorKind::InvalidPoolConfiguration => "the server pool can't be configured",
+            ErrorKind::InvalidPath => "invalid request path",
+            ErrorKind::InvalidRegex => "invalid regular expression",
+            ErrorKind::InvalidProbability => "invalid probability",
         }
     }
 }
+
+///
+/// Describes a failed mock assertion. See `Mock::try_assert`.
+///
+/// Its `Display` implementation renders the same message `Mock::assert` panics with.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AssertError {
+    /// The minimum amount of expected requests, if any
+    pub expected_min: Option<usize>,
+    /// The maximum amount of expected requests, if any
+    pub expected_max: Option<usize>,
+    /// The amount of requests the mock received, or `None` if the mock couldn't be found on
+    /// the server (e.g. because it was removed)
+    pub actual_hits: Option<usize>,
+    /// The formatted mock
+    pub mock: String,
+    /// The last request that didn't match any mock, if any
+    pub last_unmatched_request: Option<String>,
+    /// The last request that didn't match any mock as a `curl` command, if enabled with
+    /// `ServerOpts::assert_with_curl`
+    pub last_unmatched_request_curl: Option<String>,
+}
+
+impl Display for AssertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(hits) = self.actual_hits else {
+            return f.write_str("could not retrieve enough information about the remote mock");
+        };
+
+        match (self.expected_min, self.expected_max) {
+            (Some(min), Some(max)) if min == max => write!(
+                f,
+                "\n> Expected {} request(s) to:\n{}\n...but received {}\n\n",
+                min, self.mock, hits
+            )?,
+            (Some(min), Some(max)) => write!(
+                f,
+                "\n> Expected between {} and {} request(s) to:\n{}\n...but received {}\n\n",
+                min, max, self.mock, hits
+            )?,
+            (Some(min), None) => write!(
+                f,
+                "\n> Expected at least {} request(s) to:\n{}\n...but received {}\n\n",
+                min, self.mock, hits
+            )?,
+            (None, Some(max)) => write!(
+                f,
+                "\n> Expected at most {} request(s) to:\n{}\n...but received {}\n\n",
+                max, self.mock, hits
+            )?,
+            (None, None) => write!(
+                f,
+                "\n> Expected 1 request(s) to:\n{}\n...but received {}\n\n",
+                self.mock, hits
+            )?,
+        }
+
+        if let Some(ref last_request) = self.last_unmatched_request {
+            write!(f, "> The last unmatched request was:\n{}\n", last_request)?;
+
+            let difference = diff::compare(&self.mock, last_request);
+            write!(f, "> Difference:\n{}\n", difference)?;
+        }
+
+        if let Some(ref curl) = self.last_unmatched_request_curl {
+            write!(f, "> Replay the last unmatched request with:\n{}\n", curl)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ErrorTrait for AssertError {}
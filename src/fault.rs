@@ -0,0 +1,86 @@
+use crate::mock::IntoHeaderName;
+use crate::{Error, ErrorKind};
+use http::{HeaderMap, StatusCode};
+use rand::{thread_rng, Rng};
+
+///
+/// Describes the faults injected by `Server::set_fault`. Provides a default implementation
+/// which answers every request with `503 Service Unavailable`.
+///
+/// ## Example
+///
+/// ```
+/// let fault = mockito::FaultConfig {
+///     probability: 0.5,
+///     headers: vec![("retry-after".to_string(), "1".to_string())],
+///     ..Default::default()
+/// };
+/// ```
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct FaultConfig {
+    /// The status code of the faulty responses (defaults to 503)
+    pub status: usize,
+    /// The share of requests that receive a faulty response, between 0.0 and 1.0
+    /// (defaults to 1.0). Values outside of this range are clamped, while `NaN` panics.
+    pub probability: f64,
+    /// The headers of the faulty responses, e.g. `Retry-After` (defaults to none)
+    pub headers: Vec<(String, String)>,
+}
+
+impl Default for FaultConfig {
+    fn default() -> Self {
+        let status = 503;
+        let probability = 1.0;
+        let headers = vec![];
+
+        FaultConfig {
+            status,
+            probability,
+            headers,
+        }
+    }
+}
+
+///
+/// A validated `FaultConfig`, as stored on the server.
+///
+#[derive(Clone, Debug)]
+pub(crate) struct Fault {
+    pub(crate) status: StatusCode,
+    pub(crate) headers: HeaderMap<String>,
+    probability: f64,
+}
+
+impl Fault {
+    #[track_caller]
+    pub(crate) fn new(config: FaultConfig) -> Self {
+        assert!(
+            !config.probability.is_nan(),
+            "{}",
+            Error::new_with_context(ErrorKind::InvalidProbability, config.probability)
+        );
+
+        let status = u16::try_from(config.status)
+            .ok()
+            .and_then(|status| StatusCode::from_u16(status).ok())
+            .ok_or_else(|| Error::new_with_context(ErrorKind::InvalidStatusCode, config.status))
+            .unwrap();
+
+        let mut headers = HeaderMap::default();
+        for (field, value) in config.headers {
+            headers.append(field.into_header_name(), value);
+        }
+
+        Self {
+            status,
+            headers,
+            probability: config.probability.clamp(0.0, 1.0),
+        }
+    }
+
+    /// Decides whether the current request should receive a faulty response
+    pub(crate) fn triggers(&self) -> bool {
+        thread_rng().gen_bool(self.probability)
+    }
+}
@@ -23,7 +23,7 @@
 //!
 //! Add `mockito` to your `Cargo.toml` and start mocking:
 //!
-//! ```
+//! ```no_run
 //! #[cfg(test)]
 //! mod tests {
 //!   #[test]
@@ -58,7 +58,7 @@
 //!
 //! Use **matchers** to handle requests to the same endpoint in a different way:
 //!
-//! ```
+//! ```no_run
 //! #[cfg(test)]
 //! mod tests {
 //!   #[test]
@@ -84,7 +84,7 @@
 //!
 //! Start **multiple servers** to simulate requests to different hosts:
 //!
-//! ```
+//! ```no_run
 //! #[cfg(test)]
 //! mod tests {
 //!   #[test]
@@ -125,6 +125,7 @@
 //! Start a **stand-alone server** on a dedicated port:
 //!
 //! ```
+//! # extern crate mockito;
 //! fn main() {
 //!     let opts = mockito::ServerOpts {
 //!         host: "0.0.0.0",
@@ -171,6 +172,7 @@
 //! - [`Server::new_with_opts_async`]
 //! - [`Mock::create_async`]
 //! - [`Mock::assert_async`]
+//! - [`Mock::try_assert_async`]
 //! - [`Mock::matched_async`]
 //! - [`Mock::remove_async`]
 //!
@@ -184,7 +186,11 @@
 //! # Configuring the server
 //!
 //! When calling [`Server::new()`], a mock server with default options is returned from the server
-//! pool. This should suffice for most use cases.
+//! pool. This should suffice for most use cases. The size of the pool and the host of the
+//! pooled servers can be adjusted with [`set_pool_size`] and [`set_pool_host`], before
+//! requesting the first server.
+//!
+//! To bypass the server pool with the default options, use [`Server::new_unpooled`].
 //!
 //! If you'd like to bypass the server pool or configure the server in a different
 //! way, you can use [`Server::new_with_opts`]. The following **options** are available:
@@ -192,12 +198,38 @@
 //! - `host`: allows setting the host (defaults to `127.0.0.1`)
 //! - `port`: allows setting the port (defaults to a randomly assigned free port)
 //! - `assert_on_drop`: automatically call [`Mock::assert()`] before dropping a mock (defaults to `false`)
+//! - `keep_alive_timeout`: close connections which have been idle for the given duration
+//!   (defaults to `None`)
+//! - `decode_request_body`: decompress `gzip` or `deflate` encoded request bodies before matching
+//!   them (defaults to `false`, see [Compression](#compression))
+//! - `request_timeout`: give up on clients which don't send a complete request within the given
+//!   duration (defaults to `None`)
+//! - `max_matcher_depth`: the maximum nesting depth of `AnyOf` and `AllOf` matchers, deeper
+//!   matchers don't match (defaults to `64`)
+//! - `assert_with_curl`: include the last unmatched request as a `curl` command in the messages
+//!   of failed assertions (defaults to `false`)
+//! - `max_headers` and `max_header_bytes`: respond with `431 Request Header Fields Too Large` to
+//!   requests with too many or too large headers (defaults to `None`)
+//! - `warn_on_duplicate_mocks`: log a warning when a mock is created with the same matchers as an
+//!   existing mock (defaults to `false`)
 //!
 //! ```
 //! let opts = mockito::ServerOpts { assert_on_drop: true, ..Default::default() };
 //! let server = mockito::Server::new_with_opts(opts);
 //! ```
 //!
+//! # Compression
+//!
+//! Each compression format sits behind a feature flag: `gzip` and `deflate` are enabled by
+//! default, while `brotli` is optional. Disabling a feature drops the related
+//! [`ContentEncoding`] variant, and `decode_request_body` or [`Mock::match_encoded_body`] no
+//! longer decompress bodies in that format.
+//!
+//! ```toml
+//! [dev-dependencies]
+//! mockito = { version = "1", default-features = false, features = ["color", "gzip"] }
+//! ```
+//!
 //! # Matchers
 //!
 //! Mockito can match your request by method, path, query, headers or body.
@@ -371,6 +403,23 @@
 //! // Requests containing the authorization header will return `501 Mock Not Found`.
 //! ```
 //!
+//! Note that [`Matcher::Missing`] doesn't match a header that is present but empty (e.g. `X-Flag:`).
+//! Use [`Matcher::Empty`] to match exactly that case.
+//!
+//! ## Example
+//!
+//! ```
+//! let mut s = mockito::Server::new();
+//!
+//! s.mock("GET", "/hello")
+//!   .match_header("x-flag", mockito::Matcher::Empty)
+//!   .with_body("empty flag")
+//!   .create();
+//!
+//! // Requests containing an empty x-flag header will be matched.
+//! // Requests without the x-flag header, or with a non-empty value, will return `501 Mock Not Found`.
+//! ```
+//!
 //! # Matching by body
 //!
 //! You can match a request by its body by using the [`Mock::match_body`] method.
@@ -514,7 +563,7 @@
 //! {
 //!     // Place a request
 //!     let mut stream = TcpStream::connect(s.host_with_port()).unwrap();
-//!     stream.write_all("GET /hello HTTP/1.1\r\n\r\n".as_bytes()).unwrap();
+//!     stream.write_all("GET /hello HTTP/1.1\r\nConnection: close\r\n\r\n".as_bytes()).unwrap();
 //!     let mut response = String::new();
 //!     stream.read_to_string(&mut response).unwrap();
 //!     stream.flush().unwrap();
@@ -539,7 +588,7 @@
 //! {
 //!     // Place a request to GET /hello
 //!     let mut stream = TcpStream::connect(s.host_with_port()).unwrap();
-//!     stream.write_all("GET /hello HTTP/1.1\r\n\r\n".as_bytes()).unwrap();
+//!     stream.write_all("GET /hello HTTP/1.1\r\nConnection: close\r\n\r\n".as_bytes()).unwrap();
 //!     let mut response = String::new();
 //!     stream.read_to_string(&mut response).unwrap();
 //!     stream.flush().unwrap();
@@ -550,7 +599,7 @@
 //! {
 //!     // Place another request to GET /hello
 //!     let mut stream = TcpStream::connect(s.host_with_port()).unwrap();
-//!     stream.write_all("GET /hello HTTP/1.1\r\n\r\n".as_bytes()).unwrap();
+//!     stream.write_all("GET /hello HTTP/1.1\r\nConnection: close\r\n\r\n".as_bytes()).unwrap();
 //!     let mut response = String::new();
 //!     stream.read_to_string(&mut response).unwrap();
 //!     stream.flush().unwrap();
@@ -574,7 +623,7 @@
 //! for _ in 0..3 {
 //!     // Place a request
 //!     let mut stream = TcpStream::connect(s.host_with_port()).unwrap();
-//!     stream.write_all("GET /hello HTTP/1.1\r\n\r\n".as_bytes()).unwrap();
+//!     stream.write_all("GET /hello HTTP/1.1\r\nConnection: close\r\n\r\n".as_bytes()).unwrap();
 //!     let mut response = String::new();
 //!     stream.read_to_string(&mut response).unwrap();
 //!     stream.flush().unwrap();
@@ -598,7 +647,7 @@
 //! for _ in 0..3 {
 //!     // Place a request
 //!     let mut stream = TcpStream::connect(s.host_with_port()).unwrap();
-//!     stream.write_all("GET /hello HTTP/1.1\r\n\r\n".as_bytes()).unwrap();
+//!     stream.write_all("GET /hello HTTP/1.1\r\nConnection: close\r\n\r\n".as_bytes()).unwrap();
 //!     let mut response = String::new();
 //!     stream.read_to_string(&mut response).unwrap();
 //!     stream.flush().unwrap();
@@ -652,7 +701,7 @@
 //!
 //! {
 //!     let mut stream = TcpStream::connect(s.host_with_port()).unwrap();
-//!     stream.write_all("GET / HTTP/1.1\r\n\r\n".as_bytes()).unwrap();
+//!     stream.write_all("GET / HTTP/1.1\r\nConnection: close\r\n\r\n".as_bytes()).unwrap();
 //!     let mut response = String::new();
 //!     stream.read_to_string(&mut response).unwrap();
 //!     stream.flush().unwrap();
@@ -662,7 +711,7 @@
 //!
 //! {
 //!     let mut stream = TcpStream::connect(s.host_with_port()).unwrap();
-//!     stream.write_all("GET / HTTP/1.1\r\n\r\n".as_bytes()).unwrap();
+//!     stream.write_all("GET / HTTP/1.1\r\nConnection: close\r\n\r\n".as_bytes()).unwrap();
 //!     let mut response = String::new();
 //!     stream.read_to_string(&mut response).unwrap();
 //!     stream.flush().unwrap();
@@ -714,7 +763,7 @@
 //! If you'd like to activate the debug output, introduce the [env_logger](https://crates.rs/crates/env_logger) crate
 //! to your project and initialize it before each test that needs debugging:
 //!
-//! ```
+//! ```no_run
 //! #[test]
 //! fn example_test() {
 //!     let _ = env_logger::try_init();
@@ -728,19 +777,31 @@
 //! RUST_LOG=mockito=debug cargo test
 //! ```
 //!
-pub use error::{Error, ErrorKind};
+pub use cookie::{CookieBuilder, SameSite};
+pub use cors::CorsConfig;
+pub use error::{AssertError, Error, ErrorKind};
+pub use fault::FaultConfig;
 #[allow(deprecated)]
-pub use matcher::Matcher;
-pub use mock::{IntoHeaderName, Mock};
+pub use matcher::{Matcher, MatcherFn};
+pub use mock::{IntoHeaderName, Mock, MockSpec};
 pub use request::Request;
+pub use response::{ContentEncoding, MockResponse, TransferEncoding};
 pub use server::{Server, ServerOpts};
-pub use server_pool::ServerGuard;
+pub use server_pool::{
+    reset_all_pooled_servers, reset_pool, set_pool_host, set_pool_size, ServerGuard,
+};
+pub use websocket::WebSocketMessage;
 
+mod connection;
+mod cookie;
+mod cors;
 mod diff;
 mod error;
+mod fault;
 mod matcher;
 mod mock;
 mod request;
 mod response;
 mod server;
 mod server_pool;
+mod websocket;
@@ -1,16 +1,21 @@
 use crate::request::Request;
+use crate::{Error, ErrorKind};
 use assert_json_diff::{assert_json_matches_no_panic, CompareMode};
 use http::header::HeaderValue;
-use regex::Regex;
-use std::collections::HashMap;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader as XmlReader;
+use regex::{Regex, RegexBuilder};
+use std::collections::{BTreeMap, HashMap};
 use std::convert::From;
 use std::fmt;
 use std::fs::File;
 use std::io;
 use std::io::Read;
+use std::ops::RangeInclusive;
 use std::path::Path;
 use std::string::ToString;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock, RwLock};
 
 ///
 /// Allows matching the request path, headers or body in multiple ways: by the exact value, by any value (as
@@ -24,31 +29,110 @@ pub enum Matcher {
     /// Matches the exact path or header value. There's also an implementation of `From<&str>`
     /// to keep things simple and backwards compatible.
     Exact(String),
+    /// Matches the exact path, header value or body, ignoring the ASCII letter case.
+    ExactIgnoreCase(String),
+    /// Matches a path, header value or body starting with the given value.
+    Prefix(String),
+    /// Matches a path, header value or body ending with the given value.
+    Suffix(String),
+    /// Matches a path, header value or body containing the given value. Unlike `Regex`, the
+    /// value is matched literally.
+    Contains(String),
+    /// Matches a comma-separated header value (e.g. `Accept: a, b, c`) listing the given
+    /// value as one of its items. Items are trimmed and compared exactly.
+    InList(String),
     /// Matches the body content as a binary file
     Binary(BinaryBody),
+    /// Matches a body whose size in bytes is within the given range, regardless of its content
+    BodySize(RangeInclusive<usize>),
+    /// Matches the body by the MIME type detected from its leading bytes (magic numbers),
+    /// e.g. `image/png`, `application/pdf` or `application/gzip`
+    SniffedType(String),
     /// Matches a path or header value by a regular expression.
+    /// Invalid expressions cause a panic as soon as the matcher is passed to a mock.
     Regex(String),
+    /// Matches a path, header value or body by a regular expression built with the given flags.
+    /// Equivalent to prefixing the pattern with the inline flags `i`, `m` and `s`.
+    RegexWith {
+        /// The regular expression
+        pattern: String,
+        /// Letters match both upper and lower case
+        case_insensitive: bool,
+        /// `^` and `$` match at the beginning and end of lines
+        multi_line: bool,
+        /// `.` matches `\n`
+        dot_matches_new_line: bool,
+    },
     /// Matches a specified JSON body from a `serde_json::Value`
     Json(serde_json::Value),
     /// Matches a specified JSON body from a `String`
     JsonString(String),
+    /// Matches a specified JSON body from a `serde_json::Value`, ignoring the order of array elements
+    JsonUnordered(serde_json::Value),
+    /// Matches a specified JSON body from a `serde_json::Value`, comparing numbers by their value
+    /// (e.g. `1`, `1.0` and `1e0` are considered equal)
+    JsonRelaxed(serde_json::Value),
+    /// Matches a specified JSON body from a `serde_json::Value`, ignoring the given fields in
+    /// both the expected and the actual body. Fields are either top-level keys (e.g. `timestamp`)
+    /// or JSON pointers (e.g. `/meta/request_id`).
+    JsonIgnoring(serde_json::Value, Vec<String>),
+    /// Matches a specified TOML body from a `toml::Value`
+    Toml(toml::Value),
+    /// Matches a specified XML body, ignoring insignificant whitespace and the order of attributes
+    Xml(String),
+    /// Matches an XML body containing the specified XML as a subtree. Extra attributes and child
+    /// elements are ignored.
+    PartialXml(String),
     /// Matches a partial JSON body from a `serde_json::Value`
     PartialJson(serde_json::Value),
     /// Matches a specified partial JSON body from a `String`
     PartialJsonString(String),
+    /// Matches a JSON body when the array at the given JSON pointer (e.g. `/users`) contains
+    /// all the given items, regardless of their position
+    JsonContainsArrayItems(String, Vec<serde_json::Value>),
+    /// Matches an integer path, header value or query parameter greater than the given number.
+    GreaterThan(i64),
+    /// Matches an integer path, header value or query parameter less than the given number.
+    LessThan(i64),
+    /// Matches a numeric path, header value or query parameter within the given range
+    /// (inclusive). Non-numeric values don't match.
+    NumericRange(f64, f64),
+    /// Matches a path against a template with `{name}` placeholders, segment by segment,
+    /// e.g. `/users/{id}/posts/{post_id}`. Placeholders match any non-empty segment and can be
+    /// retrieved via `Request::path_param`. The query is ignored.
+    PathTemplate(String),
     /// Matches a URL-encoded key/value pair, where both key and value should be specified
     /// in plain (unencoded) format
     UrlEncoded(String, String),
-    /// At least one matcher must match
+    /// Matches a URL-encoded query or body containing exactly the given key/value pairs and no
+    /// others, in any order. Repeated pairs must appear as many times as given. Keys and values
+    /// should be specified in plain (unencoded) format.
+    UrlEncodedAll(Vec<(String, String)>),
+    /// Matches a URL-encoded query containing exactly the given key/value pairs in the given
+    /// order, e.g. for signed requests which are sensitive to the order of the parameters.
+    /// Keys and values should be specified in plain (unencoded) format.
+    QueryExactOrdered(Vec<(String, String)>),
+    /// Matches a URL-encoded query containing exactly the given number of parameters. Combine it
+    /// with `AllOf` and `UrlEncoded` to reject unexpected parameters.
+    QueryParamCount(usize),
+    /// Matches a path, header value or body for which the given predicate returns `true`.
+    /// Use it for matching logic that isn't covered by the other matchers.
+    Fn(MatcherFn),
+    /// At least one matcher must match. Matchers are evaluated in order and evaluation stops
+    /// at the first one that matches.
     AnyOf(Vec<Matcher>),
-    /// All matchers must match
+    /// All matchers must match. Matchers are evaluated in order and evaluation stops
+    /// at the first one that doesn't match.
     AllOf(Vec<Matcher>),
     /// Matches any path or any header value.
     Any,
     /// Checks that a header is not present in the request.
     Missing,
+    /// Checks that a header is present with an empty value, or that the body is empty.
+    Empty,
 }
 
+#[allow(clippy::needless_lifetimes)]
 impl<'a> From<&'a str> for Matcher {
     fn from(value: &str) -> Self {
         Matcher::Exact(value.to_string())
@@ -92,82 +176,279 @@ impl fmt::Display for Matcher {
 
         let result = match self {
             Matcher::Exact(ref value) => value.to_string(),
+            Matcher::ExactIgnoreCase(ref value) => format!("{} (exact, case-insensitive)", value),
+            Matcher::Prefix(ref value) => format!("{} (prefix)", value),
+            Matcher::Suffix(ref value) => format!("{} (suffix)", value),
+            Matcher::Contains(ref value) => format!("{} (contains)", value),
+            Matcher::InList(ref value) => format!("{} (in list)", value),
             Matcher::Binary(ref file) => format!("{} (binary)", file),
+            Matcher::BodySize(ref range) => {
+                format!("{}..={} bytes (body size)", range.start(), range.end())
+            }
+            Matcher::SniffedType(ref value) => format!("{} (sniffed type)", value),
             Matcher::Regex(ref value) => format!("{} (regex)", value),
+            Matcher::RegexWith {
+                ref pattern,
+                case_insensitive,
+                multi_line,
+                dot_matches_new_line,
+            } => {
+                let mut flags = String::from("regex");
+                if *case_insensitive {
+                    flags.push_str(", case-insensitive");
+                }
+                if *multi_line {
+                    flags.push_str(", multi-line");
+                }
+                if *dot_matches_new_line {
+                    flags.push_str(", dot matches new line");
+                }
+                format!("{} ({})", pattern, flags)
+            }
             Matcher::Json(ref json_obj) => format!("{} (json)", json_obj),
             Matcher::JsonString(ref value) => format!("{} (json)", value),
+            Matcher::JsonUnordered(ref json_obj) => format!("{} (unordered json)", json_obj),
+            Matcher::JsonRelaxed(ref json_obj) => format!("{} (relaxed json)", json_obj),
+            Matcher::JsonIgnoring(ref json_obj, ref fields) => {
+                format!("{} ignoring {:?} (json)", json_obj, fields)
+            }
+            Matcher::Toml(ref toml_obj) => format!("{} (toml)", toml_obj),
+            Matcher::Xml(ref value) => format!("{} (xml)", value),
+            Matcher::PartialXml(ref value) => format!("{} (partial xml)", value),
             Matcher::PartialJson(ref json_obj) => format!("{} (partial json)", json_obj),
             Matcher::PartialJsonString(ref value) => format!("{} (partial json)", value),
+            Matcher::JsonContainsArrayItems(ref pointer, ref items) => format!(
+                "{} contains {} (json array items)",
+                pointer,
+                serde_json::Value::from(items.clone())
+            ),
+            Matcher::PathTemplate(ref template) => format!("{} (path template)", template),
             Matcher::UrlEncoded(ref field, ref value) => {
                 format!("{}={} (urlencoded)", field, value)
             }
+            Matcher::UrlEncodedAll(ref pairs) => {
+                let pairs: Vec<String> = pairs
+                    .iter()
+                    .map(|(field, value)| format!("{}={}", field, value))
+                    .collect();
+                format!("{} (urlencoded, exhaustive)", pairs.join("&"))
+            }
+            Matcher::QueryExactOrdered(ref pairs) => {
+                let pairs: Vec<String> = pairs
+                    .iter()
+                    .map(|(field, value)| format!("{}={}", field, value))
+                    .collect();
+                format!("{} (ordered query)", pairs.join("&"))
+            }
+            Matcher::GreaterThan(ref value) => format!("> {}", value),
+            Matcher::LessThan(ref value) => format!("< {}", value),
+            Matcher::NumericRange(ref min, ref max) => {
+                format!("[{}, {}] (numeric range)", min, max)
+            }
+            Matcher::QueryParamCount(ref count) => format!("{} (query param count)", count),
+            Matcher::Fn(_) => "(fn)".to_string(),
             Matcher::Any => "(any)".to_string(),
             Matcher::AnyOf(x) => format!("({}) (any of)", join_matches(x)),
             Matcher::AllOf(x) => format!("({}) (all of)", join_matches(x)),
             Matcher::Missing => "(missing)".to_string(),
+            Matcher::Empty => "(empty)".to_string(),
         };
         write!(f, "{}", result)
     }
 }
 
 impl Matcher {
-    pub(crate) fn matches_values(&self, header_values: &[&HeaderValue]) -> bool {
+    ///
+    /// Compiles the regular expressions used by the matcher ahead of time, so that an invalid
+    /// pattern panics where the mock is defined instead of when a request arrives.
+    ///
+    #[track_caller]
+    pub(crate) fn validated(self) -> Self {
+        self.compile_regexes()
+            .map_err(|err| Error::new_with_context(ErrorKind::InvalidRegex, err))
+            .unwrap();
+
+        self
+    }
+
+    fn compile_regexes(&self) -> Result<(), regex::Error> {
         match self {
-            Matcher::Missing => header_values.is_empty(),
+            Matcher::Regex(ref regex) => compiled_regex(regex).map(|_| ()),
+            Matcher::RegexWith {
+                ref pattern,
+                case_insensitive,
+                multi_line,
+                dot_matches_new_line,
+            } => compiled_regex_with(
+                pattern,
+                RegexFlags {
+                    case_insensitive: *case_insensitive,
+                    multi_line: *multi_line,
+                    dot_matches_new_line: *dot_matches_new_line,
+                },
+            )
+            .map(|_| ()),
+            Matcher::AnyOf(ref matchers) | Matcher::AllOf(ref matchers) => {
+                matchers.iter().try_for_each(Self::compile_regexes)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn matches_values(&self, header_values: &[&HeaderValue], max_depth: usize) -> bool {
+        let values: Option<Vec<&str>> = header_values.iter().map(|val| val.to_str().ok()).collect();
+
+        values.is_some_and(|values| self.matches_str_values(&values, max_depth))
+    }
+
+    pub(crate) fn matches_str_values(&self, values: &[&str], max_depth: usize) -> bool {
+        self.matches_str_values_at(values, 0, max_depth)
+    }
+
+    fn matches_str_values_at(&self, values: &[&str], depth: usize, max_depth: usize) -> bool {
+        match self {
+            // Missing only matches when the header is absent altogether,
+            // while Empty requires it to be present with an empty value
+            // and is therefore handled by the last block.
+            Matcher::Missing => values.is_empty(),
             // AnyOf([…Missing…]) is handled here, but
             // AnyOf([Something]) is handled in the last block.
             // That's because Missing matches against all values at once,
             // but other matchers match against individual values.
-            Matcher::AnyOf(ref matchers) if header_values.is_empty() => {
-                matchers.iter().any(|m| m.matches_values(header_values))
+            Matcher::AnyOf(ref matchers) if values.is_empty() => {
+                within_max_depth(depth, max_depth)
+                    && matchers
+                        .iter()
+                        .any(|m| m.matches_str_values_at(values, depth + 1, max_depth))
             }
-            Matcher::AllOf(ref matchers) if header_values.is_empty() => {
-                matchers.iter().all(|m| m.matches_values(header_values))
+            Matcher::AllOf(ref matchers) if values.is_empty() => {
+                within_max_depth(depth, max_depth)
+                    && matchers
+                        .iter()
+                        .all(|m| m.matches_str_values_at(values, depth + 1, max_depth))
             }
             _ => {
-                !header_values.is_empty()
-                    && header_values.iter().all(|val| {
-                        val.to_str()
-                            .map(|val| self.matches_value(val))
-                            .unwrap_or(false)
-                    })
+                !values.is_empty()
+                    && values
+                        .iter()
+                        .all(|val| self.matches_value_at(val, depth, max_depth))
             }
         }
     }
 
+    pub(crate) fn matches_body(&self, body: &[u8], max_depth: usize) -> bool {
+        let safe_body = &String::from_utf8_lossy(body);
+
+        self.matches_value(safe_body, max_depth) || self.matches_binary_value(body)
+    }
+
     pub(crate) fn matches_binary_value(&self, binary: &[u8]) -> bool {
         match self {
             Matcher::Binary(ref file) => binary == &*file.content,
+            Matcher::BodySize(ref range) => range.contains(&binary.len()),
+            Matcher::SniffedType(ref value) => {
+                sniff_type(binary).is_some_and(|sniffed| sniffed.eq_ignore_ascii_case(value))
+            }
             _ => false,
         }
     }
 
+    pub(crate) fn matches_value(&self, other: &str, max_depth: usize) -> bool {
+        self.matches_value_at(other, 0, max_depth)
+    }
+
     #[allow(deprecated)]
-    pub(crate) fn matches_value(&self, other: &str) -> bool {
+    fn matches_value_at(&self, other: &str, depth: usize, max_depth: usize) -> bool {
         let compare_json_config = assert_json_diff::Config::new(CompareMode::Inclusive);
         match self {
             Matcher::Exact(ref value) => value == other,
+            Matcher::ExactIgnoreCase(ref value) => value.eq_ignore_ascii_case(other),
+            Matcher::Prefix(ref value) => other.starts_with(value.as_str()),
+            Matcher::Suffix(ref value) => other.ends_with(value.as_str()),
+            Matcher::Contains(ref value) => other.contains(value.as_str()),
+            Matcher::InList(ref value) => other.split(',').any(|item| item.trim() == value),
             Matcher::Binary(_) => false,
-            Matcher::Regex(ref regex) => Regex::new(regex).unwrap().is_match(other),
-            Matcher::Json(ref json_obj) => {
-                let other: serde_json::Value = serde_json::from_str(other).unwrap();
-                *json_obj == other
+            Matcher::BodySize(_) => false,
+            Matcher::SniffedType(_) => false,
+            Matcher::Regex(ref regex) => {
+                compiled_regex(regex).is_ok_and(|regex| regex.is_match(other))
             }
+            Matcher::RegexWith {
+                ref pattern,
+                case_insensitive,
+                multi_line,
+                dot_matches_new_line,
+            } => compiled_regex_with(
+                pattern,
+                RegexFlags {
+                    case_insensitive: *case_insensitive,
+                    multi_line: *multi_line,
+                    dot_matches_new_line: *dot_matches_new_line,
+                },
+            )
+            .is_ok_and(|regex| regex.is_match(other)),
+            Matcher::Json(ref json_obj) => serde_json::from_str::<serde_json::Value>(other)
+                .map(|other| *json_obj == other)
+                .unwrap_or(false),
             Matcher::JsonString(ref value) => {
                 let value: serde_json::Value = serde_json::from_str(value).unwrap();
-                let other: serde_json::Value = serde_json::from_str(other).unwrap();
-                value == other
+                serde_json::from_str::<serde_json::Value>(other)
+                    .map(|other| value == other)
+                    .unwrap_or(false)
+            }
+            Matcher::JsonUnordered(ref json_obj) => {
+                serde_json::from_str::<serde_json::Value>(other)
+                    .map(|other| json_eq_unordered(json_obj, &other))
+                    .unwrap_or(false)
             }
+            Matcher::JsonRelaxed(ref json_obj) => serde_json::from_str::<serde_json::Value>(other)
+                .map(|other| json_eq_relaxed(json_obj, &other))
+                .unwrap_or(false),
+            Matcher::JsonIgnoring(ref json_obj, ref fields) => {
+                let Ok(mut actual) = serde_json::from_str::<serde_json::Value>(other) else {
+                    return false;
+                };
+                let mut expected = json_obj.clone();
+                for field in fields {
+                    remove_json_field(&mut expected, field);
+                    remove_json_field(&mut actual, field);
+                }
+                expected == actual
+            }
+            Matcher::Toml(ref toml_obj) => toml::from_str::<toml::Value>(other)
+                .map(|other| *toml_obj == other)
+                .unwrap_or(false),
+            Matcher::Xml(ref value) => match (parse_xml(value), parse_xml(other)) {
+                (Some(expected), Some(actual)) => expected == actual,
+                _ => false,
+            },
+            Matcher::PartialXml(ref value) => match (parse_xml(value), parse_xml(other)) {
+                (Some(expected), Some(actual)) => xml_contains_subtree(&expected, &actual),
+                _ => false,
+            },
             Matcher::PartialJson(ref json_obj) => {
-                let actual: serde_json::Value = serde_json::from_str(other).unwrap();
+                let Ok(actual) = serde_json::from_str::<serde_json::Value>(other) else {
+                    return false;
+                };
                 let expected = json_obj.clone();
                 assert_json_matches_no_panic(&actual, &expected, compare_json_config).is_ok()
             }
             Matcher::PartialJsonString(ref value) => {
                 let expected: serde_json::Value = serde_json::from_str(value).unwrap();
-                let actual: serde_json::Value = serde_json::from_str(other).unwrap();
+                let Ok(actual) = serde_json::from_str::<serde_json::Value>(other) else {
+                    return false;
+                };
                 assert_json_matches_no_panic(&actual, &expected, compare_json_config).is_ok()
             }
+            Matcher::JsonContainsArrayItems(ref pointer, ref items) => {
+                let actual = serde_json::from_str::<serde_json::Value>(other).ok();
+                match actual.as_ref().and_then(|actual| actual.pointer(pointer)) {
+                    Some(serde_json::Value::Array(array)) => {
+                        items.iter().all(|item| array.contains(item))
+                    }
+                    _ => false,
+                }
+            }
             Matcher::UrlEncoded(ref expected_field, ref expected_value) => {
                 serde_urlencoded::from_str::<HashMap<String, String>>(other)
                     .map(|params: HashMap<_, _>| {
@@ -177,14 +458,420 @@ impl Matcher {
                     })
                     .unwrap_or(false)
             }
+            Matcher::UrlEncodedAll(ref pairs) => {
+                serde_urlencoded::from_str::<Vec<(String, String)>>(other)
+                    .map(|mut params| {
+                        let mut expected = pairs.clone();
+                        expected.sort();
+                        params.sort();
+                        params == expected
+                    })
+                    .unwrap_or(false)
+            }
+            Matcher::QueryExactOrdered(ref pairs) => {
+                serde_urlencoded::from_str::<Vec<(String, String)>>(other)
+                    .map(|params| params == *pairs)
+                    .unwrap_or(false)
+            }
+            Matcher::GreaterThan(ref value) => other
+                .trim()
+                .parse::<i64>()
+                .is_ok_and(|other| other > *value),
+            Matcher::LessThan(ref value) => other
+                .trim()
+                .parse::<i64>()
+                .is_ok_and(|other| other < *value),
+            Matcher::NumericRange(ref min, ref max) => other
+                .trim()
+                .parse::<f64>()
+                .is_ok_and(|other| *min <= other && other <= *max),
+            Matcher::PathTemplate(ref template) => path_params(template, other).is_some(),
+            Matcher::QueryParamCount(ref count) => {
+                serde_urlencoded::from_str::<Vec<(String, String)>>(other)
+                    .map(|params| params.len() == *count)
+                    .unwrap_or(false)
+            }
+            Matcher::Fn(ref predicate) => (predicate.0)(other),
             Matcher::Any => true,
-            Matcher::AnyOf(ref matchers) => matchers.iter().any(|m| m.matches_value(other)),
-            Matcher::AllOf(ref matchers) => matchers.iter().all(|m| m.matches_value(other)),
+            Matcher::AnyOf(ref matchers) => {
+                within_max_depth(depth, max_depth)
+                    && matchers
+                        .iter()
+                        .any(|m| m.matches_value_at(other, depth + 1, max_depth))
+            }
+            Matcher::AllOf(ref matchers) => {
+                within_max_depth(depth, max_depth)
+                    && matchers
+                        .iter()
+                        .all(|m| m.matches_value_at(other, depth + 1, max_depth))
+            }
             Matcher::Missing => other.is_empty(),
+            Matcher::Empty => other.is_empty(),
         }
     }
 }
 
+/// The default maximum nesting depth of `AnyOf` and `AllOf` matchers, see
+/// `ServerOpts::max_matcher_depth`
+pub(crate) const DEFAULT_MAX_DEPTH: usize = 64;
+
+/// Checks whether nested matchers can still be evaluated at the given depth, so that
+/// pathologically deep matchers don't overflow the stack of the server thread
+fn within_max_depth(depth: usize, max_depth: usize) -> bool {
+    if depth < max_depth {
+        return true;
+    }
+
+    log::warn!(
+        "Matchers nested deeper than {} levels are treated as not matching",
+        max_depth
+    );
+    false
+}
+
+/// Matches the path (ignoring the query) against the template, returning the values of
+/// its `{name}` placeholders
+fn path_params(template: &str, path_and_query: &str) -> Option<HashMap<String, String>> {
+    let path = path_and_query.split('?').next().unwrap_or("");
+    let mut template_segments = template.split('/');
+    let mut path_segments = path.split('/');
+    let mut params = HashMap::new();
+
+    loop {
+        match (template_segments.next(), path_segments.next()) {
+            (None, None) => return Some(params),
+            (Some(expected), Some(segment)) => {
+                match expected.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+                    Some(name) if !segment.is_empty() => {
+                        params.insert(name.to_string(), segment.to_string());
+                    }
+                    Some(_) => return None,
+                    None if expected == segment => {}
+                    None => return None,
+                }
+            }
+            _ => return None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+struct RegexFlags {
+    case_insensitive: bool,
+    multi_line: bool,
+    dot_matches_new_line: bool,
+}
+
+fn compiled_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    compiled_regex_with(pattern, RegexFlags::default())
+}
+
+/// The maximum number of compiled regular expressions kept in the cache
+const REGEX_CACHE_CAPACITY: usize = 256;
+
+/// A compiled regular expression along with the moment it was last used
+struct CachedRegex {
+    regex: Regex,
+    last_used: AtomicU64,
+}
+
+type RegexCache = HashMap<RegexFlags, HashMap<String, CachedRegex>>;
+
+/// Compiles the given pattern once and reuses it for subsequent matches. Once the cache
+/// reaches `REGEX_CACHE_CAPACITY` entries, the least recently used one is evicted.
+fn compiled_regex_with(pattern: &str, flags: RegexFlags) -> Result<Regex, regex::Error> {
+    static CACHE: OnceLock<RwLock<RegexCache>> = OnceLock::new();
+    static CLOCK: AtomicU64 = AtomicU64::new(0);
+
+    let cache = CACHE.get_or_init(Default::default);
+    let cached = cache
+        .read()
+        .unwrap()
+        .get(&flags)
+        .and_then(|regexes| regexes.get(pattern))
+        .map(|cached| {
+            let now = CLOCK.fetch_add(1, Ordering::Relaxed);
+            cached.last_used.store(now, Ordering::Relaxed);
+            cached.regex.clone()
+        });
+    if let Some(regex) = cached {
+        return Ok(regex);
+    }
+
+    let regex = RegexBuilder::new(pattern)
+        .case_insensitive(flags.case_insensitive)
+        .multi_line(flags.multi_line)
+        .dot_matches_new_line(flags.dot_matches_new_line)
+        .build()?;
+
+    let mut cache = cache.write().unwrap();
+    if cache.values().map(HashMap::len).sum::<usize>() >= REGEX_CACHE_CAPACITY {
+        let least_recently_used = cache
+            .iter()
+            .flat_map(|(flags, regexes)| regexes.iter().map(move |entry| (flags, entry)))
+            .min_by_key(|(_, (_, cached))| cached.last_used.load(Ordering::Relaxed))
+            .map(|(flags, (pattern, _))| (*flags, pattern.clone()));
+        if let Some((flags, pattern)) = least_recently_used {
+            if let Some(regexes) = cache.get_mut(&flags) {
+                regexes.remove(&pattern);
+            }
+        }
+    }
+    let last_used = AtomicU64::new(CLOCK.fetch_add(1, Ordering::Relaxed));
+    cache.entry(flags).or_default().insert(
+        pattern.to_string(),
+        CachedRegex {
+            regex: regex.clone(),
+            last_used,
+        },
+    );
+    Ok(regex)
+}
+
+/// Detects the MIME type of a body by its magic numbers
+fn sniff_type(body: &[u8]) -> Option<&'static str> {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"\x1f\x8b", "application/gzip"),
+        (b"PK\x03\x04", "application/zip"),
+    ];
+
+    if body.len() >= 12 && &body[..4] == b"RIFF" && &body[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+    // "BM" alone is too common, so the reserved bytes of the file header must be zero as well
+    if body.len() >= 14 && &body[..2] == b"BM" && body[6..10] == [0; 4] {
+        return Some("image/bmp");
+    }
+
+    SIGNATURES
+        .iter()
+        .find(|(signature, _)| body.starts_with(signature))
+        .map(|(_, mime)| *mime)
+}
+
+#[derive(Debug, PartialEq)]
+enum XmlNode {
+    Element(XmlElement),
+    Text(String),
+}
+
+#[derive(Debug, PartialEq)]
+struct XmlElement {
+    name: String,
+    attributes: BTreeMap<String, String>,
+    children: Vec<XmlNode>,
+}
+
+impl XmlElement {
+    fn new(start: &BytesStart) -> Option<Self> {
+        let attributes = start
+            .attributes()
+            .map(|attribute| {
+                let attribute = attribute.ok()?;
+                let key = String::from_utf8_lossy(attribute.key.as_ref()).into_owned();
+                let value = attribute.unescape_value().ok()?.into_owned();
+                Some((key, value))
+            })
+            .collect::<Option<_>>()?;
+
+        Some(Self {
+            name: String::from_utf8_lossy(start.name().as_ref()).into_owned(),
+            attributes,
+            children: vec![],
+        })
+    }
+
+    fn push_text(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+
+        match self.children.last_mut() {
+            Some(XmlNode::Text(ref mut existing)) => existing.push_str(text),
+            _ => self.children.push(XmlNode::Text(text.to_string())),
+        }
+    }
+}
+
+/// Parses an XML document into its root element, dropping whitespace between elements,
+/// comments and processing instructions
+fn parse_xml(xml: &str) -> Option<XmlElement> {
+    let mut reader = XmlReader::from_str(xml);
+    reader.config_mut().trim_text(true);
+
+    let mut stack: Vec<XmlElement> = vec![];
+    let mut root = None;
+
+    loop {
+        let element = match reader.read_event().ok()? {
+            Event::Start(ref start) => {
+                stack.push(XmlElement::new(start)?);
+                continue;
+            }
+            Event::Empty(ref start) => XmlElement::new(start)?,
+            Event::End(_) => stack.pop()?,
+            Event::Text(ref text) => {
+                stack.last_mut()?.push_text(&text.unescape().ok()?);
+                continue;
+            }
+            Event::CData(ref data) => {
+                stack.last_mut()?.push_text(&String::from_utf8_lossy(data));
+                continue;
+            }
+            Event::Eof => break,
+            _ => continue,
+        };
+
+        match stack.last_mut() {
+            Some(parent) => parent.children.push(XmlNode::Element(element)),
+            None if root.is_none() => root = Some(element),
+            None => return None,
+        }
+    }
+
+    if stack.is_empty() {
+        root
+    } else {
+        None
+    }
+}
+
+/// Checks whether the expected element is contained within the actual element or any of its
+/// descendants
+fn xml_contains_subtree(expected: &XmlElement, actual: &XmlElement) -> bool {
+    xml_contains(expected, actual)
+        || actual.children.iter().any(|child| match child {
+            XmlNode::Element(ref element) => xml_contains_subtree(expected, element),
+            XmlNode::Text(_) => false,
+        })
+}
+
+fn xml_contains(expected: &XmlElement, actual: &XmlElement) -> bool {
+    expected.name == actual.name
+        && expected
+            .attributes
+            .iter()
+            .all(|(key, value)| actual.attributes.get(key) == Some(value))
+        && expected.children.iter().all(|expected| {
+            actual
+                .children
+                .iter()
+                .any(|actual| match (expected, actual) {
+                    (XmlNode::Element(ref expected), XmlNode::Element(ref actual)) => {
+                        xml_contains(expected, actual)
+                    }
+                    (XmlNode::Text(ref expected), XmlNode::Text(ref actual)) => expected == actual,
+                    _ => false,
+                })
+        })
+}
+
+/// Compares two JSON values structurally, treating arrays as multisets
+fn json_eq_unordered(expected: &serde_json::Value, actual: &serde_json::Value) -> bool {
+    use serde_json::Value;
+
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            expected.len() == actual.len()
+                && expected.iter().all(|(key, value)| {
+                    actual
+                        .get(key)
+                        .map(|other| json_eq_unordered(value, other))
+                        .unwrap_or(false)
+                })
+        }
+        (Value::Array(expected), Value::Array(actual)) => {
+            if expected.len() != actual.len() {
+                return false;
+            }
+
+            let mut used = vec![false; actual.len()];
+            expected.iter().all(|value| {
+                let position = actual
+                    .iter()
+                    .enumerate()
+                    .position(|(i, other)| !used[i] && json_eq_unordered(value, other));
+
+                match position {
+                    Some(i) => {
+                        used[i] = true;
+                        true
+                    }
+                    None => false,
+                }
+            })
+        }
+        _ => expected == actual,
+    }
+}
+
+/// Removes a top-level key or the value at a JSON pointer, if present
+fn remove_json_field(value: &mut serde_json::Value, field: &str) {
+    use serde_json::Value;
+
+    let (parent, key) = match field.rsplit_once('/') {
+        Some(pointer) if field.starts_with('/') => pointer,
+        _ => {
+            if let Value::Object(object) = value {
+                object.remove(field);
+            }
+            return;
+        }
+    };
+
+    let key = key.replace("~1", "/").replace("~0", "~");
+    match value.pointer_mut(parent) {
+        Some(Value::Object(object)) => {
+            object.remove(&key);
+        }
+        Some(Value::Array(array)) => {
+            if let Some(index) = key.parse::<usize>().ok().filter(|i| *i < array.len()) {
+                array.remove(index);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Compares two JSON values structurally, treating numbers as equal when their values are equal
+fn json_eq_relaxed(expected: &serde_json::Value, actual: &serde_json::Value) -> bool {
+    use serde_json::Value;
+
+    match (expected, actual) {
+        (Value::Number(expected), Value::Number(actual)) => {
+            match (expected.as_i64(), actual.as_i64()) {
+                (Some(expected), Some(actual)) => expected == actual,
+                _ => match (expected.as_u64(), actual.as_u64()) {
+                    (Some(expected), Some(actual)) => expected == actual,
+                    _ => expected.as_f64() == actual.as_f64(),
+                },
+            }
+        }
+        (Value::Object(expected), Value::Object(actual)) => {
+            expected.len() == actual.len()
+                && expected.iter().all(|(key, value)| {
+                    actual
+                        .get(key)
+                        .map(|other| json_eq_relaxed(value, other))
+                        .unwrap_or(false)
+                })
+        }
+        (Value::Array(expected), Value::Array(actual)) => {
+            expected.len() == actual.len()
+                && expected
+                    .iter()
+                    .zip(actual.iter())
+                    .all(|(value, other)| json_eq_relaxed(value, other))
+        }
+        _ => expected == actual,
+    }
+}
+
 #[derive(Clone, PartialEq, Debug)]
 pub(crate) enum PathAndQueryMatcher {
     Unified(Matcher),
@@ -192,16 +879,32 @@ pub(crate) enum PathAndQueryMatcher {
 }
 
 impl PathAndQueryMatcher {
-    pub(crate) fn matches_value(&self, other: &str) -> bool {
+    pub(crate) fn matches_value(&self, other: &str, max_depth: usize) -> bool {
         match self {
-            PathAndQueryMatcher::Unified(matcher) => matcher.matches_value(other),
+            PathAndQueryMatcher::Unified(matcher) => matcher.matches_value(other, max_depth),
             PathAndQueryMatcher::Split(ref path_matcher, ref query_matcher) => {
                 let mut parts = other.splitn(2, '?');
                 let path = parts.next().unwrap();
                 let query = parts.next().unwrap_or("");
 
-                path_matcher.matches_value(path) && query_matcher.matches_value(query)
+                path_matcher.matches_value(path, max_depth)
+                    && query_matcher.matches_value(query, max_depth)
+            }
+        }
+    }
+
+    /// The values captured by a `Matcher::PathTemplate`, if that's how the path is matched
+    pub(crate) fn path_params(&self, path_and_query: &str) -> HashMap<String, String> {
+        let path_matcher = match self {
+            PathAndQueryMatcher::Unified(matcher) => matcher,
+            PathAndQueryMatcher::Split(path_matcher, _) => path_matcher.as_ref(),
+        };
+
+        match path_matcher {
+            Matcher::PathTemplate(template) => {
+                path_params(template, path_and_query).unwrap_or_default()
             }
+            _ => HashMap::new(),
         }
     }
 }
@@ -284,6 +987,42 @@ impl fmt::Display for BinaryBody {
     }
 }
 
+type MatcherPredicate = dyn Fn(&str) -> bool + Send + Sync;
+
+///
+/// A predicate used by `Matcher::Fn`. Two predicates are only equal if they are the same
+/// instance.
+///
+/// ## Example
+///
+/// ```
+/// use mockito::{Matcher, MatcherFn};
+///
+/// let is_numeric = Matcher::Fn(MatcherFn::new(|value| value.parse::<u64>().is_ok()));
+/// ```
+///
+#[derive(Clone)]
+pub struct MatcherFn(Arc<MatcherPredicate>);
+
+impl MatcherFn {
+    /// Wraps the given predicate, which receives the path, header value or body as a string
+    pub fn new(predicate: impl Fn(&str) -> bool + Send + Sync + 'static) -> Self {
+        Self(Arc::new(predicate))
+    }
+}
+
+impl fmt::Debug for MatcherFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("(fn)")
+    }
+}
+
+impl PartialEq for MatcherFn {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
 #[derive(Clone)]
 pub(crate) struct RequestMatcher(Arc<dyn Fn(&Request) -> bool + Send + Sync>);
 
@@ -1,22 +1,31 @@
-use crate::diff;
+use crate::cookie::CookieBuilder;
 use crate::matcher::{Matcher, PathAndQueryMatcher, RequestMatcher};
-use crate::response::{Body, Header, Response};
+use crate::response::{
+    BackpressureBody, Body, BodySequence, ContentEncoding, Header, LazyBody, MockResponse,
+    RandomStatus, Response, StatusFn, TransferEncoding,
+};
 use crate::server::RemoteMock;
 use crate::server::State;
+use crate::websocket::{WebSocketHandler, WebSocketMessage};
 use crate::Request;
-use crate::{Error, ErrorKind};
+use crate::{AssertError, Error, ErrorKind};
 use bytes::Bytes;
+use http::header::{CONTENT_TYPE, LOCATION, SET_COOKIE};
 use http::{HeaderMap, HeaderName, StatusCode};
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
+use regex::Regex;
 use std::convert::Into;
 use std::fmt;
+use std::future::Future;
 use std::io;
-use std::ops::Drop;
+use std::net::SocketAddr;
+use std::ops::{Drop, RangeInclusive};
 use std::path::Path;
 use std::string::ToString;
 use std::sync::Arc;
-use std::sync::RwLock;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
 
 #[allow(missing_docs)]
 pub trait IntoHeaderName {
@@ -66,12 +75,68 @@ pub struct InnerMock {
     pub(crate) method: String,
     pub(crate) path: PathAndQueryMatcher,
     pub(crate) headers: HeaderMap<Matcher>,
+    pub(crate) query_params: Vec<(String, Matcher)>,
     pub(crate) body: Matcher,
+    pub(crate) body_captures: Option<(Regex, Vec<(String, Matcher)>)>,
     pub(crate) request_matcher: RequestMatcher,
+    pub(crate) remote_addr_matcher: RequestMatcher,
+    pub(crate) encoded_body: Option<(String, Matcher)>,
+    pub(crate) connection_request_index: Option<usize>,
     pub(crate) response: Response,
     pub(crate) hits: usize,
     pub(crate) expected_hits_at_least: Option<usize>,
     pub(crate) expected_hits_at_most: Option<usize>,
+    pub(crate) expire_after: Option<Duration>,
+    pub(crate) created_at: Option<Instant>,
+}
+
+impl InnerMock {
+    /// Checks the body against the body matcher and the capture group matchers
+    pub(crate) fn body_matches(&self, body: &[u8], max_depth: usize) -> bool {
+        self.body.matches_body(body, max_depth) && self.body_captures_match(body, max_depth)
+    }
+
+    /// Checks whether both mocks have the same method, path, query, header and body matchers.
+    /// Custom predicates like `Mock::match_request` can't be compared and are ignored.
+    pub(crate) fn matchers_eq(&self, other: &InnerMock) -> bool {
+        let body_captures = |mock: &InnerMock| {
+            mock.body_captures
+                .as_ref()
+                .map(|(regex, matchers)| (regex.as_str().to_string(), matchers.clone()))
+        };
+
+        self.method == other.method
+            && self.path == other.path
+            && self.headers == other.headers
+            && self.query_params == other.query_params
+            && self.body == other.body
+            && body_captures(self) == body_captures(other)
+            && self.connection_request_index == other.connection_request_index
+    }
+
+    fn body_captures_match(&self, body: &[u8], max_depth: usize) -> bool {
+        let Some((ref regex, ref expected)) = self.body_captures else {
+            return true;
+        };
+
+        let body = String::from_utf8_lossy(body);
+        let Some(captures) = regex.captures(&body) else {
+            return false;
+        };
+
+        expected.iter().all(|(group, matcher)| {
+            let capture = match group.parse::<usize>() {
+                Ok(index) => captures.get(index),
+                Err(_) => captures.name(group),
+            };
+            let values: Vec<&str> = capture
+                .map(|capture| capture.as_str())
+                .into_iter()
+                .collect();
+
+            matcher.matches_str_values(&values, max_depth)
+        })
+    }
 }
 
 impl fmt::Display for InnerMock {
@@ -84,6 +149,14 @@ impl fmt::Display for InnerMock {
         formatted.push(' ');
         formatted.push_str(&self.path.to_string());
 
+        for (name, value) in &self.query_params {
+            formatted.push_str("query param ");
+            formatted.push_str(name);
+            formatted.push_str(": ");
+            formatted.push_str(&value.to_string());
+            formatted.push_str("\r\n");
+        }
+
         for (key, value) in &self.headers {
             formatted.push_str(key.as_str());
             formatted.push_str(": ");
@@ -93,30 +166,80 @@ impl fmt::Display for InnerMock {
 
         match self.body {
             Matcher::Exact(ref value)
+            | Matcher::ExactIgnoreCase(ref value)
+            | Matcher::Prefix(ref value)
+            | Matcher::Suffix(ref value)
+            | Matcher::Contains(ref value)
+            | Matcher::InList(ref value)
             | Matcher::JsonString(ref value)
             | Matcher::PartialJsonString(ref value)
-            | Matcher::Regex(ref value) => {
+            | Matcher::Xml(ref value)
+            | Matcher::PartialXml(ref value)
+            | Matcher::PathTemplate(ref value)
+            | Matcher::Regex(ref value)
+            | Matcher::RegexWith {
+                pattern: ref value, ..
+            } => {
                 formatted.push_str(value);
                 formatted.push_str("\r\n");
             }
             Matcher::Binary(_) => {
                 formatted.push_str("(binary)\r\n");
             }
-            Matcher::Json(ref json_obj) | Matcher::PartialJson(ref json_obj) => {
+            Matcher::SniffedType(ref value) => {
+                formatted.push_str(value);
+                formatted.push_str(" (sniffed type)\r\n");
+            }
+            Matcher::Json(ref json_obj)
+            | Matcher::JsonUnordered(ref json_obj)
+            | Matcher::JsonRelaxed(ref json_obj)
+            | Matcher::JsonIgnoring(ref json_obj, _)
+            | Matcher::PartialJson(ref json_obj) => {
                 formatted.push_str(&json_obj.to_string());
                 formatted.push_str("\r\n")
             }
+            Matcher::Toml(ref toml_obj) => {
+                formatted.push_str(&toml_obj.to_string());
+                formatted.push_str("\r\n")
+            }
+            Matcher::JsonContainsArrayItems(ref pointer, ref items) => {
+                formatted.push_str(pointer);
+                formatted.push_str(" contains ");
+                formatted.push_str(&serde_json::Value::from(items.clone()).to_string());
+                formatted.push_str("\r\n")
+            }
             Matcher::UrlEncoded(ref field, ref value) => {
                 formatted.push_str(field);
                 formatted.push('=');
                 formatted.push_str(value);
             }
+            Matcher::GreaterThan(..)
+            | Matcher::LessThan(..)
+            | Matcher::NumericRange(..)
+            | Matcher::UrlEncodedAll(..)
+            | Matcher::QueryExactOrdered(..)
+            | Matcher::BodySize(..)
+            | Matcher::QueryParamCount(..) => {
+                formatted.push_str(&self.body.to_string());
+                formatted.push_str("\r\n");
+            }
+            Matcher::Fn(..) => formatted.push_str("(fn)\r\n"),
             Matcher::Missing => formatted.push_str("(missing)\r\n"),
+            Matcher::Empty => formatted.push_str("(empty)\r\n"),
             Matcher::AnyOf(..) => formatted.push_str("(any of)\r\n"),
             Matcher::AllOf(..) => formatted.push_str("(all of)\r\n"),
             Matcher::Any => {}
         }
 
+        if let Some((ref regex, ref captures)) = self.body_captures {
+            for (group, matcher) in captures {
+                formatted.push_str(&format!(
+                    "body capture {} of {}: {}\r\n",
+                    group, regex, matcher
+                ));
+            }
+        }
+
         f.write_str(&formatted)
     }
 }
@@ -133,6 +256,60 @@ impl PartialEq for InnerMock {
     }
 }
 
+fn mime_type_for(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+
+    match extension.as_deref() {
+        Some("json") => "application/json",
+        Some("html" | "htm") => "text/html",
+        Some("txt" | "http") => "text/plain",
+        Some("css") => "text/css",
+        Some("csv") => "text/csv",
+        Some("js") => "text/javascript",
+        Some("xml") => "application/xml",
+        Some("pdf") => "application/pdf",
+        Some("zip") => "application/zip",
+        Some("gz") => "application/gzip",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        Some("webp") => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
+
+fn random_id() -> String {
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .map(char::from)
+        .take(24)
+        .collect()
+}
+
+///
+/// A reusable mock definition, which isn't bound to any server. Build one with
+/// `MockSpec::builder` (or `Mock::into_spec`) and register it with `Server::register`.
+///
+#[derive(Clone, Debug)]
+pub struct MockSpec {
+    inner: InnerMock,
+}
+
+impl MockSpec {
+    ///
+    /// Starts building a mock definition with the given HTTP `method` and `path`. Chain the
+    /// usual `Mock` methods and finish with `Mock::into_spec`.
+    ///
+    #[track_caller]
+    pub fn builder<P: Into<Matcher>>(method: &str, path: P) -> Mock {
+        Mock::new(Arc::new(RwLock::new(State::new())), method, path, false)
+    }
+}
+
 ///
 /// Stores information about a mocked request. Should be initialized via `Server::mock()`.
 ///
@@ -146,6 +323,7 @@ pub struct Mock {
 }
 
 impl Mock {
+    #[track_caller]
     pub(crate) fn new<P: Into<Matcher>>(
         state: Arc<RwLock<State>>,
         method: &str,
@@ -153,20 +331,23 @@ impl Mock {
         assert_on_drop: bool,
     ) -> Mock {
         let inner = InnerMock {
-            id: thread_rng()
-                .sample_iter(&Alphanumeric)
-                .map(char::from)
-                .take(24)
-                .collect(),
+            id: random_id(),
             method: method.to_owned().to_uppercase(),
-            path: PathAndQueryMatcher::Unified(path.into()),
+            path: PathAndQueryMatcher::Unified(path.into().validated()),
             headers: HeaderMap::<Matcher>::default(),
+            query_params: vec![],
             body: Matcher::Any,
+            body_captures: None,
             request_matcher: RequestMatcher::default(),
+            remote_addr_matcher: RequestMatcher::default(),
+            encoded_body: None,
+            connection_request_index: None,
             response: Response::default(),
             hits: 0,
             expected_hits_at_least: None,
             expected_hits_at_most: None,
+            expire_after: None,
+            created_at: None,
         };
 
         Self {
@@ -177,6 +358,52 @@ impl Mock {
         }
     }
 
+    pub(crate) fn from_spec(
+        state: Arc<RwLock<State>>,
+        spec: &MockSpec,
+        assert_on_drop: bool,
+    ) -> Mock {
+        let mut inner = spec.inner.clone();
+        inner.id = random_id();
+        inner.hits = 0;
+        inner.response.restart();
+
+        Self {
+            state,
+            inner,
+            created: false,
+            assert_on_drop,
+        }
+    }
+
+    ///
+    /// Turns this mock definition into a reusable `MockSpec`, which can be registered on
+    /// multiple servers via `Server::register`. The mock itself is not created.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use mockito::MockSpec;
+    ///
+    /// let spec = MockSpec::builder("GET", "/hello").with_body("world").into_spec();
+    ///
+    /// let mut s1 = mockito::Server::new();
+    /// let mut s2 = mockito::Server::new();
+    ///
+    /// s1.register(&spec);
+    /// s2.register(&spec);
+    /// ```
+    ///
+    pub fn into_spec(mut self) -> MockSpec {
+        // the definition lives on in the spec, so there's nothing to create or assert
+        self.created = true;
+        self.assert_on_drop = false;
+
+        MockSpec {
+            inner: self.inner.clone(),
+        }
+    }
+
     ///
     /// Allows matching against the query part when responding with a mock.
     ///
@@ -212,13 +439,15 @@ impl Mock {
     ///   .create();
     /// ```
     ///
+    #[track_caller]
     pub fn match_query<M: Into<Matcher>>(mut self, query: M) -> Self {
+        let query = query.into().validated();
         let new_path = match &self.inner.path {
             PathAndQueryMatcher::Unified(matcher) => {
-                PathAndQueryMatcher::Split(Box::new(matcher.clone()), Box::new(query.into()))
+                PathAndQueryMatcher::Split(Box::new(matcher.clone()), Box::new(query))
             }
             PathAndQueryMatcher::Split(path, _) => {
-                PathAndQueryMatcher::Split(path.clone(), Box::new(query.into()))
+                PathAndQueryMatcher::Split(path.clone(), Box::new(query))
             }
         };
 
@@ -227,6 +456,81 @@ impl Mock {
         self
     }
 
+    ///
+    /// Allows matching a single query parameter by its (unencoded) name. The matcher is applied
+    /// to the decoded value(s) of the parameter, the same way header values are matched.
+    ///
+    /// Calling this multiple times requires all the given parameters to match. The rest of the
+    /// query is ignored, unless `Mock::match_query` is used as well.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use mockito::Matcher;
+    ///
+    /// let mut s = mockito::Server::new();
+    ///
+    /// // This will match requests like `/users?page=3`, but not `/users?page=abc`
+    /// s.mock("GET", "/users")
+    ///   .match_query_param("page", Matcher::GreaterThan(0))
+    ///   .create();
+    /// ```
+    ///
+    #[track_caller]
+    pub fn match_query_param<M: Into<Matcher>>(mut self, name: &str, value: M) -> Self {
+        if let PathAndQueryMatcher::Unified(matcher) = &self.inner.path {
+            self.inner.path =
+                PathAndQueryMatcher::Split(Box::new(matcher.clone()), Box::new(Matcher::Any));
+        }
+
+        self.inner
+            .query_params
+            .push((name.to_string(), value.into().validated()));
+
+        self
+    }
+
+    ///
+    /// Matches the path of a gRPC call, which has the form `/{service}/{method}`, where
+    /// `service` is the fully qualified service name (e.g. `helloworld.Greeter`).
+    ///
+    /// Panics if either the service or the method are empty or contain a `/`.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// // This will match requests to `/helloworld.Greeter/SayHello`
+    /// s.mock("POST", "/")
+    ///   .match_grpc_method("helloworld.Greeter", "SayHello")
+    ///   .create();
+    /// ```
+    ///
+    #[track_caller]
+    pub fn match_grpc_method(mut self, service: &str, method: &str) -> Self {
+        for part in [service, method] {
+            assert!(
+                !part.is_empty() && !part.contains('/'),
+                "{}",
+                Error::new_with_context(
+                    ErrorKind::InvalidPath,
+                    format!("invalid gRPC service or method: {:?}", part)
+                )
+            );
+        }
+
+        let path = Matcher::Exact(format!("/{}/{}", service, method));
+        self.inner.path = match &self.inner.path {
+            PathAndQueryMatcher::Unified(_) => PathAndQueryMatcher::Unified(path),
+            PathAndQueryMatcher::Split(_, query) => {
+                PathAndQueryMatcher::Split(Box::new(path), query.clone())
+            }
+        };
+
+        self
+    }
+
     ///
     /// Allows matching a particular request header when responding with a mock.
     ///
@@ -256,11 +560,29 @@ impl Mock {
     pub fn match_header<T: IntoHeaderName, M: Into<Matcher>>(mut self, field: T, value: M) -> Self {
         self.inner
             .headers
-            .append(field.into_header_name(), value.into());
+            .append(field.into_header_name(), value.into().validated());
 
         self
     }
 
+    ///
+    /// Allows matching the `Expect` request header. This is a shortcut for
+    /// `Mock::match_header("expect", ...)`.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// // Only uploads announced with `Expect: 100-continue` will be matched
+    /// s.mock("PUT", "/upload").match_expect("100-continue").create();
+    /// ```
+    ///
+    #[track_caller]
+    pub fn match_expect<M: Into<Matcher>>(self, value: M) -> Self {
+        self.match_header(http::header::EXPECT, value)
+    }
+
     ///
     /// Allows matching a particular request body when responding with a mock.
     ///
@@ -299,8 +621,101 @@ impl Mock {
     /// s.mock("POST", "/").match_body(&mut f_read).create();
     /// ```
     ///
+    #[track_caller]
     pub fn match_body<M: Into<Matcher>>(mut self, body: M) -> Self {
-        self.inner.body = body.into();
+        self.inner.body = body.into().validated();
+
+        self
+    }
+
+    ///
+    /// Matches requests whose body size in bytes is within the given range, regardless of its
+    /// content. Shorthand for `Mock::match_body(Matcher::BodySize(range))`.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// // Matches uploads of up to 1 KB
+    /// s.mock("POST", "/upload").match_body_size(1..=1024).create();
+    /// ```
+    ///
+    pub fn match_body_size(self, range: RangeInclusive<usize>) -> Self {
+        self.match_body(Matcher::BodySize(range))
+    }
+
+    ///
+    /// Matches the request body against the given regular expression and applies the given
+    /// matchers to its capture groups. Groups are referenced by name or by index (e.g. `"1"`).
+    /// Groups which didn't participate in the match are treated as missing values.
+    ///
+    /// Panics if the regular expression is invalid.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use mockito::Matcher;
+    ///
+    /// let mut s = mockito::Server::new();
+    ///
+    /// // This will match bodies like `order 42 for alice`
+    /// s.mock("POST", "/")
+    ///   .match_body_captures(
+    ///     r"order (?P<id>\w+) for (\w+)",
+    ///     vec![
+    ///       ("id", Matcher::Regex("^[0-9]+$".to_string())),
+    ///       ("2", Matcher::Exact("alice".to_string())),
+    ///     ],
+    ///   )
+    ///   .create();
+    /// ```
+    ///
+    #[track_caller]
+    pub fn match_body_captures<M: Into<Matcher>>(
+        mut self,
+        regex: &str,
+        captures: Vec<(&str, M)>,
+    ) -> Self {
+        let regex = Regex::new(regex)
+            .map_err(|err| Error::new_with_context(ErrorKind::InvalidRegex, err))
+            .unwrap();
+        let captures = captures
+            .into_iter()
+            .map(|(group, matcher)| (group.to_string(), matcher.into().validated()))
+            .collect();
+
+        self.inner.body_captures = Some((regex, captures));
+
+        self
+    }
+
+    ///
+    /// Requires the request to declare the given `content-encoding` (e.g. `gzip` or `deflate`)
+    /// and matches its decompressed body against the given matcher. Requests with a different
+    /// encoding or a body that can't be decompressed don't match.
+    ///
+    /// Unlike `ServerOpts::decode_request_body`, this doesn't accept uncompressed bodies.
+    /// Encodings whose feature flag (`gzip` or `deflate`) is disabled never match.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use mockito::Matcher;
+    ///
+    /// let mut s = mockito::Server::new();
+    ///
+    /// s.mock("POST", "/")
+    ///   .match_encoded_body("gzip", Matcher::Regex("hello".to_string()))
+    ///   .create();
+    /// ```
+    ///
+    #[track_caller]
+    pub fn match_encoded_body<M: Into<Matcher>>(mut self, encoding: &str, body: M) -> Self {
+        let encoding = encoding.trim().to_ascii_lowercase();
+        let body = body.into().validated();
+
+        self.inner.encoded_body = Some((encoding, body));
 
         self
     }
@@ -336,113 +751,594 @@ impl Mock {
     }
 
     ///
-    /// Sets the status code of the mock response. The default status code is 200.
+    /// Allows matching the request by the address of the client, based on a closure that takes
+    /// the remote [`SocketAddr`] as an argument and returns a boolean value.
     ///
     /// ## Example
     ///
     /// ```
     /// let mut s = mockito::Server::new();
     ///
-    /// s.mock("GET", "/").with_status(201);
+    /// // This will only match requests coming from the loopback interface
+    /// s.mock("GET", "/")
+    ///     .match_remote_addr(|addr| addr.ip().is_loopback())
+    ///     .create();
     /// ```
     ///
-    #[track_caller]
-    pub fn with_status(mut self, status: usize) -> Self {
-        self.inner.response.status = StatusCode::from_u16(status as u16)
-            .map_err(|_| Error::new_with_context(ErrorKind::InvalidStatusCode, status))
-            .unwrap();
+    pub fn match_remote_addr<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(SocketAddr) -> bool + Send + Sync + 'static,
+    {
+        self.inner.remote_addr_matcher =
+            (move |request: &Request| request.remote_addr().is_some_and(&predicate)).into();
 
         self
     }
 
     ///
-    /// Sets a header of the mock response.
+    /// Allows matching only the request with the given zero-based index on its connection.
+    /// The first request sent over a connection has the index 0, the second request sent over
+    /// the same (kept-alive or multiplexed) connection has the index 1 and so on.
+    ///
+    /// Use this to verify how clients reuse their connections.
     ///
     /// ## Example
     ///
     /// ```
     /// let mut s = mockito::Server::new();
     ///
-    /// s.mock("GET", "/").with_header("content-type", "application/json");
+    /// // Only the second request sent over a connection will be matched
+    /// s.mock("GET", "/").match_connection_request_index(1).create();
     /// ```
     ///
-    pub fn with_header<T: IntoHeaderName>(mut self, field: T, value: &str) -> Self {
-        self.inner
-            .response
-            .headers
-            .append(field.into_header_name(), Header::String(value.to_string()));
-
+    pub fn match_connection_request_index(mut self, index: usize) -> Self {
+        self.inner.connection_request_index = Some(index);
         self
     }
 
     ///
-    /// Sets the headers of the mock response dynamically while exposing the request object.
-    ///
-    /// You can use this method to provide custom headers for every incoming request.
-    ///
-    /// The function must be thread-safe. If it's a closure, it can't be borrowing its context.
-    /// Use `move` closures and `Arc` to share any data.
+    /// Replaces the response of the mock with the given `MockResponse`. Any status, headers or
+    /// body defined on the mock so far are overridden.
     ///
-    /// ### Example
+    /// ## Example
     ///
     /// ```
+    /// use mockito::MockResponse;
+    ///
     /// let mut s = mockito::Server::new();
     ///
-    /// let _m = s.mock("GET", mockito::Matcher::Any).with_header_from_request("x-user", |request| {
-    ///     if request.path() == "/bob" {
-    ///         "bob".into()
-    ///     } else if request.path() == "/alice" {
-    ///         "alice".into()
-    ///     } else {
-    ///         "everyone".into()
-    ///     }
-    /// });
+    /// let not_found = MockResponse::new().with_status(404).with_body("not found");
+    ///
+    /// s.mock("GET", "/").with_response(not_found).create();
     /// ```
     ///
-    pub fn with_header_from_request<T: IntoHeaderName>(
-        mut self,
-        field: T,
-        callback: impl Fn(&Request) -> String + Send + Sync + 'static,
-    ) -> Self {
-        self.inner.response.headers.append(
-            field.into_header_name(),
-            Header::FnWithRequest(Arc::new(move |req| callback(req))),
-        );
+    pub fn with_response(mut self, response: MockResponse) -> Self {
+        self.inner.response = response.inner;
         self
     }
 
     ///
-    /// Sets the body of the mock response. Its `Content-Length` is handled automatically.
+    /// Sets the status code of the mock response. The default status code is 200.
     ///
     /// ## Example
     ///
     /// ```
     /// let mut s = mockito::Server::new();
     ///
-    /// s.mock("GET", "/").with_body("hello world");
+    /// s.mock("GET", "/").with_status(201);
     /// ```
     ///
-    pub fn with_body<StrOrBytes: AsRef<[u8]>>(mut self, body: StrOrBytes) -> Self {
-        self.inner.response.body = Body::Bytes(Bytes::from(body.as_ref().to_owned()));
+    #[track_caller]
+    pub fn with_status(mut self, status: usize) -> Self {
+        self.inner.response.status = StatusCode::from_u16(status as u16)
+            .map_err(|_| Error::new_with_context(ErrorKind::InvalidStatusCode, status))
+            .unwrap();
+        self.inner.response.random_status = None;
+        self.inner.response.status_fn = None;
+
         self
     }
 
     ///
-    /// Sets the body of the mock response dynamically. The response will use chunked transfer encoding.
-    ///
-    /// The callback function will be called only once. You can sleep in between calls to the
-    /// writer to simulate delays between the chunks. The callback function can also return an
-    /// error after any number of writes in order to abort the response.
+    /// Sets the mock response to use a random status code from the given range on every hit.
+    /// The status codes are picked by a random number generator initialized with `seed`, so
+    /// the same seed always produces the same sequence.
     ///
-    /// The function must be thread-safe. If it's a closure, it can't be borrowing its context.
-    /// Use `move` closures and `Arc` to share any data.
+    /// Useful for chaos testing your client against e.g. a flaky upstream.
     ///
     /// ## Example
     ///
     /// ```
     /// let mut s = mockito::Server::new();
     ///
-    /// s.mock("GET", "/").with_chunked_body(|w| w.write_all(b"hello world"));
+    /// s.mock("GET", "/").with_random_status_in(500..=504, 42);
+    /// ```
+    ///
+    #[track_caller]
+    pub fn with_random_status_in(mut self, range: RangeInclusive<usize>, seed: u64) -> Self {
+        let (start, end) = (*range.start(), *range.end());
+        let start = StatusCode::from_u16(start as u16)
+            .map_err(|_| Error::new_with_context(ErrorKind::InvalidStatusCode, start))
+            .unwrap()
+            .as_u16();
+        let end = StatusCode::from_u16(end as u16)
+            .map_err(|_| Error::new_with_context(ErrorKind::InvalidStatusCode, end))
+            .unwrap()
+            .as_u16();
+        assert!(
+            start <= end,
+            "{}",
+            Error::new_with_context(ErrorKind::InvalidStatusCode, format!("{:?}", range))
+        );
+
+        let range = start..=end;
+        self.inner.response.random_status = Some(RandomStatus::new(range, seed));
+        self.inner.response.status_fn = None;
+
+        self
+    }
+
+    ///
+    /// Sets the status code of the mock response dynamically, based on the request.
+    ///
+    /// The returned status code is validated when the response is sent. If it isn't a valid
+    /// status code, the connection is closed without a response.
+    ///
+    /// The function must be thread-safe. If it's a closure, it can't be borrowing its context.
+    /// Use `move` closures and `Arc` to share any data.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// s.mock("GET", mockito::Matcher::Regex("^/users".to_string()))
+    ///   .with_status_from_request(|request| {
+    ///       if request.path_and_query().contains("id=") {
+    ///           200
+    ///       } else {
+    ///           400
+    ///       }
+    ///   })
+    ///   .create();
+    /// ```
+    ///
+    pub fn with_status_from_request(
+        mut self,
+        callback: impl Fn(&Request) -> u16 + Send + Sync + 'static,
+    ) -> Self {
+        self.inner.response.status_fn = Some(StatusFn::new(callback));
+        self.inner.response.random_status = None;
+
+        self
+    }
+
+    ///
+    /// Sets the mock response to redirect to the given `location`, using the given 3xx
+    /// `status` code, a `Location` header and an empty body.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// s.mock("GET", "/old").with_redirect(303, "/new");
+    /// ```
+    ///
+    #[track_caller]
+    pub fn with_redirect(mut self, status: usize, location: &str) -> Self {
+        assert!(
+            (300..400).contains(&status),
+            "{}",
+            Error::new_with_context(
+                ErrorKind::InvalidStatusCode,
+                format!("{} is not a redirect", status)
+            )
+        );
+
+        self = self.with_status(status);
+        self.inner
+            .response
+            .headers
+            .insert(LOCATION, Header::String(location.to_string()));
+        self.inner.response.body = Body::Bytes(Bytes::new());
+
+        self
+    }
+
+    ///
+    /// Same as `Mock::with_redirect`, using the `302 Found` status code.
+    ///
+    #[track_caller]
+    pub fn with_temporary_redirect(self, location: &str) -> Self {
+        self.with_redirect(302, location)
+    }
+
+    ///
+    /// Same as `Mock::with_redirect`, using the `301 Moved Permanently` status code.
+    ///
+    #[track_caller]
+    pub fn with_permanent_redirect(self, location: &str) -> Self {
+        self.with_redirect(301, location)
+    }
+
+    ///
+    /// Sets the mock to accept WebSocket connections: the `101 Switching Protocols` handshake
+    /// is performed and every message received from the client is passed to the `handler`.
+    /// Returning `Some` from the handler sends a message back to the client. Pings and close
+    /// frames are answered automatically. Messages larger than 16 MiB close the connection with
+    /// status `1009` (message too big).
+    ///
+    /// Requests that are not WebSocket upgrades receive a `400 Bad Request` response.
+    /// Only HTTP/1.1 upgrades are supported.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// // Echoes every message back to the client
+    /// s.mock("GET", "/ws").with_websocket(Some).create();
+    /// ```
+    ///
+    pub fn with_websocket<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(WebSocketMessage) -> Option<WebSocketMessage> + Send + Sync + 'static,
+    {
+        self.inner.response.websocket = Some(WebSocketHandler::new(handler));
+        self
+    }
+
+    ///
+    /// Sets a header of the mock response.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// s.mock("GET", "/").with_header("content-type", "application/json");
+    /// ```
+    ///
+    pub fn with_header<T: IntoHeaderName>(mut self, field: T, value: &str) -> Self {
+        self.inner
+            .response
+            .headers
+            .append(field.into_header_name(), Header::String(value.to_string()));
+
+        self
+    }
+
+    ///
+    /// Appends all the headers of the given `HeaderMap` to the mock response, e.g. the headers
+    /// copied from a real response. Repeated headers are preserved in order.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use http::header::{HeaderMap, HeaderValue, SET_COOKIE};
+    ///
+    /// let mut headers = HeaderMap::new();
+    /// headers.append(SET_COOKIE, HeaderValue::from_static("a=1"));
+    /// headers.append(SET_COOKIE, HeaderValue::from_static("b=2"));
+    ///
+    /// let mut s = mockito::Server::new();
+    ///
+    /// s.mock("GET", "/").with_header_map(headers);
+    /// ```
+    ///
+    pub fn with_header_map(mut self, headers: HeaderMap) -> Self {
+        for (field, value) in headers.iter() {
+            let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+            self.inner
+                .response
+                .headers
+                .append(field, Header::String(value));
+        }
+
+        self
+    }
+
+    ///
+    /// Adds a `Set-Cookie` header with the given name and value to the mock response.
+    /// Calling this multiple times adds multiple `Set-Cookie` headers.
+    ///
+    /// Use `Mock::with_cookie_builder` to also set the cookie attributes.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// s.mock("GET", "/").with_cookie("session", "abc123");
+    /// ```
+    ///
+    pub fn with_cookie(self, name: &str, value: &str) -> Self {
+        self.with_cookie_builder(CookieBuilder::new(name, value))
+    }
+
+    ///
+    /// Adds a `Set-Cookie` header built from a `CookieBuilder` to the mock response.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use mockito::{CookieBuilder, SameSite};
+    ///
+    /// let mut s = mockito::Server::new();
+    ///
+    /// s.mock("GET", "/").with_cookie_builder(
+    ///     CookieBuilder::new("session", "abc123")
+    ///         .path("/")
+    ///         .secure(true)
+    ///         .same_site(SameSite::Strict),
+    /// );
+    /// ```
+    ///
+    pub fn with_cookie_builder(mut self, cookie: CookieBuilder) -> Self {
+        self.inner
+            .response
+            .headers
+            .append(SET_COOKIE, Header::String(cookie.to_string()));
+
+        self
+    }
+
+    ///
+    /// Sets the headers of the mock response dynamically while exposing the request object.
+    ///
+    /// You can use this method to provide custom headers for every incoming request.
+    ///
+    /// The function must be thread-safe. If it's a closure, it can't be borrowing its context.
+    /// Use `move` closures and `Arc` to share any data.
+    ///
+    /// ### Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// let _m = s.mock("GET", mockito::Matcher::Any).with_header_from_request("x-user", |request| {
+    ///     if request.path() == "/bob" {
+    ///         "bob".into()
+    ///     } else if request.path() == "/alice" {
+    ///         "alice".into()
+    ///     } else {
+    ///         "everyone".into()
+    ///     }
+    /// });
+    /// ```
+    ///
+    pub fn with_header_from_request<T: IntoHeaderName>(
+        mut self,
+        field: T,
+        callback: impl Fn(&Request) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.inner.response.headers.append(
+            field.into_header_name(),
+            Header::FnWithRequest(Arc::new(move |req| callback(req))),
+        );
+        self
+    }
+
+    ///
+    /// Sends a `103 Early Hints` informational response with the given headers, ahead of
+    /// the final response.
+    ///
+    /// Interim responses are only sent to HTTP/1.1 clients and are skipped for other versions.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// s.mock("GET", "/")
+    ///   .with_early_hints(vec![("link", "</style.css>; rel=preload; as=style")])
+    ///   .with_body("hello world");
+    /// ```
+    ///
+    #[track_caller]
+    pub fn with_early_hints<T: IntoHeaderName>(mut self, headers: Vec<(T, &str)>) -> Self {
+        let mut early_hints = HeaderMap::<String>::default();
+        for (field, value) in headers {
+            early_hints.append(field.into_header_name(), value.to_string());
+        }
+
+        self.inner.response.early_hints = Some(early_hints);
+        self
+    }
+
+    ///
+    /// Appends a trailer field, sent after the last chunk of the mock response. The `Trailer`
+    /// response header announcing the trailer fields is set automatically.
+    ///
+    /// Trailers are only delivered along with chunked responses (e.g. `Mock::with_chunked_body`
+    /// or `TransferEncoding::Chunked`) and, for HTTP/1.1, only to clients sending `TE: trailers`.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// s.mock("GET", "/")
+    ///   .with_chunked_body(|w| w.write_all(b"hello world"))
+    ///   .with_trailer("x-checksum", "abc123");
+    /// ```
+    ///
+    #[track_caller]
+    pub fn with_trailer<T: IntoHeaderName>(mut self, field: T, value: &str) -> Self {
+        self.inner
+            .response
+            .trailers
+            .append(field.into_header_name(), value.to_string());
+        self
+    }
+
+    ///
+    /// Sets the body of the mock response. Its `Content-Length` is handled automatically.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// s.mock("GET", "/").with_body("hello world");
+    /// ```
+    ///
+    pub fn with_body<StrOrBytes: AsRef<[u8]>>(mut self, body: StrOrBytes) -> Self {
+        self.inner.response.body = Body::Bytes(Bytes::from(body.as_ref().to_owned()));
+        self
+    }
+
+    ///
+    /// Sets the body of the mock response to the given JSON value, pretty-printed for
+    /// readability when eyeballing captured traffic. Also sets the `Content-Type` header to
+    /// `application/json`, unless a content type was already set on the mock.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use serde_json::json;
+    ///
+    /// let mut s = mockito::Server::new();
+    ///
+    /// s.mock("GET", "/").with_body_from_json_pretty(json!({"hello": "world"}));
+    /// ```
+    ///
+    pub fn with_body_from_json_pretty(mut self, value: serde_json::Value) -> Self {
+        // serializing a `Value` can't fail
+        let body = serde_json::to_string_pretty(&value).unwrap();
+        self.inner.response.body = Body::Bytes(Bytes::from(body));
+
+        if !self.inner.response.headers.contains_key(CONTENT_TYPE) {
+            self.inner
+                .response
+                .headers
+                .insert(CONTENT_TYPE, Header::String("application/json".to_string()));
+        }
+
+        self
+    }
+
+    ///
+    /// Sets the body of the mock response, compressed with the given encoding, along with the
+    /// matching `Content-Encoding` header. The body is compressed once, when calling this method.
+    ///
+    /// Each encoding requires its feature flag: `gzip` and `deflate` are enabled by default,
+    /// while `brotli` is optional.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// # #[cfg(feature = "gzip")]
+    /// # {
+    /// use mockito::ContentEncoding;
+    ///
+    /// let mut s = mockito::Server::new();
+    ///
+    /// s.mock("GET", "/").with_body_encoded(ContentEncoding::Gzip, "hello world");
+    /// # }
+    /// ```
+    ///
+    #[track_caller]
+    pub fn with_body_encoded<StrOrBytes: AsRef<[u8]>>(
+        mut self,
+        encoding: ContentEncoding,
+        body: StrOrBytes,
+    ) -> Self {
+        let encoded = encoding
+            .encode(body.as_ref())
+            .map_err(|err| Error::new_with_context(ErrorKind::ResponseBodyFailure, err))
+            .unwrap();
+
+        self.inner.response.headers.insert(
+            http::header::CONTENT_ENCODING,
+            Header::String(encoding.name().to_string()),
+        );
+        self.inner.response.body = Body::Bytes(Bytes::from(encoded));
+        self
+    }
+
+    ///
+    /// Compresses the body of the mock response according to the `Accept-Encoding` header of
+    /// every request, like a real server would. The supported encoding with the highest
+    /// quality value is picked (`br` requires the `brotli` feature) and the `Content-Encoding`
+    /// header is set accordingly. If none of them is acceptable, the body is sent uncompressed.
+    ///
+    /// Only applies to bodies set with `Mock::with_body`, `Mock::with_lazy_body` or
+    /// the body sequences, and not to mocks with a `Content-Encoding` header.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// s.mock("GET", "/").with_body("hello world").with_auto_encoding();
+    /// ```
+    ///
+    pub fn with_auto_encoding(mut self) -> Self {
+        self.inner.response.auto_encoding = true;
+        self
+    }
+
+    ///
+    /// Sets a sequence of bodies for the mock response: every request receives the next body
+    /// in the sequence, while the last body is repeated once the sequence is exhausted.
+    ///
+    /// Use `Mock::with_cycling_body_sequence` to start over from the first body instead.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// // Responds with "pending", then "running" and then "done" for all subsequent requests
+    /// s.mock("GET", "/job")
+    ///   .with_body_sequence(vec!["pending", "running", "done"])
+    ///   .expect_at_least(1)
+    ///   .create();
+    /// ```
+    ///
+    pub fn with_body_sequence<StrOrBytes: AsRef<[u8]>>(mut self, bodies: Vec<StrOrBytes>) -> Self {
+        let bodies = bodies
+            .iter()
+            .map(|body| Bytes::from(body.as_ref().to_owned()))
+            .collect();
+        self.inner.response.body = Body::Sequence(Arc::new(BodySequence::new(bodies, false)));
+        self
+    }
+
+    ///
+    /// Same as `Mock::with_body_sequence`, but starts over from the first body once the
+    /// sequence is exhausted.
+    ///
+    pub fn with_cycling_body_sequence<StrOrBytes: AsRef<[u8]>>(
+        mut self,
+        bodies: Vec<StrOrBytes>,
+    ) -> Self {
+        let bodies = bodies
+            .iter()
+            .map(|body| Bytes::from(body.as_ref().to_owned()))
+            .collect();
+        self.inner.response.body = Body::Sequence(Arc::new(BodySequence::new(bodies, true)));
+        self
+    }
+
+    ///
+    /// Sets the body of the mock response dynamically. The response will use chunked transfer encoding.
+    ///
+    /// The callback function will be called only once. You can sleep in between calls to the
+    /// writer to simulate delays between the chunks. The callback function can also return an
+    /// error after any number of writes in order to abort the response.
+    ///
+    /// The function must be thread-safe. If it's a closure, it can't be borrowing its context.
+    /// Use `move` closures and `Arc` to share any data.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// s.mock("GET", "/").with_chunked_body(|w| w.write_all(b"hello world"));
     /// ```
     ///
     pub fn with_chunked_body(
@@ -454,69 +1350,373 @@ impl Mock {
     }
 
     ///
-    /// **DEPRECATED:** Replaced by `Mock::with_chunked_body`.
+    /// Sets the body of the mock response to the contents of the given reader, e.g. an
+    /// in-memory cursor or a pipe. The response will use chunked transfer encoding.
+    ///
+    /// The reader is streamed out without being read into memory first. Since it can only be
+    /// consumed once, the first matching request receives its contents and any subsequent
+    /// requests receive an empty body.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    ///
+    /// let mut s = mockito::Server::new();
+    ///
+    /// s.mock("GET", "/").with_body_from_reader(Cursor::new(b"hello world".to_vec()));
+    /// ```
+    ///
+    pub fn with_body_from_reader(self, reader: impl io::Read + Send + 'static) -> Self {
+        let reader = Mutex::new(Some(reader));
+
+        self.with_chunked_body(move |writer| match reader.lock().unwrap().take() {
+            Some(mut reader) => io::copy(&mut reader, writer).map(|_| ()),
+            None => Ok(()),
+        })
+    }
+
+    ///
+    /// Sets the body of the mock response to the given chunks, sent using chunked transfer
+    /// encoding. A chunk is only handed over to the connection once the previous one was
+    /// written out, so a client that reads slowly holds back the rest of the body.
+    ///
+    /// Use large chunks (larger than the socket buffers) to make the pacing observable and
+    /// `Mock::chunks_sent_at` to inspect it.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// s.mock("GET", "/").with_backpressure_body(vec![vec![b'a'; 1 << 20]; 4]);
+    /// ```
+    ///
+    pub fn with_backpressure_body<StrOrBytes: AsRef<[u8]>>(
+        mut self,
+        chunks: Vec<StrOrBytes>,
+    ) -> Self {
+        let chunks = chunks
+            .into_iter()
+            .map(|chunk| Bytes::from(chunk.as_ref().to_owned()))
+            .collect();
+        self.inner.response.body = Body::Backpressure(Arc::new(BackpressureBody::new(chunks)));
+        self
+    }
+
+    ///
+    /// Returns the moments at which the chunks of the last response were handed over to the
+    /// connection. Only available for bodies set via `Mock::with_backpressure_body`, otherwise
+    /// the result is empty.
+    ///
+    pub fn chunks_sent_at(&self) -> Vec<Instant> {
+        match self.inner.response.body {
+            Body::Backpressure(ref backpressure) => backpressure.sent_at(),
+            _ => vec![],
+        }
+    }
+
+    ///
+    /// **DEPRECATED:** Replaced by `Mock::with_chunked_body`.
+    ///
+    #[deprecated(since = "1.0.0", note = "Use `Mock::with_chunked_body` instead")]
+    pub fn with_body_from_fn(
+        self,
+        callback: impl Fn(&mut dyn io::Write) -> io::Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.with_chunked_body(callback)
+    }
+
+    ///
+    /// Sets the body of the mock response dynamically while exposing the request object.
+    ///
+    /// You can use this method to provide a custom reponse body for every incoming request.
+    ///
+    /// The function must be thread-safe. If it's a closure, it can't be borrowing its context.
+    /// Use `move` closures and `Arc` to share any data.
+    ///
+    /// ### Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// let _m = s.mock("GET", mockito::Matcher::Any).with_body_from_request(|request| {
+    ///     if request.path() == "/bob" {
+    ///         "hello bob".into()
+    ///     } else if request.path() == "/alice" {
+    ///         "hello alice".into()
+    ///     } else {
+    ///         "hello world".into()
+    ///     }
+    /// });
+    /// ```
+    ///
+    pub fn with_body_from_request(
+        mut self,
+        callback: impl Fn(&Request) -> Vec<u8> + Send + Sync + 'static,
+    ) -> Self {
+        self.inner.response.body =
+            Body::FnWithRequest(Arc::new(move |req| Bytes::from(callback(req))));
+        self
+    }
+
+    ///
+    /// Same as `Mock::with_body_from_request`, but the callback returns a future which resolves
+    /// to the body, for responses that depend on async work.
+    ///
+    /// The callback is called with the matching request and has to be `Send + Sync + 'static`,
+    /// since the mock is shared with the server thread. The future it returns can't borrow the
+    /// request, so copy whatever it needs out of the request first. It has to be
+    /// `Send + 'static` and is awaited on the server runtime while the response is being sent,
+    /// which means the body is always sent with `Transfer-Encoding: chunked`.
+    ///
+    /// ### Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// let _m = s
+    ///     .mock("GET", mockito::Matcher::Any)
+    ///     .with_body_from_request_async(|request| {
+    ///         let path = request.path().to_string();
+    ///         async move { format!("hello {}", path).into_bytes() }
+    ///     });
+    /// ```
+    ///
+    pub fn with_body_from_request_async<F, Fut>(mut self, callback: F) -> Self
+    where
+        F: Fn(&Request) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Vec<u8>> + Send + 'static,
+    {
+        self.inner.response.body = Body::FnWithRequestAsync(Arc::new(move |req| {
+            let future = callback(req);
+            Box::pin(async move { Bytes::from(future.await) })
+        }));
+        self
+    }
+
+    ///
+    /// Sets the body of the mock response lazily. Its `Content-Length` is handled automatically.
+    ///
+    /// The callback function is called only once, when the first matching request is received.
+    /// The resulting body is cached and reused for all subsequent requests, which makes this
+    /// method a good fit for bodies that are expensive to compute but never change.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// s.mock("GET", "/").with_lazy_body(|| "hello world".repeat(1000).into_bytes());
+    /// ```
+    ///
+    pub fn with_lazy_body(mut self, callback: impl FnOnce() -> Vec<u8> + Send + 'static) -> Self {
+        self.inner.response.body = Body::Lazy(Arc::new(LazyBody::new(Box::new(callback))));
+        self
+    }
+
+    ///
+    /// Sets the body of the mock response from the contents of a file stored under `path`.
+    /// Its `Content-Length` is handled automatically.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// s.mock("GET", "/").with_body_from_file("tests/files/simple.http");
+    /// ```
+    ///
+    #[track_caller]
+    pub fn with_body_from_file(mut self, path: impl AsRef<Path>) -> Self {
+        self.inner.response.body = Body::Bytes(
+            std::fs::read(path)
+                .map_err(|_| Error::new(ErrorKind::FileNotFound))
+                .unwrap()
+                .into(),
+        );
+        self
+    }
+
+    ///
+    /// Same as `Mock::with_body_from_file`, but also sets the `Content-Type` header based on
+    /// the file extension (e.g. `application/json` for `.json` files), unless a content type
+    /// was already set on the mock. Unknown extensions fall back to `application/octet-stream`.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// // Responds with `content-type: image/png`
+    /// s.mock("GET", "/").with_body_from_file_typed("tests/files/pixel.png");
+    /// ```
+    ///
+    #[track_caller]
+    pub fn with_body_from_file_typed(mut self, path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        self = self.with_body_from_file(path);
+
+        if !self.inner.response.headers.contains_key(CONTENT_TYPE) {
+            self.inner.response.headers.insert(
+                CONTENT_TYPE,
+                Header::String(mime_type_for(path).to_string()),
+            );
+        }
+
+        self
+    }
+
+    ///
+    /// Sets the body of the mock response to the contents of a file stored under `path`,
+    /// without reading the whole file into memory.
     ///
-    #[deprecated(since = "1.0.0", note = "Use `Mock::with_chunked_body` instead")]
-    pub fn with_body_from_fn(
-        self,
-        callback: impl Fn(&mut dyn io::Write) -> io::Result<()> + Send + Sync + 'static,
-    ) -> Self {
-        self.with_chunked_body(callback)
+    /// Unlike `Mock::with_body_from_file`, the file is read in chunks for every matching request,
+    /// which makes this method suitable for large fixtures. The `Content-Length` is taken from
+    /// the file metadata.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// s.mock("GET", "/").with_body_from_file_stream("tests/files/simple.http");
+    /// ```
+    ///
+    #[track_caller]
+    pub fn with_body_from_file_stream(mut self, path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        std::fs::metadata(path)
+            .map_err(|_| Error::new(ErrorKind::FileNotFound))
+            .unwrap();
+
+        self.inner.response.body = Body::File(path.to_path_buf());
+        self
     }
 
     ///
-    /// Sets the body of the mock response dynamically while exposing the request object.
+    /// Resets the connection (by sending a TCP `RST`) as soon as a matching request is received,
+    /// without sending a response. Use this to test how clients handle connections dropped by
+    /// the server, e.g. by a firewall.
     ///
-    /// You can use this method to provide a custom reponse body for every incoming request.
+    /// Only requests matching this mock are affected, so you can reset the connections to some
+    /// endpoints while serving others normally.
     ///
-    /// The function must be thread-safe. If it's a closure, it can't be borrowing its context.
-    /// Use `move` closures and `Arc` to share any data.
+    /// Note that this bypasses the normal response building: any status, headers or body
+    /// defined on the mock are ignored.
     ///
-    /// ### Example
+    /// ## Example
     ///
     /// ```
     /// let mut s = mockito::Server::new();
     ///
-    /// let _m = s.mock("GET", mockito::Matcher::Any).with_body_from_request(|request| {
-    ///     if request.path() == "/bob" {
-    ///         "hello bob".into()
-    ///     } else if request.path() == "/alice" {
-    ///         "hello alice".into()
-    ///     } else {
-    ///         "hello world".into()
-    ///     }
-    /// });
+    /// // Requests to /blocked will be reset, requests to /ok will succeed
+    /// s.mock("GET", "/blocked").with_connection_reset().create();
+    /// s.mock("GET", "/ok").create();
     /// ```
     ///
-    pub fn with_body_from_request(
+    pub fn with_connection_reset(mut self) -> Self {
+        self.inner.response.reset_connection = true;
+        self
+    }
+
+    ///
+    /// Sends the status, headers and the given partial body using chunked transfer encoding,
+    /// but never completes the response. When `then_close` is `true`, the connection is closed
+    /// right after the partial body was sent, otherwise the response stalls until the client
+    /// hangs up.
+    ///
+    /// Use this to test how clients handle responses interrupted midway.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// s.mock("GET", "/").with_partial_body("hello wor", true);
+    /// ```
+    ///
+    pub fn with_partial_body<StrOrBytes: AsRef<[u8]>>(
         mut self,
-        callback: impl Fn(&Request) -> Vec<u8> + Send + Sync + 'static,
+        body: StrOrBytes,
+        then_close: bool,
     ) -> Self {
-        self.inner.response.body =
-            Body::FnWithRequest(Arc::new(move |req| Bytes::from(callback(req))));
+        self.inner.response.body = Body::Partial(Bytes::from(body.as_ref().to_owned()), then_close);
         self
     }
 
     ///
-    /// Sets the body of the mock response from the contents of a file stored under `path`.
-    /// Its `Content-Length` is handled automatically.
+    /// Sends the status line and headers right away, but holds back the body for the given
+    /// `delay`. The rest of the body follows without further delays.
+    ///
+    /// Use this to test read timeouts that apply to the response body specifically.
     ///
     /// ## Example
     ///
     /// ```
+    /// use std::time::Duration;
+    ///
     /// let mut s = mockito::Server::new();
     ///
-    /// s.mock("GET", "/").with_body_from_file("tests/files/simple.http");
+    /// s.mock("GET", "/")
+    ///   .with_body("hello world")
+    ///   .with_first_byte_delay(Duration::from_millis(500));
     /// ```
     ///
-    #[track_caller]
-    pub fn with_body_from_file(mut self, path: impl AsRef<Path>) -> Self {
-        self.inner.response.body = Body::Bytes(
-            std::fs::read(path)
-                .map_err(|_| Error::new(ErrorKind::FileNotFound))
-                .unwrap()
-                .into(),
-        );
+    pub fn with_first_byte_delay(mut self, delay: Duration) -> Self {
+        self.inner.response.first_byte_delay = Some(delay);
+        self
+    }
+
+    ///
+    /// Sends the response body at roughly the given amount of bytes per second, in order to
+    /// simulate a slow network, e.g. for testing progress bars or download timeouts.
+    ///
+    /// The body is split into small chunks which are paced out over time, so this forces
+    /// chunked transfer encoding for bodies that would otherwise be sent with a
+    /// `Content-Length` header.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// // takes about 2 seconds to download
+    /// s.mock("GET", "/")
+    ///   .with_body(vec![0; 100 * 1024])
+    ///   .with_throttle(50 * 1024);
+    /// ```
+    ///
+    pub fn with_throttle(mut self, bytes_per_second: usize) -> Self {
+        self.inner.response.throttle = Some(bytes_per_second);
+        self
+    }
+
+    ///
+    /// Forces the framing of the response body, regardless of how the body was defined.
+    ///
+    /// By default, static bodies are sent along with a `Content-Length` header, while bodies
+    /// defined via `Mock::with_chunked_body` use chunked transfer encoding.
+    /// `TransferEncoding::Identity` buffers chunked bodies before sending them, while
+    /// `TransferEncoding::Chunked` sends static bodies in chunks.
+    ///
+    /// Note that this has no effect on HTTP/2 requests, which don't support transfer encodings.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use mockito::TransferEncoding;
+    ///
+    /// let mut s = mockito::Server::new();
+    ///
+    /// s.mock("GET", "/")
+    ///   .with_body("hello world")
+    ///   .with_transfer_encoding(TransferEncoding::Chunked)
+    ///   .create();
+    /// ```
+    ///
+    pub fn with_transfer_encoding(mut self, transfer_encoding: TransferEncoding) -> Self {
+        self.inner.response.transfer_encoding = Some(transfer_encoding);
         self
     }
 
@@ -532,6 +1732,18 @@ impl Mock {
         self
     }
 
+    ///
+    /// Sets the expected amount of requests to `target`, allowing `tolerance` requests more
+    /// or less. E.g. `expect_approx(5, 1)` accepts between 4 and 6 requests.
+    /// This is only enforced when calling the `assert` method.
+    ///
+    #[allow(clippy::missing_const_for_fn)]
+    pub fn expect_approx(mut self, target: usize, tolerance: usize) -> Self {
+        self.inner.expected_hits_at_least = Some(target.saturating_sub(tolerance));
+        self.inner.expected_hits_at_most = Some(target.saturating_add(tolerance));
+        self
+    }
+
     ///
     /// Sets the minimum amount of requests that this mock is supposed to receive.
     /// This is only enforced when calling the `assert` method.
@@ -565,44 +1777,259 @@ impl Mock {
     ///
     #[track_caller]
     pub fn assert(&self) {
+        if let Err(err) = self.try_assert() {
+            panic!("{}", err)
+        }
+    }
+
+    ///
+    /// Same as `Mock::assert` but async.
+    ///
+    pub async fn assert_async(&self) {
+        if let Err(err) = self.try_assert_async().await {
+            panic!("{}", err)
+        }
+    }
+
+    ///
+    /// Same as `Mock::assert` but returns the failed assertion as an error instead of panicking.
+    /// The error can be inspected or formatted into the usual assertion message.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// let mock = s.mock("GET", "/").create();
+    ///
+    /// if let Err(err) = mock.try_assert() {
+    ///     assert_eq!(Some(0), err.actual_hits);
+    ///     println!("{}", err);
+    /// }
+    /// ```
+    ///
+    pub fn try_assert(&self) -> Result<(), AssertError> {
         let mutex = self.state.clone();
         let state = mutex.read().unwrap();
-        if let Some(hits) = state.get_mock_hits(self.inner.id.clone()) {
-            let matched = self.matched_hits(hits);
-            let message = if !matched {
-                let last_request = state.get_last_unmatched_request();
-                self.build_assert_message(hits, last_request)
-            } else {
-                String::default()
-            };
+        let hits = state.get_mock_hits(self.inner.id.clone());
 
-            assert!(matched, "{}", message)
-        } else {
-            panic!("could not retrieve enough information about the remote mock")
+        if hits.is_some_and(|hits| self.matched_hits(hits)) {
+            return Ok(());
         }
+
+        Err(AssertError {
+            expected_min: self.inner.expected_hits_at_least,
+            expected_max: self.inner.expected_hits_at_most,
+            actual_hits: hits,
+            mock: self.to_string(),
+            last_unmatched_request: state.get_last_unmatched_request().map(Request::formatted),
+            last_unmatched_request_curl: state
+                .get_last_unmatched_request()
+                .filter(|_| state.assert_with_curl)
+                .map(Request::as_curl),
+        })
     }
 
     ///
-    /// Same as `Mock::assert` but async.
+    /// Same as `Mock::try_assert` but async.
     ///
-    pub async fn assert_async(&self) {
+    pub async fn try_assert_async(&self) -> Result<(), AssertError> {
+        self.try_assert()
+    }
+
+    ///
+    /// Asserts that the last response sent by this mock had the given status code and a body
+    /// matching the given matcher. Useful for verifying dynamic responses, e.g. the ones
+    /// defined with `Mock::with_body_from_request`.
+    ///
+    /// Bodies which are streamed (e.g. `Mock::with_chunked_body`) are not recorded and will
+    /// make this assertion fail.
+    ///
+    /// ## Example
+    ///
+    /// ```no_run
+    /// use mockito::Matcher;
+    ///
+    /// let mut s = mockito::Server::new();
+    ///
+    /// let m = s
+    ///     .mock("GET", Matcher::Any)
+    ///     .with_body_from_request(|request| format!("hello {}", request.path()).into())
+    ///     .create();
+    ///
+    /// // Will panic unless the mock responded with a 200 and a matching body
+    /// m.assert_last_response(200, Matcher::Regex("^hello /".to_string()));
+    /// ```
+    ///
+    #[track_caller]
+    pub fn assert_last_response<M: Into<Matcher>>(&self, status: usize, body: M) {
+        let body = body.into();
         let mutex = self.state.clone();
         let state = mutex.read().unwrap();
-        if let Some(hits) = state.get_mock_hits(self.inner.id.clone()) {
-            let matched = self.matched_hits(hits);
-            let message = if !matched {
-                let last_request = state.get_last_unmatched_request();
-                self.build_assert_message(hits, last_request)
-            } else {
-                String::default()
-            };
+        let max_depth = state.max_matcher_depth;
+
+        let Some(response) = state.get_last_response(&self.inner.id) else {
+            panic!(
+                "\n> Expected the mock to have sent a response:\n{}\n...but it sent none\n",
+                self
+            );
+        };
+
+        if response.status.as_u16() as usize != status {
+            panic!(
+                "\n> Expected the last response of the mock to have the status {}:\n{}\n...but it had the status {}\n",
+                status, self, response.status
+            );
+        }
+
+        let Some(ref sent_body) = response.body else {
+            panic!(
+                "\n> Expected the last response of the mock to have a body matching {}:\n{}\n...but the body was streamed\n",
+                body, self
+            );
+        };
+
+        if !body.matches_body(sent_body, max_depth) {
+            panic!(
+                "\n> Expected the last response of the mock to have a body matching {}:\n{}\n...but it had the body:\n{}\n",
+                body,
+                self,
+                String::from_utf8_lossy(sent_body)
+            );
+        }
+    }
+
+    ///
+    /// Returns a copy of the last request matched by this mock, including its body, or `None`
+    /// if the mock hasn't matched any request yet.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// let m = s.mock("POST", "/").create();
+    ///
+    /// assert!(m.last_request().is_none());
+    /// ```
+    ///
+    pub fn last_request(&self) -> Option<Request> {
+        let mutex = self.state.clone();
+        let state = mutex.read().unwrap();
+
+        state.get_last_request(&self.inner.id).cloned()
+    }
+
+    ///
+    /// Asserts that the last request matched by this mock had a body matching the given
+    /// matcher. Pass a string to check the exact body.
+    ///
+    /// ## Example
+    ///
+    /// ```no_run
+    /// let mut s = mockito::Server::new();
+    ///
+    /// let m = s.mock("POST", "/").create();
+    ///
+    /// // Will panic unless the last matched request had the body `hello`
+    /// m.assert_last_request_body("hello");
+    /// ```
+    ///
+    #[track_caller]
+    pub fn assert_last_request_body<M: Into<Matcher>>(&self, body: M) {
+        let body = body.into();
+        let Some(request) = self.last_request() else {
+            panic!(
+                "\n> Expected the mock to have matched a request:\n{}\n...but it matched none\n",
+                self
+            );
+        };
+
+        let received = request.decoded_body().unwrap_or_default();
+        let max_depth = self.state.read().unwrap().max_matcher_depth;
+        if !body.matches_body(&received, max_depth) {
+            panic!(
+                "\n> Expected the last request matched by the mock to have a body matching {}:\n{}\n...but it had the body:\n{}\n",
+                body,
+                self,
+                String::from_utf8_lossy(&received)
+            );
+        }
+    }
+
+    ///
+    /// Asserts that the given headers appeared in the last request matched by this mock in the
+    /// given relative order. Other headers may appear in between. Header names are compared
+    /// case-insensitively and repeated headers count by their first occurrence.
+    ///
+    /// ## Example
+    ///
+    /// ```no_run
+    /// let mut s = mockito::Server::new();
+    ///
+    /// let m = s.mock("GET", "/").create();
+    ///
+    /// // Will panic unless `date` was sent before `authorization`
+    /// m.assert_request_header_order(&["date", "authorization"]);
+    /// ```
+    ///
+    #[track_caller]
+    pub fn assert_request_header_order(&self, names: &[&str]) {
+        let mutex = self.state.clone();
+        let state = mutex.read().unwrap();
+
+        let Some(request) = state.get_last_request(&self.inner.id) else {
+            panic!(
+                "\n> Expected the mock to have matched a request:\n{}\n...but it matched none\n",
+                self
+            );
+        };
+
+        let received = request.header_names();
+        let mut last_position = None;
+        for name in names {
+            let position = received
+                .iter()
+                .position(|received| received.eq_ignore_ascii_case(name));
+
+            if position.is_none() || position < last_position {
+                panic!(
+                    "\n> Expected the last request matched by the mock to have the headers {:?} in this order:\n{}\n...but it had the headers {:?}\n",
+                    names, self, received
+                );
+            }
 
-            assert!(matched, "{}", message)
-        } else {
-            panic!("could not retrieve enough information about the remote mock")
+            last_position = position;
         }
     }
 
+    ///
+    /// Returns whether the given request body would be matched by the body matchers of this
+    /// mock (`Mock::match_body` and `Mock::match_body_captures`). Useful for validating the
+    /// matcher configuration without sending any requests.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use mockito::Matcher;
+    /// use serde_json::json;
+    ///
+    /// let mut s = mockito::Server::new();
+    ///
+    /// let m = s
+    ///     .mock("POST", "/")
+    ///     .match_body(Matcher::PartialJson(json!({"hello": "world"})));
+    ///
+    /// assert!(m.would_body_match(br#"{"hello": "world", "foo": "bar"}"#));
+    /// assert!(!m.would_body_match(br#"{"hello": "you"}"#));
+    /// assert!(!m.would_body_match(b"not json"));
+    /// ```
+    ///
+    pub fn would_body_match(&self, body: &[u8]) -> bool {
+        let max_depth = self.state.read().unwrap().max_matcher_depth;
+        self.inner.body_matches(body, max_depth)
+    }
+
     ///
     /// Returns whether the expected amount of requests (defaults to 1) were performed.
     ///
@@ -629,6 +2056,27 @@ impl Mock {
         self.matched_hits(hits)
     }
 
+    ///
+    /// Makes the mock stop matching requests once the given `duration` has elapsed since it
+    /// was created. After that, the mock behaves as if it was removed from the server.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// let mut s = mockito::Server::new();
+    ///
+    /// s.mock("GET", "/token")
+    ///   .expire_after(Duration::from_secs(30))
+    ///   .create();
+    /// ```
+    ///
+    pub fn expire_after(mut self, duration: Duration) -> Self {
+        self.inner.expire_after = Some(duration);
+        self
+    }
+
     ///
     /// Registers the mock to the server - your mock will be served only after calling this method.
     ///
@@ -641,9 +2089,11 @@ impl Mock {
     /// ```
     ///
     pub fn create(mut self) -> Mock {
+        self.inner.created_at = Some(Instant::now());
         let remote_mock = RemoteMock::new(self.inner.clone());
         let state = self.state.clone();
         let mut state = state.write().unwrap();
+        self.warn_on_duplicate(&state);
         state.mocks.push(remote_mock);
 
         self.created = true;
@@ -655,9 +2105,11 @@ impl Mock {
     /// Same as `Mock::create` but async.
     ///
     pub async fn create_async(mut self) -> Mock {
+        self.inner.created_at = Some(Instant::now());
         let remote_mock = RemoteMock::new(self.inner.clone());
         let state = self.state.clone();
         let mut state = state.write().unwrap();
+        self.warn_on_duplicate(&state);
         state.mocks.push(remote_mock);
 
         self.created = true;
@@ -683,6 +2135,10 @@ impl Mock {
         state.remove_mock(self.inner.id.clone());
     }
 
+    pub(crate) fn id(&self) -> &str {
+        &self.inner.id
+    }
+
     fn matched_hits(&self, hits: usize) -> bool {
         match (
             self.inner.expected_hits_at_least,
@@ -694,45 +2150,24 @@ impl Mock {
             (None, None) => hits == 1,
         }
     }
+}
 
-    fn build_assert_message(&self, hits: usize, last_request: Option<String>) -> String {
-        let mut message = match (
-            self.inner.expected_hits_at_least,
-            self.inner.expected_hits_at_most,
-        ) {
-            (Some(min), Some(max)) if min == max => format!(
-                "\n> Expected {} request(s) to:\n{}\n...but received {}\n\n",
-                min, self, hits
-            ),
-            (Some(min), Some(max)) => format!(
-                "\n> Expected between {} and {} request(s) to:\n{}\n...but received {}\n\n",
-                min, max, self, hits
-            ),
-            (Some(min), None) => format!(
-                "\n> Expected at least {} request(s) to:\n{}\n...but received {}\n\n",
-                min, self, hits
-            ),
-            (None, Some(max)) => format!(
-                "\n> Expected at most {} request(s) to:\n{}\n...but received {}\n\n",
-                max, self, hits
-            ),
-            (None, None) => format!(
-                "\n> Expected 1 request(s) to:\n{}\n...but received {}\n\n",
-                self, hits
-            ),
-        };
-
-        if let Some(last_request) = last_request {
-            message.push_str(&format!(
-                "> The last unmatched request was:\n{}\n",
-                last_request
-            ));
-
-            let difference = diff::compare(&self.to_string(), &last_request);
-            message.push_str(&format!("> Difference:\n{}\n", difference));
+impl Mock {
+    fn warn_on_duplicate(&self, state: &State) {
+        if !state.warn_on_duplicate_mocks {
+            return;
         }
 
-        message
+        if state
+            .mocks
+            .iter()
+            .any(|mock| mock.inner.matchers_eq(&self.inner))
+        {
+            log::warn!(
+                "Duplicate mock, an existing mock has the same matchers {}",
+                self
+            );
+        }
     }
 }
 
@@ -1,27 +1,97 @@
 use crate::{Error, ErrorKind};
-use http::header::{AsHeaderName, HeaderValue};
-use http::Request as HttpRequest;
+#[cfg(feature = "gzip")]
+use flate2::read::GzDecoder;
+#[cfg(feature = "deflate")]
+use flate2::read::{DeflateDecoder, ZlibDecoder};
+use http::header::{AsHeaderName, HeaderValue, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE};
+use http::{Request as HttpRequest, Uri, Version};
 use http_body_util::BodyExt;
 use hyper::body::Incoming;
+use hyper::upgrade::OnUpgrade;
 use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io;
+#[cfg(any(feature = "gzip", feature = "deflate"))]
+use std::io::Read;
+use std::net::SocketAddr;
+use std::time::Duration;
 
 ///
 /// Stores a HTTP request
 ///
 #[derive(Debug)]
 pub struct Request {
-    inner: HttpRequest<Incoming>,
+    inner: HttpRequest<()>,
+    incoming: Option<Incoming>,
     body: Option<Vec<u8>>,
+    body_decoded: bool,
+    local_addr: Option<SocketAddr>,
+    remote_addr: Option<SocketAddr>,
+    pub(crate) connection_request_index: usize,
+    pub(crate) elapsed_since_connect: Duration,
+    pub(crate) path_params: HashMap<String, String>,
+}
+
+impl Clone for Request {
+    /// Copies the request line, headers and the body read so far. The body is not read
+    /// anymore and the connection can't be upgraded from the copy.
+    fn clone(&self) -> Self {
+        let mut inner = HttpRequest::new(());
+        *inner.method_mut() = self.inner.method().clone();
+        *inner.uri_mut() = self.inner.uri().clone();
+        *inner.version_mut() = self.inner.version();
+        *inner.headers_mut() = self.inner.headers().clone();
+
+        Request {
+            inner,
+            incoming: None,
+            body: self.body.clone(),
+            body_decoded: self.body_decoded,
+            local_addr: self.local_addr,
+            remote_addr: self.remote_addr,
+            connection_request_index: self.connection_request_index,
+            elapsed_since_connect: self.elapsed_since_connect,
+            path_params: self.path_params.clone(),
+        }
+    }
 }
 
 impl Request {
-    pub(crate) fn new(request: HttpRequest<Incoming>) -> Self {
+    pub(crate) fn new(
+        request: HttpRequest<Incoming>,
+        local_addr: Option<SocketAddr>,
+        remote_addr: Option<SocketAddr>,
+    ) -> Self {
+        let (parts, incoming) = request.into_parts();
+
         Request {
-            inner: request,
+            inner: HttpRequest::from_parts(parts, ()),
+            incoming: Some(incoming),
             body: None,
+            body_decoded: false,
+            local_addr,
+            remote_addr,
+            connection_request_index: 0,
+            elapsed_since_connect: Duration::ZERO,
+            path_params: HashMap::new(),
         }
     }
 
+    /// The HTTP version
+    pub(crate) fn version(&self) -> Version {
+        self.inner.version()
+    }
+
+    /// Takes over the connection once the response was sent, e.g. for WebSocket upgrades
+    pub(crate) fn upgrade(&mut self) -> OnUpgrade {
+        hyper::upgrade::on(&mut self.inner)
+    }
+
+    /// Whether the request was sent over HTTP/2
+    pub fn is_http2(&self) -> bool {
+        self.version() == Version::HTTP_2
+    }
+
     /// The HTTP method
     pub fn method(&self) -> &str {
         self.inner.method().as_ref()
@@ -41,6 +111,26 @@ impl Request {
             .unwrap_or("")
     }
 
+    /// The path segment captured by the `{name}` placeholder of a `Matcher::PathTemplate`,
+    /// once the request was matched by a mock
+    pub fn path_param(&self, name: &str) -> Option<&str> {
+        self.path_params.get(name).map(String::as_str)
+    }
+
+    /// Replaces the path and query of the request, e.g. within `Server::with_request_rewriter`
+    pub fn set_path_and_query(&mut self, path_and_query: &str) -> Result<(), Error> {
+        let mut parts = self.inner.uri().clone().into_parts();
+        parts.path_and_query = Some(
+            path_and_query
+                .parse()
+                .map_err(|err| Error::new_with_context(ErrorKind::InvalidPath, err))?,
+        );
+        *self.inner.uri_mut() = Uri::from_parts(parts)
+            .map_err(|err| Error::new_with_context(ErrorKind::InvalidPath, err))?;
+
+        Ok(())
+    }
+
     /// Retrieves all the header values for the given header field name
     pub fn header<T: AsHeaderName>(&self, header_name: T) -> Vec<&HeaderValue> {
         self.inner.headers().get_all(header_name).iter().collect()
@@ -51,8 +141,63 @@ impl Request {
         self.inner.headers().contains_key(header_name)
     }
 
+    /// The value of the `content-type` header, unless it's missing or not valid UTF-8
+    pub fn content_type(&self) -> Option<&str> {
+        self.inner
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+    }
+
+    /// The value of the `content-length` header, unless it's missing or not a valid number
+    pub fn content_length(&self) -> Option<u64> {
+        self.inner
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse().ok())
+    }
+
+    /// The number of headers, counting repeated headers once per value
+    pub(crate) fn header_count(&self) -> usize {
+        self.inner.headers().len()
+    }
+
+    /// The total size of the header names and values, in bytes
+    pub(crate) fn header_bytes(&self) -> usize {
+        self.inner
+            .headers()
+            .iter()
+            .map(|(name, value)| name.as_str().len() + value.len())
+            .sum()
+    }
+
+    /// The header names, in the order in which they were first received
+    pub(crate) fn header_names(&self) -> Vec<&str> {
+        self.inner
+            .headers()
+            .keys()
+            .map(|name| name.as_str())
+            .collect()
+    }
+
+    /// The address of the mock server the request was received on
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    /// The address of the client which sent the request
+    pub fn remote_addr(&self) -> Option<SocketAddr> {
+        self.remote_addr
+    }
+
+    /// The time elapsed between accepting the connection and fully reading the request
+    pub fn elapsed_since_connect(&self) -> Duration {
+        self.elapsed_since_connect
+    }
+
     /// Returns the request body or an error, if the body hasn't been read
-    /// yet.
+    /// yet. The body is decompressed when `ServerOpts::decode_request_body` is enabled.
     pub fn body(&self) -> Result<&Vec<u8>, Error> {
         self.body
             .as_ref()
@@ -67,9 +212,7 @@ impl Request {
 
     /// Reads the body (if it hasn't been read already) and returns it
     pub(crate) async fn read_body(&mut self) -> &Vec<u8> {
-        if self.body.is_none() {
-            let raw_body = self.inner.body_mut();
-
+        if let Some(raw_body) = self.incoming.take() {
             let bytes = raw_body
                 .collect()
                 .await
@@ -83,6 +226,82 @@ impl Request {
         self.body.as_ref().unwrap()
     }
 
+    /// Checks whether both requests have the same method, path, query, headers and body
+    pub(crate) fn is_identical(&self, other: &Request) -> bool {
+        self.inner.method() == other.inner.method()
+            && self.inner.uri() == other.inner.uri()
+            && self.inner.headers() == other.inner.headers()
+            && self.body == other.body
+    }
+
+    /// The value of the `content-encoding` header, normalized to lowercase
+    pub(crate) fn content_encoding(&self) -> Option<String> {
+        self.inner
+            .headers()
+            .get(CONTENT_ENCODING)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.trim().to_ascii_lowercase())
+    }
+
+    /// Decompresses the body according to the `content-encoding` header. Bodies that can't
+    /// be decompressed are left untouched.
+    pub(crate) fn decode_body(&mut self) {
+        let Some(body) = self.body.as_ref() else {
+            return;
+        };
+        let Some(encoding) = self.content_encoding() else {
+            return;
+        };
+
+        match decode(&encoding, body) {
+            Some(Ok(decoded)) => {
+                self.body = Some(decoded);
+                self.body_decoded = true;
+            }
+            Some(Err(err)) => log::warn!("Could not decode the request body: {}", err),
+            None => {}
+        }
+    }
+
+    /// Returns the body decompressed according to the `content-encoding` header, or `None`
+    /// if the body can't be decompressed
+    pub(crate) fn decoded_body(&self) -> Option<Cow<'_, [u8]>> {
+        let body = self.body.as_ref()?;
+        if self.body_decoded {
+            return Some(Cow::Borrowed(body));
+        }
+
+        match self.content_encoding().as_deref() {
+            None | Some("identity") => Some(Cow::Borrowed(body)),
+            Some(encoding) => decode(encoding, body)?.ok().map(Cow::Owned),
+        }
+    }
+
+    ///
+    /// Renders the request as a `curl` command, which is handy for replaying the exact request
+    /// a client sent. The URL is reconstructed from the address of the server.
+    ///
+    pub fn as_curl(&self) -> String {
+        let url = match self.local_addr {
+            Some(address) => format!("http://{}{}", address, self.path_and_query()),
+            None => self.path_and_query().to_string(),
+        };
+        let mut curl = format!("curl -X {} {}", self.method(), shell_quote(&url));
+
+        for (name, value) in self.inner.headers() {
+            let header = format!("{}: {}", name, String::from_utf8_lossy(value.as_bytes()));
+            curl.push_str(" -H ");
+            curl.push_str(&shell_quote(&header));
+        }
+
+        if let Some(body) = self.body.as_ref().filter(|body| !body.is_empty()) {
+            curl.push_str(" --data-binary ");
+            curl.push_str(&shell_quote(&String::from_utf8_lossy(body)));
+        }
+
+        curl
+    }
+
     pub(crate) fn formatted(&self) -> String {
         let mut formatted = format!(
             "\r\n{} {}\r\n",
@@ -112,3 +331,33 @@ impl Request {
         formatted
     }
 }
+
+/// Wraps the value in single quotes for the shell
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Decompresses `body` using the given content encoding, or returns `None` if the encoding
+/// isn't supported or its feature is disabled
+#[cfg_attr(
+    not(any(feature = "gzip", feature = "deflate")),
+    allow(unused_variables)
+)]
+fn decode(encoding: &str, body: &[u8]) -> Option<io::Result<Vec<u8>>> {
+    match encoding {
+        #[cfg(feature = "gzip")]
+        "gzip" | "x-gzip" => Some(read_all(GzDecoder::new(body))),
+        #[cfg(feature = "deflate")]
+        "deflate" => {
+            Some(read_all(ZlibDecoder::new(body)).or_else(|_| read_all(DeflateDecoder::new(body))))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(any(feature = "gzip", feature = "deflate"))]
+fn read_all(mut reader: impl Read) -> io::Result<Vec<u8>> {
+    let mut decoded = vec![];
+    reader.read_to_end(&mut decoded)?;
+    Ok(decoded)
+}
@@ -1,13 +1,31 @@
 use crate::error::Error;
-use crate::Request;
+use crate::mock::IntoHeaderName;
+use crate::websocket::WebSocketHandler;
+use crate::{ErrorKind, Request};
 use bytes::Bytes;
+#[cfg(feature = "gzip")]
+use flate2::write::GzEncoder;
+#[cfg(feature = "deflate")]
+use flate2::write::ZlibEncoder;
+#[cfg(any(feature = "gzip", feature = "deflate"))]
+use flate2::Compression;
 use futures_util::Stream;
 use http::{HeaderMap, StatusCode};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use std::fmt;
+use std::future::Future;
 use std::io;
-use std::sync::Arc;
+#[cfg(any(feature = "gzip", feature = "deflate", feature = "brotli"))]
+use std::io::Write;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
 use std::task::Poll;
 use std::thread;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 
 #[derive(Clone, Debug, PartialEq)]
@@ -15,6 +33,220 @@ pub(crate) struct Response {
     pub status: StatusCode,
     pub headers: HeaderMap<Header>,
     pub body: Body,
+    pub transfer_encoding: Option<TransferEncoding>,
+    pub early_hints: Option<HeaderMap<String>>,
+    pub trailers: HeaderMap<String>,
+    pub reset_connection: bool,
+    pub random_status: Option<RandomStatus>,
+    pub status_fn: Option<StatusFn>,
+    pub websocket: Option<WebSocketHandler>,
+    pub first_byte_delay: Option<Duration>,
+    pub throttle: Option<usize>,
+    pub auto_encoding: bool,
+}
+
+///
+/// The framing used to deliver a response body. See `Mock::with_transfer_encoding`.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransferEncoding {
+    /// Sends the body in chunks, using `Transfer-Encoding: chunked`
+    Chunked,
+    /// Sends the body in one piece, along with a `Content-Length` header
+    Identity,
+}
+
+///
+/// The compression applied to a response body. See `Mock::with_body_encoded`.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ContentEncoding {
+    /// `Content-Encoding: gzip`. Requires the `gzip` feature (enabled by default).
+    #[cfg(feature = "gzip")]
+    Gzip,
+    /// `Content-Encoding: deflate`, i.e. the zlib format. Requires the `deflate` feature
+    /// (enabled by default).
+    #[cfg(feature = "deflate")]
+    Deflate,
+    /// `Content-Encoding: br`. Requires the `brotli` feature.
+    #[cfg(feature = "brotli")]
+    Brotli,
+}
+
+impl ContentEncoding {
+    /// The value of the `content-encoding` header
+    pub(crate) fn name(&self) -> &'static str {
+        match *self {
+            #[cfg(feature = "gzip")]
+            ContentEncoding::Gzip => "gzip",
+            #[cfg(feature = "deflate")]
+            ContentEncoding::Deflate => "deflate",
+            #[cfg(feature = "brotli")]
+            ContentEncoding::Brotli => "br",
+        }
+    }
+
+    /// The supported encodings, in order of preference
+    const SUPPORTED: &'static [ContentEncoding] = &[
+        #[cfg(feature = "brotli")]
+        ContentEncoding::Brotli,
+        #[cfg(feature = "gzip")]
+        ContentEncoding::Gzip,
+        #[cfg(feature = "deflate")]
+        ContentEncoding::Deflate,
+    ];
+
+    /// Picks the supported encoding with the highest quality value in the given
+    /// `Accept-Encoding` header, or `None` if the body should be sent uncompressed
+    pub(crate) fn negotiate(accept_encoding: &str) -> Option<ContentEncoding> {
+        let mut wildcard = None;
+        let mut accepted: Vec<(ContentEncoding, f32)> = vec![];
+
+        for item in accept_encoding.split(',') {
+            let mut params = item.split(';');
+            let name = params.next().unwrap_or("").trim().to_ascii_lowercase();
+            let quality = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|quality| quality.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+
+            if name == "*" {
+                wildcard = Some(quality);
+            } else if let Some(encoding) = Self::SUPPORTED
+                .iter()
+                .find(|encoding| encoding.name() == name)
+            {
+                accepted.push((*encoding, quality));
+            }
+        }
+
+        // encodings which weren't listed explicitly are covered by the wildcard
+        if let Some(quality) = wildcard {
+            for encoding in Self::SUPPORTED {
+                if accepted.iter().all(|(accepted, _)| accepted != encoding) {
+                    accepted.push((*encoding, quality));
+                }
+            }
+        }
+
+        let mut best: Option<(ContentEncoding, f32)> = None;
+        for (encoding, quality) in accepted {
+            if quality > 0.0 && best.map_or(true, |(_, best)| quality > best) {
+                best = Some((encoding, quality));
+            }
+        }
+
+        best.map(|(encoding, _)| encoding)
+    }
+
+    /// Compresses the given body
+    #[cfg_attr(
+        not(any(feature = "gzip", feature = "deflate", feature = "brotli")),
+        allow(unused_variables)
+    )]
+    pub(crate) fn encode(&self, body: &[u8]) -> io::Result<Vec<u8>> {
+        match *self {
+            #[cfg(feature = "gzip")]
+            ContentEncoding::Gzip => {
+                let mut encoder = GzEncoder::new(vec![], Compression::default());
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+            #[cfg(feature = "deflate")]
+            ContentEncoding::Deflate => {
+                let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+            #[cfg(feature = "brotli")]
+            ContentEncoding::Brotli => {
+                let mut encoder = brotli::CompressorWriter::new(vec![], 4096, 11, 22);
+                encoder.write_all(body)?;
+                Ok(encoder.into_inner())
+            }
+        }
+    }
+}
+
+///
+/// Picks a status code from a range on every hit, using a seeded random number generator.
+///
+#[derive(Clone)]
+pub(crate) struct RandomStatus {
+    range: RangeInclusive<u16>,
+    seed: u64,
+    rng: Arc<Mutex<StdRng>>,
+}
+
+impl RandomStatus {
+    pub(crate) fn new(range: RangeInclusive<u16>, seed: u64) -> Self {
+        Self {
+            range,
+            seed,
+            rng: Arc::new(Mutex::new(StdRng::seed_from_u64(seed))),
+        }
+    }
+
+    /// A generator for the same range and seed, starting over from the first status code
+    pub(crate) fn restarted(&self) -> Self {
+        Self::new(self.range.clone(), self.seed)
+    }
+
+    /// Returns the next status code from the range
+    pub(crate) fn next(&self) -> StatusCode {
+        let status = self.rng.lock().unwrap().gen_range(self.range.clone());
+        // the range bounds were validated when the mock was defined
+        StatusCode::from_u16(status).unwrap()
+    }
+}
+
+impl fmt::Debug for RandomStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RandomStatus")
+            .field("range", &self.range)
+            .field("seed", &self.seed)
+            .finish()
+    }
+}
+
+impl PartialEq for RandomStatus {
+    fn eq(&self, other: &Self) -> bool {
+        self.range == other.range && self.seed == other.seed
+    }
+}
+
+type StatusFnWithRequest = dyn Fn(&Request) -> u16 + Send + Sync;
+
+///
+/// Computes the status code of the response from the incoming request.
+///
+#[derive(Clone)]
+pub(crate) struct StatusFn(Arc<StatusFnWithRequest>);
+
+impl StatusFn {
+    pub(crate) fn new(callback: impl Fn(&Request) -> u16 + Send + Sync + 'static) -> Self {
+        Self(Arc::new(callback))
+    }
+
+    /// Returns the status code for the given request, failing if it isn't a valid one
+    pub(crate) fn status(&self, request: &Request) -> Result<StatusCode, Error> {
+        let status = (self.0)(request);
+        StatusCode::from_u16(status)
+            .map_err(|_| Error::new_with_context(ErrorKind::InvalidStatusCode, status))
+    }
+}
+
+impl fmt::Debug for StatusFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<callback>")
+    }
+}
+
+impl PartialEq for StatusFn {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
 }
 
 #[derive(Clone)]
@@ -49,12 +281,119 @@ type HeaderFnWithRequest = dyn Fn(&Request) -> String + Send + Sync;
 
 type BodyFnWithWriter = dyn Fn(&mut dyn io::Write) -> io::Result<()> + Send + Sync + 'static;
 type BodyFnWithRequest = dyn Fn(&Request) -> Bytes + Send + Sync + 'static;
+pub(crate) type BodyFuture = Pin<Box<dyn Future<Output = Bytes> + Send + 'static>>;
+type BodyFnWithRequestAsync = dyn Fn(&Request) -> BodyFuture + Send + Sync + 'static;
+type BodyFnOnce = dyn FnOnce() -> Vec<u8> + Send + 'static;
 
 #[derive(Clone)]
 pub(crate) enum Body {
     Bytes(Bytes),
     FnWithWriter(Arc<BodyFnWithWriter>),
     FnWithRequest(Arc<BodyFnWithRequest>),
+    FnWithRequestAsync(Arc<BodyFnWithRequestAsync>),
+    Lazy(Arc<LazyBody>),
+    Sequence(Arc<BodySequence>),
+    File(PathBuf),
+    Partial(Bytes, bool),
+    Backpressure(Arc<BackpressureBody>),
+}
+
+pub(crate) struct LazyBody {
+    callback: Mutex<Option<Box<BodyFnOnce>>>,
+    bytes: OnceLock<Bytes>,
+}
+
+impl LazyBody {
+    pub(crate) fn new(callback: Box<BodyFnOnce>) -> Self {
+        Self {
+            callback: Mutex::new(Some(callback)),
+            bytes: OnceLock::new(),
+        }
+    }
+
+    /// Evaluates the callback on the first call and returns the cached bytes afterwards
+    pub(crate) fn bytes(&self) -> Bytes {
+        self.bytes
+            .get_or_init(|| {
+                let callback = self.callback.lock().unwrap().take().unwrap();
+                Bytes::from(callback())
+            })
+            .clone()
+    }
+}
+
+pub(crate) struct BodySequence {
+    bodies: Vec<Bytes>,
+    cycle: bool,
+    index: AtomicUsize,
+}
+
+impl BodySequence {
+    pub(crate) fn new(bodies: Vec<Bytes>, cycle: bool) -> Self {
+        Self {
+            bodies,
+            cycle,
+            index: AtomicUsize::new(0),
+        }
+    }
+
+    /// A sequence of the same bodies, starting over from the first one
+    pub(crate) fn restarted(&self) -> Self {
+        Self::new(self.bodies.clone(), self.cycle)
+    }
+
+    /// Returns the body for the next request, repeating the last one or starting over once
+    /// the sequence is exhausted
+    pub(crate) fn next(&self) -> Bytes {
+        if self.bodies.is_empty() {
+            return Bytes::new();
+        }
+
+        let index = self.index.fetch_add(1, Ordering::SeqCst);
+        let index = if self.cycle {
+            index % self.bodies.len()
+        } else {
+            index.min(self.bodies.len() - 1)
+        };
+
+        self.bodies[index].clone()
+    }
+}
+
+pub(crate) struct BackpressureBody {
+    chunks: Vec<Bytes>,
+    sent_at: Mutex<Vec<Instant>>,
+}
+
+impl BackpressureBody {
+    pub(crate) fn new(chunks: Vec<Bytes>) -> Self {
+        Self {
+            chunks,
+            sent_at: Mutex::new(vec![]),
+        }
+    }
+
+    /// The total length of all chunks
+    pub(crate) fn len(&self) -> usize {
+        self.chunks.iter().map(Bytes::len).sum()
+    }
+
+    /// Forgets the timings of the previous response
+    pub(crate) fn reset(&self) {
+        self.sent_at.lock().unwrap().clear();
+    }
+
+    /// Returns the chunk at `index`, recording the moment it was requested by the connection
+    pub(crate) fn chunk(&self, index: usize) -> Option<Bytes> {
+        let chunk = self.chunks.get(index)?.clone();
+        self.sent_at.lock().unwrap().push(Instant::now());
+        Some(chunk)
+    }
+
+    /// The moments at which the chunks of the last response were handed over to the connection
+    pub(crate) fn sent_at(&self) -> Vec<Instant> {
+        self.sent_at.lock().unwrap().clone()
+    }
 }
 
 impl fmt::Debug for Body {
@@ -63,6 +402,12 @@ impl fmt::Debug for Body {
             Body::Bytes(ref b) => b.fmt(f),
             Body::FnWithWriter(_) => f.write_str("<callback>"),
             Body::FnWithRequest(_) => f.write_str("<callback>"),
+            Body::FnWithRequestAsync(_) => f.write_str("<callback>"),
+            Body::Lazy(_) => f.write_str("<callback>"),
+            Body::Sequence(ref sequence) => sequence.bodies.fmt(f),
+            Body::File(ref path) => path.fmt(f),
+            Body::Partial(ref b, _) => b.fmt(f),
+            Body::Backpressure(ref backpressure) => backpressure.chunks.fmt(f),
         }
     }
 }
@@ -79,19 +424,172 @@ impl PartialEq for Body {
                 a.as_ref() as *const BodyFnWithRequest as *const u8,
                 b.as_ref() as *const BodyFnWithRequest as *const u8,
             ),
+            (Body::FnWithRequestAsync(ref a), Body::FnWithRequestAsync(ref b)) => Arc::ptr_eq(a, b),
+            (Body::Lazy(ref a), Body::Lazy(ref b)) => Arc::ptr_eq(a, b),
+            (Body::Sequence(ref a), Body::Sequence(ref b)) => Arc::ptr_eq(a, b),
+            (Body::File(ref a), Body::File(ref b)) => a == b,
+            (Body::Partial(ref a, a_close), Body::Partial(ref b, b_close)) => {
+                a == b && a_close == b_close
+            }
+            (Body::Backpressure(ref a), Body::Backpressure(ref b)) => Arc::ptr_eq(a, b),
             _ => false,
         }
     }
 }
 
+///
+/// A reusable response definition that can be applied to a mock via `Mock::with_response`.
+///
+/// ## Example
+///
+/// ```
+/// use mockito::MockResponse;
+///
+/// let response = MockResponse::new()
+///     .with_status(201)
+///     .with_header("content-type", "application/json")
+///     .with_body(r#"{"id": 1}"#);
+///
+/// let mut s = mockito::Server::new();
+///
+/// s.mock("POST", "/users").with_response(response.clone()).create();
+/// s.mock("PUT", "/users/1").with_response(response).create();
+/// ```
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MockResponse {
+    pub(crate) inner: Response,
+}
+
+impl MockResponse {
+    ///
+    /// Creates a new response, with the status code 200 and an empty body.
+    ///
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// Sets the status code of the response. See `Mock::with_status`.
+    ///
+    #[track_caller]
+    pub fn with_status(mut self, status: usize) -> Self {
+        self.inner.status = StatusCode::from_u16(status as u16)
+            .map_err(|_| Error::new_with_context(ErrorKind::InvalidStatusCode, status))
+            .unwrap();
+
+        self
+    }
+
+    ///
+    /// Sets a header of the response. See `Mock::with_header`.
+    ///
+    pub fn with_header<T: IntoHeaderName>(mut self, field: T, value: &str) -> Self {
+        self.inner
+            .headers
+            .append(field.into_header_name(), Header::String(value.to_string()));
+
+        self
+    }
+
+    ///
+    /// Sets a header of the response dynamically. See `Mock::with_header_from_request`.
+    ///
+    pub fn with_header_from_request<T: IntoHeaderName>(
+        mut self,
+        field: T,
+        callback: impl Fn(&Request) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.inner.headers.append(
+            field.into_header_name(),
+            Header::FnWithRequest(Arc::new(move |req| callback(req))),
+        );
+
+        self
+    }
+
+    ///
+    /// Sets the body of the response. See `Mock::with_body`.
+    ///
+    pub fn with_body<StrOrBytes: AsRef<[u8]>>(mut self, body: StrOrBytes) -> Self {
+        self.inner.body = Body::Bytes(Bytes::from(body.as_ref().to_owned()));
+        self
+    }
+
+    ///
+    /// Sets the body of the response dynamically, using chunked transfer encoding.
+    /// See `Mock::with_chunked_body`.
+    ///
+    pub fn with_chunked_body(
+        mut self,
+        callback: impl Fn(&mut dyn io::Write) -> io::Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.inner.body = Body::FnWithWriter(Arc::new(callback));
+        self
+    }
+
+    ///
+    /// Sets the body of the response dynamically while exposing the request object.
+    /// See `Mock::with_body_from_request`.
+    ///
+    pub fn with_body_from_request(
+        mut self,
+        callback: impl Fn(&Request) -> Vec<u8> + Send + Sync + 'static,
+    ) -> Self {
+        self.inner.body = Body::FnWithRequest(Arc::new(move |req| Bytes::from(callback(req))));
+        self
+    }
+
+    ///
+    /// Forces the framing of the response body. See `Mock::with_transfer_encoding`.
+    ///
+    pub fn with_transfer_encoding(mut self, transfer_encoding: TransferEncoding) -> Self {
+        self.inner.transfer_encoding = Some(transfer_encoding);
+        self
+    }
+
+    ///
+    /// Holds back the first byte of the response body for the given duration.
+    /// See `Mock::with_first_byte_delay`.
+    ///
+    pub fn with_first_byte_delay(mut self, delay: Duration) -> Self {
+        self.inner.first_byte_delay = Some(delay);
+        self
+    }
+}
+
+impl Response {
+    /// Gives the response its own body sequence, random status generator and chunk timings,
+    /// starting over as if the mock was just defined, instead of sharing them with the
+    /// response it was cloned from
+    pub(crate) fn restart(&mut self) {
+        self.body = match self.body {
+            Body::Sequence(ref sequence) => Body::Sequence(Arc::new(sequence.restarted())),
+            Body::Backpressure(ref backpressure) => {
+                Body::Backpressure(Arc::new(BackpressureBody::new(backpressure.chunks.clone())))
+            }
+            ref body => body.clone(),
+        };
+        self.random_status = self.random_status.as_ref().map(RandomStatus::restarted);
+    }
+}
+
 impl Default for Response {
     fn default() -> Self {
-        let mut headers = HeaderMap::with_capacity(1);
-        headers.insert("connection", Header::String("close".to_string()));
         Self {
             status: StatusCode::OK,
-            headers,
+            headers: HeaderMap::default(),
             body: Body::Bytes(Bytes::new()),
+            transfer_encoding: None,
+            early_hints: None,
+            trailers: HeaderMap::default(),
+            reset_connection: false,
+            random_status: None,
+            status_fn: None,
+            websocket: None,
+            first_byte_delay: None,
+            throttle: None,
+            auto_encoding: false,
         }
     }
 }
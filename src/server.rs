@@ -1,30 +1,53 @@
-use crate::mock::InnerMock;
+use crate::connection::{Connection, ConnectionIo, InFlightRequest};
+use crate::cors::CorsConfig;
+use crate::fault::{Fault, FaultConfig};
+use crate::matcher;
+use crate::mock::{InnerMock, IntoHeaderName};
 use crate::request::Request;
-use crate::response::{Body as ResponseBody, ChunkedStream, Header};
+use crate::response::{
+    Body as ResponseBody, ChunkedStream, ContentEncoding, Header, TransferEncoding,
+};
+use crate::websocket::{self, WebSocketHandler};
 use crate::ServerGuard;
-use crate::{Error, ErrorKind, Matcher, Mock};
+use crate::{Error, ErrorKind, Matcher, Mock, MockSpec};
 use bytes::Bytes;
-use futures_util::{TryStream, TryStreamExt};
-use http::{Request as HttpRequest, Response, StatusCode};
+use futures_util::future::{self, Either};
+use futures_util::{FutureExt, TryFutureExt};
+use futures_util::{StreamExt, TryStream, TryStreamExt};
+use http::header::{
+    HeaderValue, ACCEPT_ENCODING, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+    ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_MAX_AGE, CONNECTION, CONTENT_ENCODING,
+    SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY, TRAILER, UPGRADE, VARY,
+};
+use http::response::Builder as ResponseBuilder;
+use http::HeaderMap;
+use http::{Method, Request as HttpRequest, Response, StatusCode, Version};
 use http_body::{Body as HttpBody, Frame, SizeHint};
 use http_body_util::{BodyExt, StreamBody};
 use hyper::body::Incoming;
 use hyper::service::service_fn;
-use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
 use hyper_util::server::conn::auto::Builder as ConnectionBuilder;
 use std::default::Default;
 use std::error::Error as StdError;
 use std::fmt;
+use std::future::Future;
+use std::io;
 use std::net::{IpAddr, SocketAddr};
 use std::ops::Drop;
-use std::pin::Pin;
+use std::pin::{pin, Pin};
+use std::rc::Rc;
 use std::str::FromStr;
 use std::sync::{mpsc, Arc, RwLock};
 use std::task::{ready, Context, Poll};
-use std::thread;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
 use tokio::net::TcpListener;
 use tokio::runtime;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::{watch, Notify};
 use tokio::task::{spawn_local, LocalSet};
+use tokio::time::Sleep;
 
 #[derive(Clone, Debug)]
 pub(crate) struct RemoteMock {
@@ -36,40 +59,98 @@ impl RemoteMock {
         RemoteMock { inner }
     }
 
-    fn matches(&self, other: &mut Request) -> bool {
+    fn matches(&self, other: &mut Request, max_depth: usize) -> bool {
         self.method_matches(other)
-            && self.path_matches(other)
-            && self.headers_match(other)
-            && self.body_matches(other)
+            && self.path_matches(other, max_depth)
+            && self.query_params_match(other, max_depth)
+            && self.headers_match(other, max_depth)
+            && self.body_matches(other, max_depth)
             && self.request_matches(other)
+            && self.remote_addr_matches(other)
+            && self.encoded_body_matches(other, max_depth)
+            && self.connection_request_index_matches(other)
+            && !self.is_expired()
     }
 
     fn method_matches(&self, request: &Request) -> bool {
         self.inner.method.as_str() == request.method()
     }
 
-    fn path_matches(&self, request: &Request) -> bool {
-        self.inner.path.matches_value(request.path_and_query())
+    fn path_matches(&self, request: &Request, max_depth: usize) -> bool {
+        self.inner
+            .path
+            .matches_value(request.path_and_query(), max_depth)
+    }
+
+    fn query_params_match(&self, request: &Request, max_depth: usize) -> bool {
+        if self.inner.query_params.is_empty() {
+            return true;
+        }
+
+        let query = request
+            .path_and_query()
+            .split_once('?')
+            .map_or("", |(_, query)| query);
+        let params: Vec<(String, String)> = serde_urlencoded::from_str(query).unwrap_or_default();
+
+        self.inner.query_params.iter().all(|(name, expected)| {
+            let values: Vec<&str> = params
+                .iter()
+                .filter(|(field, _)| field == name)
+                .map(|(_, value)| value.as_str())
+                .collect();
+
+            expected.matches_str_values(&values, max_depth)
+        })
     }
 
-    fn headers_match(&self, request: &Request) -> bool {
+    fn headers_match(&self, request: &Request, max_depth: usize) -> bool {
         self.inner
             .headers
             .iter()
-            .all(|(field, expected)| expected.matches_values(&request.header(field)))
+            .all(|(field, expected)| expected.matches_values(&request.header(field), max_depth))
     }
 
-    fn body_matches(&self, request: &mut Request) -> bool {
+    fn body_matches(&self, request: &mut Request, max_depth: usize) -> bool {
         let body = request.body().unwrap();
-        let safe_body = &String::from_utf8_lossy(body);
 
-        self.inner.body.matches_value(safe_body) || self.inner.body.matches_binary_value(body)
+        self.inner.body_matches(body, max_depth)
     }
 
     fn request_matches(&self, request: &Request) -> bool {
         self.inner.request_matcher.matches(request)
     }
 
+    fn remote_addr_matches(&self, request: &Request) -> bool {
+        self.inner.remote_addr_matcher.matches(request)
+    }
+
+    fn encoded_body_matches(&self, request: &Request, max_depth: usize) -> bool {
+        let Some((ref encoding, ref body)) = self.inner.encoded_body else {
+            return true;
+        };
+        if request.content_encoding().as_deref() != Some(encoding.as_str()) {
+            return false;
+        }
+
+        request
+            .decoded_body()
+            .is_some_and(|decoded| body.matches_body(&decoded, max_depth))
+    }
+
+    fn connection_request_index_matches(&self, request: &Request) -> bool {
+        self.inner
+            .connection_request_index
+            .map_or(true, |index| index == request.connection_request_index)
+    }
+
+    fn is_expired(&self) -> bool {
+        match (self.inner.created_at, self.inner.expire_after) {
+            (Some(created_at), Some(expire_after)) => created_at.elapsed() >= expire_after,
+            _ => false,
+        }
+    }
+
     #[allow(clippy::missing_const_for_fn)]
     fn is_missing_hits(&self) -> bool {
         match (
@@ -84,20 +165,109 @@ impl RemoteMock {
     }
 }
 
+#[derive(Debug)]
+pub(crate) struct ReceivedRequest {
+    pub(crate) request: Request,
+    /// The id of the mock that matched the request, if any
+    pub(crate) mock_id: Option<String>,
+    /// The response sent by the mock, if any
+    pub(crate) response: Option<SentResponse>,
+}
+
+#[derive(Debug)]
+pub(crate) struct SentResponse {
+    pub(crate) status: StatusCode,
+    /// The response body, unless it was streamed
+    pub(crate) body: Option<Bytes>,
+}
+
+#[derive(Clone)]
+pub(crate) struct RequestRewriter(Arc<dyn Fn(&mut Request) + Send + Sync>);
+
+impl fmt::Debug for RequestRewriter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(RequestRewriter)")
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct RequestHook(Arc<dyn Fn(&Request) + Send + Sync>);
+
+impl fmt::Debug for RequestHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(RequestHook)")
+    }
+}
+
+type ResponseHookFn = dyn Fn(&Request, usize) + Send + Sync;
+
+#[derive(Clone)]
+pub(crate) struct ResponseHook(Arc<ResponseHookFn>);
+
+impl fmt::Debug for ResponseHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(ResponseHook)")
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct State {
     pub(crate) mocks: Vec<RemoteMock>,
-    pub(crate) unmatched_requests: Vec<Request>,
+    pub(crate) requests: Vec<ReceivedRequest>,
+    pub(crate) request_rewriter: Option<RequestRewriter>,
+    pub(crate) request_hook: Option<RequestHook>,
+    pub(crate) response_hook: Option<ResponseHook>,
+    pub(crate) default_headers: HeaderMap<Header>,
+    pub(crate) fault: Option<Fault>,
+    pub(crate) assert_with_curl: bool,
+    pub(crate) warn_on_duplicate_mocks: bool,
+    pub(crate) max_matcher_depth: usize,
 }
 
 impl State {
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         State {
             mocks: vec![],
-            unmatched_requests: vec![],
+            requests: vec![],
+            request_rewriter: None,
+            request_hook: None,
+            response_hook: None,
+            default_headers: HeaderMap::default(),
+            fault: None,
+            assert_with_curl: false,
+            warn_on_duplicate_mocks: false,
+            max_matcher_depth: matcher::DEFAULT_MAX_DEPTH,
         }
     }
 
+    /// Removes all the mocks, received requests, the request rewriter, the hooks, the
+    /// default headers and the injected faults
+    pub(crate) fn clear(&mut self) {
+        self.mocks.clear();
+        self.requests.clear();
+        self.request_rewriter = None;
+        self.request_hook = None;
+        self.response_hook = None;
+        self.default_headers.clear();
+        self.fault = None;
+    }
+
+    pub(crate) fn get_last_response(&self, mock_id: &str) -> Option<&SentResponse> {
+        self.requests
+            .iter()
+            .rev()
+            .find(|received| received.mock_id.as_deref() == Some(mock_id))
+            .and_then(|received| received.response.as_ref())
+    }
+
+    pub(crate) fn get_last_request(&self, mock_id: &str) -> Option<&Request> {
+        self.requests
+            .iter()
+            .rev()
+            .find(|received| received.mock_id.as_deref() == Some(mock_id))
+            .map(|received| &received.request)
+    }
+
     pub(crate) fn get_mock_hits(&self, mock_id: String) -> Option<usize> {
         self.mocks
             .iter()
@@ -118,8 +288,18 @@ impl State {
         false
     }
 
-    pub(crate) fn get_last_unmatched_request(&self) -> Option<String> {
-        self.unmatched_requests.last().map(|req| req.formatted())
+    pub(crate) fn get_last_unmatched_request(&self) -> Option<&Request> {
+        self.requests
+            .iter()
+            .rev()
+            .find(|received| received.mock_id.is_none())
+            .map(|received| &received.request)
+    }
+
+    fn creation_index(&self, mock_id: &str) -> Option<usize> {
+        self.mocks
+            .iter()
+            .position(|remote_mock| remote_mock.inner.id == mock_id)
     }
 }
 
@@ -131,12 +311,44 @@ impl State {
 /// ```
 ///
 pub struct ServerOpts {
-    /// The server host (defaults to 127.0.0.1)
+    /// The server host, as an IPv4 or IPv6 address (defaults to 127.0.0.1)
     pub host: &'static str,
     /// The server port (defaults to a randomly assigned free port)
     pub port: u16,
     /// Automatically call `assert()` before dropping a mock (defaults to false)
     pub assert_on_drop: bool,
+    /// Close connections once they have been idle between requests for the given duration
+    /// (defaults to `None`, which keeps idle connections open). Slow responses don't count
+    /// as idle.
+    pub keep_alive_timeout: Option<Duration>,
+    /// Decompress request bodies sent with `Content-Encoding: gzip` or `deflate` before
+    /// matching them (defaults to false). Each format requires its feature flag.
+    pub decode_request_body: bool,
+    /// Give up on clients which don't send a complete request within the given duration
+    /// (defaults to `None`). Slow request headers close the connection, while slow request
+    /// bodies are answered with `408 Request Timeout` and recorded as unmatched requests. For
+    /// HTTP/1 this also closes connections which stay idle between requests for longer than
+    /// the given duration.
+    pub request_timeout: Option<Duration>,
+    /// The maximum nesting depth of `Matcher::AnyOf` and `Matcher::AllOf` matchers (defaults
+    /// to 64). Deeper matchers don't match and log a warning, instead of overflowing the
+    /// stack of the server thread.
+    pub max_matcher_depth: usize,
+    /// Include the last unmatched request as a `curl` command in the messages of failed
+    /// assertions (defaults to false). See `Request::as_curl`.
+    pub assert_with_curl: bool,
+    /// Respond with `431 Request Header Fields Too Large` to requests with more headers than
+    /// the given number (defaults to `None`). Note that HTTP/1 requests with more than 100
+    /// headers are always rejected. Requests rejected by this or `max_header_bytes` are
+    /// recorded as unmatched requests.
+    pub max_headers: Option<usize>,
+    /// Respond with `431 Request Header Fields Too Large` to requests whose header names and
+    /// values add up to more than the given number of bytes (defaults to `None`)
+    pub max_header_bytes: Option<usize>,
+    /// Log a warning when a mock is created with the same method, path, query, header and
+    /// body matchers as an existing mock, which means only one of them receives the matching
+    /// requests at a time (defaults to false)
+    pub warn_on_duplicate_mocks: bool,
 }
 
 impl ServerOpts {
@@ -151,11 +363,27 @@ impl Default for ServerOpts {
         let host = "127.0.0.1";
         let port = 0;
         let assert_on_drop = false;
+        let keep_alive_timeout = None;
+        let decode_request_body = false;
+        let request_timeout = None;
+        let max_matcher_depth = matcher::DEFAULT_MAX_DEPTH;
+        let assert_with_curl = false;
+        let max_headers = None;
+        let max_header_bytes = None;
+        let warn_on_duplicate_mocks = false;
 
         ServerOpts {
             host,
             port,
             assert_on_drop,
+            keep_alive_timeout,
+            decode_request_body,
+            request_timeout,
+            max_matcher_depth,
+            assert_with_curl,
+            max_headers,
+            max_header_bytes,
+            warn_on_duplicate_mocks,
         }
     }
 }
@@ -193,6 +421,9 @@ pub struct Server {
     address: SocketAddr,
     state: Arc<RwLock<State>>,
     assert_on_drop: bool,
+    shutdown: Arc<Notify>,
+    drain: UnboundedSender<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
 }
 
 impl Server {
@@ -232,7 +463,7 @@ impl Server {
     /// Same as `Server::try_new` but async.
     ///
     pub(crate) async fn try_new_async() -> Result<ServerGuard, Error> {
-        let server = crate::server_pool::SERVER_POOL
+        let server = crate::server_pool::server_pool()
             .get_async()
             .await
             .map_err(|err| Error::new_with_context(ErrorKind::ServerFailure, err))?;
@@ -241,7 +472,44 @@ impl Server {
     }
 
     ///
-    /// **DEPRECATED:** Use `Server::new_with_opts` instead.
+    /// Same as `Server::new`, but gives up waiting for a free server once the given `timeout`
+    /// has elapsed, returning an error of the kind `ErrorKind::ServerBusy`.
+    ///
+    /// This is useful for failing fast instead of hanging when the server pool is exhausted.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// let server = mockito::Server::new_with_pool_timeout(Duration::from_secs(5)).unwrap();
+    /// ```
+    ///
+    pub fn new_with_pool_timeout(timeout: Duration) -> Result<ServerGuard, Error> {
+        runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Cannot build local tokio runtime")
+            .block_on(async { Server::new_with_pool_timeout_async(timeout).await })
+    }
+
+    ///
+    /// Same as `Server::new_with_pool_timeout` but async.
+    ///
+    pub async fn new_with_pool_timeout_async(timeout: Duration) -> Result<ServerGuard, Error> {
+        tokio::time::timeout(timeout, Server::try_new_async())
+            .await
+            .map_err(|_| {
+                Error::new_with_context(
+                    ErrorKind::ServerBusy,
+                    format!("no pooled server became available within {:?}", timeout),
+                )
+            })?
+    }
+
+    ///
+    /// **DEPRECATED:** Use `Server::new_with_opts` instead, or `Server::new_unpooled` for a
+    /// random port.
     ///
     #[deprecated(since = "1.3.0", note = "Use `Server::new_with_opts` instead")]
     #[track_caller]
@@ -282,14 +550,49 @@ impl Server {
         Server::try_new_with_opts_async(opts).await.unwrap()
     }
 
+    ///
+    /// Starts a new server on a random free port, **bypassing the server pool**. Use it when
+    /// the pool size limit gets in the way, e.g. for tests which need many servers at once.
+    ///
+    /// Unlike pooled servers, the server is not reused once dropped.
+    ///
+    /// This method will panic on failure.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new_unpooled();
+    ///
+    /// s.mock("GET", "/").create();
+    /// ```
+    ///
+    #[track_caller]
+    pub fn new_unpooled() -> Server {
+        Server::try_new_with_opts(ServerOpts::default()).unwrap()
+    }
+
+    ///
+    /// Same as `Server::new_unpooled` but async.
+    ///
+    pub async fn new_unpooled_async() -> Server {
+        Server::try_new_with_opts_async(ServerOpts::default())
+            .await
+            .unwrap()
+    }
+
     ///
     /// Same as `Server::new_with_opts` but won't panic on failure.
     ///
     #[track_caller]
     pub(crate) fn try_new_with_opts(opts: ServerOpts) -> Result<Server, Error> {
-        let state = Arc::new(RwLock::new(State::new()));
+        let mut state = State::new();
+        state.assert_with_curl = opts.assert_with_curl;
+        state.warn_on_duplicate_mocks = opts.warn_on_duplicate_mocks;
+        state.max_matcher_depth = opts.max_matcher_depth;
+        let state = Arc::new(RwLock::new(state));
         let address = opts.address();
         let assert_on_drop = opts.assert_on_drop;
+        let opts = Arc::new(opts);
         let (address_sender, address_receiver) = mpsc::channel::<SocketAddr>();
         let runtime = runtime::Builder::new_current_thread()
             .enable_all()
@@ -297,8 +600,18 @@ impl Server {
             .expect("Cannot build local tokio runtime");
 
         let state_clone = state.clone();
-        thread::spawn(move || {
-            let server = Server::bind_server(address, address_sender, state_clone);
+        let shutdown = Arc::new(Notify::new());
+        let shutdown_clone = shutdown.clone();
+        let (drain, drain_receiver) = unbounded_channel();
+        let thread = thread::spawn(move || {
+            let server = Server::bind_server(
+                address,
+                address_sender,
+                state_clone,
+                opts,
+                shutdown_clone,
+                drain_receiver,
+            );
             LocalSet::new().block_on(&runtime, server).unwrap();
         });
 
@@ -310,6 +623,9 @@ impl Server {
             address,
             state,
             assert_on_drop,
+            shutdown,
+            drain,
+            thread: Some(thread),
         };
 
         Ok(server)
@@ -319,9 +635,14 @@ impl Server {
     /// Same as `Server::try_new_with_opts` but async.
     ///
     pub(crate) async fn try_new_with_opts_async(opts: ServerOpts) -> Result<Server, Error> {
-        let state = Arc::new(RwLock::new(State::new()));
+        let mut state = State::new();
+        state.assert_with_curl = opts.assert_with_curl;
+        state.warn_on_duplicate_mocks = opts.warn_on_duplicate_mocks;
+        state.max_matcher_depth = opts.max_matcher_depth;
+        let state = Arc::new(RwLock::new(state));
         let address = opts.address();
         let assert_on_drop = opts.assert_on_drop;
+        let opts = Arc::new(opts);
         let (address_sender, address_receiver) = mpsc::channel::<SocketAddr>();
         let runtime = runtime::Builder::new_current_thread()
             .enable_all()
@@ -329,8 +650,18 @@ impl Server {
             .expect("Cannot build local tokio runtime");
 
         let state_clone = state.clone();
-        thread::spawn(move || {
-            let server = Server::bind_server(address, address_sender, state_clone);
+        let shutdown = Arc::new(Notify::new());
+        let shutdown_clone = shutdown.clone();
+        let (drain, drain_receiver) = unbounded_channel();
+        let thread = thread::spawn(move || {
+            let server = Server::bind_server(
+                address,
+                address_sender,
+                state_clone,
+                opts,
+                shutdown_clone,
+                drain_receiver,
+            );
             LocalSet::new().block_on(&runtime, server).unwrap();
         });
 
@@ -342,6 +673,9 @@ impl Server {
             address,
             state,
             assert_on_drop,
+            shutdown,
+            drain,
+            thread: Some(thread),
         };
 
         Ok(server)
@@ -351,6 +685,9 @@ impl Server {
         address: SocketAddr,
         address_sender: mpsc::Sender<SocketAddr>,
         state: Arc<RwLock<State>>,
+        opts: Arc<ServerOpts>,
+        shutdown: Arc<Notify>,
+        mut drain: UnboundedReceiver<mpsc::Sender<()>>,
     ) -> Result<(), Error> {
         let listener = TcpListener::bind(address)
             .await
@@ -362,18 +699,83 @@ impl Server {
 
         address_sender.send(address).unwrap();
 
-        while let Ok((stream, _)) = listener.accept().await {
+        // signals the open connections to close, see `Server::drain`
+        let (close_sender, _) = watch::channel(());
+        // every open connection holds a clone
+        let open_connections = Rc::new(());
+
+        loop {
+            let notified = pin!(shutdown.notified());
+            // once the `Server` is dropped no drain can be requested anymore,
+            // but unpooled servers keep running in the background
+            let drain_requested = pin!(async {
+                match drain.recv().await {
+                    Some(drained) => drained,
+                    None => future::pending().await,
+                }
+            });
+            let interrupted = future::select(notified, drain_requested);
+            let (stream, remote_addr) =
+                match future::select(pin!(listener.accept()), interrupted).await {
+                    Either::Left((Ok(accepted), _)) => accepted,
+                    Either::Right((Either::Right((drained, _)), _)) => {
+                        let _ = close_sender.send(());
+                        // drop the connections which are still waiting to be accepted
+                        while let Some(Ok(_)) = listener.accept().now_or_never() {}
+                        while Rc::strong_count(&open_connections) > 1 {
+                            tokio::task::yield_now().await;
+                        }
+                        let _ = drained.send(());
+                        continue;
+                    }
+                    Either::Left((Err(_), _)) | Either::Right(_) => break,
+                };
+
             let mutex = state.clone();
+            let opts = opts.clone();
+            let connection = Connection::new(stream.local_addr().ok(), Some(remote_addr));
+            let io = ConnectionIo::new(stream, connection.clone());
+
+            let idle_connection = connection.clone();
+            let keep_alive_timeout = opts.keep_alive_timeout;
+            let request_timeout = opts.request_timeout;
+            let mut closed = close_sender.subscribe();
+            let open_connection = open_connections.clone();
 
             spawn_local(async move {
-                let _ = ConnectionBuilder::new(TokioExecutor::new())
-                    .serve_connection(
-                        TokioIo::new(stream),
-                        service_fn(move |request: HttpRequest<Incoming>| {
-                            handle_request(request, mutex.clone())
-                        }),
-                    )
-                    .await;
+                let mut builder = ConnectionBuilder::new(TokioExecutor::new());
+                if let Some(timeout) = request_timeout {
+                    builder
+                        .http1()
+                        .timer(TokioTimer::new())
+                        .header_read_timeout(timeout);
+                }
+                let serve = builder.serve_connection_with_upgrades(
+                    TokioIo::new(io),
+                    service_fn(move |request: HttpRequest<Incoming>| {
+                        // lasts until the response body was sent
+                        let in_flight = connection.start_request();
+                        handle_request(request, mutex.clone(), connection.clone(), opts.clone())
+                            .map_ok(|response| response.map(|body| body.in_flight(in_flight)))
+                    }),
+                );
+
+                let serve = async move {
+                    match keep_alive_timeout {
+                        Some(timeout) => {
+                            // dropping the connection future closes the connection
+                            let idle = idle_connection.idle(timeout);
+                            let _ = future::select(pin!(serve), pin!(idle)).await;
+                        }
+                        None => {
+                            let _ = serve.await;
+                        }
+                    }
+                };
+
+                // check for the close signal first, so that no further requests are served
+                let _ = future::select(pin!(closed.changed()), pin!(serve)).await;
+                drop(open_connection);
             });
         }
 
@@ -395,13 +797,121 @@ impl Server {
     /// let _m3 = s.mock("DELETE", "/users?id=1");
     /// ```
     ///
+    #[track_caller]
     pub fn mock<P: Into<Matcher>>(&mut self, method: &str, path: P) -> Mock {
         Mock::new(self.state.clone(), method, path, self.assert_on_drop)
     }
 
+    ///
+    /// Same as `Server::mock`, but takes the method as an `http::Method`, which rules out typos.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use http::Method;
+    ///
+    /// let mut s = mockito::Server::new();
+    ///
+    /// let _m = s.mock_method(Method::POST, "/users");
+    /// ```
+    ///
+    #[track_caller]
+    pub fn mock_method<P: Into<Matcher>>(&mut self, method: Method, path: P) -> Mock {
+        self.mock(method.as_str(), path)
+    }
+
+    ///
+    /// Creates a mock on this server from a reusable `MockSpec`. The returned mock is already
+    /// enabled on the server and keeps track of its own hits.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use mockito::MockSpec;
+    ///
+    /// let spec = MockSpec::builder("GET", "/hello").with_status(201).into_spec();
+    ///
+    /// let mut s = mockito::Server::new();
+    /// let m = s.register(&spec);
+    /// ```
+    ///
+    pub fn register(&mut self, spec: &MockSpec) -> Mock {
+        Mock::from_spec(self.state.clone(), spec, self.assert_on_drop).create()
+    }
+
+    ///
+    /// Creates a mock with the given HTTP `method` and `path`, responding with the given
+    /// `status` and `body`. The returned mock is already enabled on the server.
+    ///
+    /// This is a shortcut for
+    /// `Server::mock(method, path).with_status(status).with_body(body).create()`.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// let m = s.mock_with("GET", "/hello", 200, "world");
+    /// ```
+    ///
+    #[track_caller]
+    pub fn mock_with<P: Into<Matcher>, StrOrBytes: AsRef<[u8]>>(
+        &mut self,
+        method: &str,
+        path: P,
+        status: usize,
+        body: StrOrBytes,
+    ) -> Mock {
+        self.mock(method, path)
+            .with_status(status)
+            .with_body(body)
+            .create()
+    }
+
+    ///
+    /// Simulates an asynchronous job which is polled for completion:
+    ///
+    /// - `POST submit_path` responds with `202 Accepted` and a `Location: status_path` header
+    /// - `GET status_path` responds with `202 Accepted` for the first `poll_count` requests
+    /// - `GET status_path` responds with `200 OK` afterwards
+    ///
+    /// Returns the created submit, in-progress and completed mocks, in this order.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// let (submit, in_progress, completed) = s.mock_async_job("/jobs", "/jobs/1", 2);
+    /// ```
+    ///
+    pub fn mock_async_job(
+        &mut self,
+        submit_path: &str,
+        status_path: &str,
+        poll_count: usize,
+    ) -> (Mock, Mock, Mock) {
+        let submit = self
+            .mock("POST", submit_path)
+            .with_status(202)
+            .with_header(http::header::LOCATION, status_path)
+            .create();
+        // the in-progress mock is served while it's missing hits, since it was created first
+        let in_progress = self
+            .mock("GET", status_path)
+            .with_status(202)
+            .expect(poll_count)
+            .create();
+        let completed = self.mock("GET", status_path).with_status(200).create();
+
+        (submit, in_progress, completed)
+    }
+
     ///
     /// The URL of the mock server (including the protocol).
     ///
+    /// IPv6 hosts are enclosed in brackets, e.g. `http://[::1]:1234`.
+    ///
     pub fn url(&self) -> String {
         format!("http://{}", self.address)
     }
@@ -422,98 +932,794 @@ impl Server {
     }
 
     ///
-    /// Removes all the mocks stored on the server.
+    /// The port the mock server is bound to.
     ///
-    pub fn reset(&mut self) {
-        let state = self.state.clone();
-        let mut state = state.write().unwrap();
-        state.mocks.clear();
-        state.unmatched_requests.clear();
+    pub fn port(&self) -> u16 {
+        self.address.port()
     }
 
     ///
-    /// **DEPRECATED:** Use `Server::reset` instead. The implementation is not async any more.
+    /// The IP address the mock server is bound to.
     ///
-    #[deprecated(since = "1.0.1", note = "Use `Server::reset` instead")]
-    pub async fn reset_async(&mut self) {
-        let state = self.state.clone();
-        let mut state = state.write().unwrap();
-        state.mocks.clear();
-        state.unmatched_requests.clear();
+    pub fn host(&self) -> IpAddr {
+        self.address.ip()
     }
-}
 
-impl Drop for Server {
-    fn drop(&mut self) {
-        self.reset();
+    ///
+    /// Stops the server: no new connections are accepted and the port is released. Any open
+    /// connections are closed.
+    ///
+    /// Unlike dropping the server, this waits for the server to shut down, so it's safe to
+    /// reuse the port right after calling it. Stopping a server more than once has no effect.
+    /// Pooled servers which were stopped are not returned to the pool.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    /// let address = s.host_with_port();
+    ///
+    /// s.stop();
+    ///
+    /// assert!(std::net::TcpStream::connect(address).is_err());
+    /// ```
+    ///
+    pub fn stop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.shutdown.notify_one();
+            let _ = thread.join();
+        }
     }
-}
 
-impl fmt::Display for Server {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str(&format!("server {}", self.host_with_port()))
+    pub(crate) fn state(&self) -> &Arc<RwLock<State>> {
+        &self.state
     }
-}
-
-type BoxError = Box<dyn StdError + Send + Sync>;
 
-enum Body {
-    Once(Option<Bytes>),
-    Wrap(http_body_util::combinators::UnsyncBoxBody<Bytes, BoxError>),
-}
+    ///
+    /// Closes the open connections and drops the ones waiting to be accepted, so that requests
+    /// sent over connections of a previous pooled server user can't reach the next one.
+    /// Blocks until the server is done.
+    ///
+    pub(crate) fn drain(&self) {
+        let (sender, receiver) = mpsc::channel();
+        if self.drain.send(sender).is_ok() {
+            let _ = receiver.recv();
+        }
+    }
 
-impl Body {
-    fn empty() -> Self {
-        Self::Once(None)
+    pub(crate) fn is_stopped(&self) -> bool {
+        self.thread.is_none()
     }
 
-    fn from_data_stream<S>(stream: S) -> Self
+    ///
+    /// Registers a closure which transforms every incoming request before it gets matched
+    /// against the mocks, e.g. to strip a base path added by a proxy. Replaces any previously
+    /// registered rewriter and is removed by `Server::reset`.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// s.with_request_rewriter(|request| {
+    ///     if let Some(path) = request.path_and_query().strip_prefix("/api/v1") {
+    ///         let path = path.to_string();
+    ///         request.set_path_and_query(&path).unwrap();
+    ///     }
+    /// });
+    ///
+    /// // Matches requests to `/api/v1/users`
+    /// s.mock("GET", "/users").create();
+    /// ```
+    ///
+    pub fn with_request_rewriter<F>(&mut self, rewriter: F) -> &mut Self
     where
-        S: TryStream<Ok = Bytes> + Send + 'static,
-        S::Error: Into<BoxError>,
+        F: Fn(&mut Request) + Send + Sync + 'static,
     {
-        let body = StreamBody::new(stream.map_ok(Frame::data).map_err(Into::into)).boxed_unsync();
-        Self::Wrap(body)
-    }
-}
+        let state = self.state.clone();
+        let mut state = state.write().unwrap();
+        state.request_rewriter = Some(RequestRewriter(Arc::new(rewriter)));
+        drop(state);
 
-impl From<Bytes> for Body {
-    fn from(bytes: Bytes) -> Self {
-        if bytes.is_empty() {
-            Self::empty()
-        } else {
-            Self::Once(Some(bytes))
-        }
+        self
     }
-}
-
-impl HttpBody for Body {
-    type Data = Bytes;
-    type Error = BoxError;
 
-    fn poll_frame(
-        mut self: Pin<&mut Self>,
-        cx: &mut Context<'_>,
-    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
-        match self.as_mut().get_mut() {
-            Self::Once(val) => Poll::Ready(Ok(val.take().map(Frame::data)).transpose()),
-            Self::Wrap(body) => Poll::Ready(ready!(Pin::new(body).poll_frame(cx))),
+    ///
+    /// Sets headers which are added to the responses of every mock on the server, e.g.
+    /// `Server: mockito` or CORS headers. Headers set on a mock take precedence over the
+    /// default headers with the same name. Replaces any previously set default headers and is
+    /// removed by `Server::reset`.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// s.with_default_headers(vec![("server".to_string(), "mockito".to_string())]);
+    ///
+    /// // Responds with `server: mockito`
+    /// s.mock("GET", "/").create();
+    /// ```
+    ///
+    #[track_caller]
+    pub fn with_default_headers(&mut self, headers: Vec<(String, String)>) -> &mut Self {
+        let mut default_headers = HeaderMap::default();
+        for (field, value) in headers {
+            default_headers.append(field.into_header_name(), Header::String(value));
         }
-    }
 
-    fn size_hint(&self) -> SizeHint {
-        match self {
-            Self::Once(None) => SizeHint::with_exact(0),
-            Self::Once(Some(bytes)) => SizeHint::with_exact(bytes.len() as u64),
-            Self::Wrap(body) => body.size_hint(),
-        }
+        let state = self.state.clone();
+        let mut state = state.write().unwrap();
+        state.default_headers = default_headers;
+        drop(state);
+
+        self
     }
 
-    fn is_end_stream(&self) -> bool {
-        match self {
+    ///
+    /// Injects faults for testing how clients deal with an unavailable server: while set,
+    /// requests are answered with the configured status and headers instead of being matched
+    /// against the mocks, according to the configured probability. Pass `None` to restore
+    /// the normal behaviour. Faults are also removed by `Server::reset`.
+    ///
+    /// Faulty requests are recorded as unmatched, so they show up in failed assertions without
+    /// counting towards `Server::requests_for` or the hits of any mock.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// s.mock("GET", "/").create();
+    ///
+    /// // Answers every request with `503 Service Unavailable` and `retry-after: 1`
+    /// s.set_fault(Some(mockito::FaultConfig {
+    ///     headers: vec![("retry-after".to_string(), "1".to_string())],
+    ///     ..Default::default()
+    /// }));
+    ///
+    /// // Back to normal
+    /// s.set_fault(None);
+    /// ```
+    ///
+    #[track_caller]
+    pub fn set_fault(&mut self, fault: Option<FaultConfig>) -> &mut Self {
+        let fault = fault.map(Fault::new);
+
+        self.state.write().unwrap().fault = fault;
+
+        self
+    }
+
+    ///
+    /// Shorthand for answering every request with `503 Service Unavailable` (`true`) or
+    /// restoring the normal behaviour (`false`). See `Server::set_fault`.
+    ///
+    pub fn set_busy(&mut self, busy: bool) -> &mut Self {
+        self.set_fault(busy.then(FaultConfig::default))
+    }
+
+    ///
+    /// Answers CORS preflight requests and adds the `Access-Control-Allow-Origin` header to
+    /// the responses of every mock on the server, according to the given policy. Useful for
+    /// testing browser clients.
+    ///
+    /// Preflight requests are answered by an `OPTIONS` mock matching any path, with a
+    /// `204 No Content` and the `Access-Control-Allow-*` headers. Mocks created afterwards for
+    /// `OPTIONS` requests take precedence. The CORS headers are added to the default headers,
+    /// so call `Server::with_default_headers` first when combining them. Everything is removed
+    /// by `Server::reset`.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// s.enable_cors(mockito::CorsConfig::default());
+    ///
+    /// // Responds with `access-control-allow-origin: *`
+    /// s.mock("GET", "/").create();
+    /// ```
+    ///
+    pub fn enable_cors(&mut self, config: CorsConfig) -> &mut Self {
+        let mut preflight = Mock::new(self.state.clone(), "OPTIONS", Matcher::Any, false)
+            .with_status(204)
+            .with_header(
+                ACCESS_CONTROL_ALLOW_METHODS,
+                &config.allow_methods.join(", "),
+            )
+            .with_header(
+                ACCESS_CONTROL_ALLOW_HEADERS,
+                &config.allow_headers.join(", "),
+            )
+            .expect_at_least(0);
+        if let Some(max_age) = config.max_age {
+            preflight =
+                preflight.with_header(ACCESS_CONTROL_MAX_AGE, &max_age.as_secs().to_string());
+        }
+
+        let any_origin = config.allows_any_origin();
+        let allow_origin =
+            Header::FnWithRequest(Arc::new(move |request| config.allow_origin(request)));
+
+        let state = self.state.clone();
+        let mut state = state.write().unwrap();
+        state
+            .default_headers
+            .insert(ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+        if !any_origin {
+            state
+                .default_headers
+                .append(VARY, Header::String("origin".to_string()));
+        }
+        drop(state);
+
+        preflight.create();
+
+        self
+    }
+
+    ///
+    /// Registers a closure which is called for every incoming request, before it gets matched
+    /// against the mocks. Useful for capturing metrics or asserting side effects. Replaces any
+    /// previously registered hook and is removed by `Server::reset`.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    ///
+    /// let mut s = mockito::Server::new();
+    ///
+    /// let count = Arc::new(AtomicUsize::new(0));
+    /// let count_clone = count.clone();
+    /// s.on_request(move |_request| {
+    ///     count_clone.fetch_add(1, Ordering::SeqCst);
+    /// });
+    /// ```
+    ///
+    pub fn on_request<F>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn(&Request) + Send + Sync + 'static,
+    {
+        let state = self.state.clone();
+        let mut state = state.write().unwrap();
+        state.request_hook = Some(RequestHook(Arc::new(hook)));
+        drop(state);
+
+        self
+    }
+
+    ///
+    /// Registers a closure which is called with the request and the response status code,
+    /// whenever a mock responds to a request. Replaces any previously registered hook and is
+    /// removed by `Server::reset`.
+    ///
+    /// The hook is called while the server state is locked, so it shouldn't call back into
+    /// the server.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// s.on_response(|request, status| {
+    ///     println!("{} {} -> {}", request.method(), request.path(), status);
+    /// });
+    /// ```
+    ///
+    pub fn on_response<F>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn(&Request, usize) + Send + Sync + 'static,
+    {
+        let state = self.state.clone();
+        let mut state = state.write().unwrap();
+        state.response_hook = Some(ResponseHook(Arc::new(hook)));
+        drop(state);
+
+        self
+    }
+
+    ///
+    /// Resets the server to its initial state, removing:
+    ///
+    /// - the mocks stored on the server
+    /// - the requests received so far
+    /// - the request rewriter set by `Server::with_request_rewriter`
+    /// - the hooks set by `Server::on_request` and `Server::on_response`
+    /// - the default headers set by `Server::with_default_headers` or `Server::enable_cors`
+    /// - the fault set by `Server::set_fault` or `Server::set_busy`
+    ///
+    /// The options the server was created with are kept.
+    ///
+    pub fn reset(&mut self) {
+        self.state.write().unwrap().clear();
+    }
+
+    ///
+    /// The number of mocks registered on the server.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// let _m = s.mock("GET", "/hello").create();
+    /// assert_eq!(1, s.mock_count());
+    ///
+    /// s.reset();
+    /// assert_eq!(0, s.mock_count());
+    /// ```
+    ///
+    pub fn mock_count(&self) -> usize {
+        self.state.read().unwrap().mocks.len()
+    }
+
+    ///
+    /// Returns whether the server has no mocks registered, e.g. to verify the cleanup
+    /// between test phases sharing a server.
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.mock_count() == 0
+    }
+
+    ///
+    /// Returns a description of every mock registered on the server (method, path, headers and
+    /// body matchers), in the order they were created. Useful for debugging requests that
+    /// unexpectedly didn't match any mock.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// s.mock("GET", "/hello").create();
+    /// s.mock("POST", "/hello").match_body("world").create();
+    ///
+    /// for mock in s.dump_mocks() {
+    ///     println!("{}", mock);
+    /// }
+    /// ```
+    ///
+    pub fn dump_mocks(&self) -> Vec<String> {
+        let state = self.state.read().unwrap();
+
+        state
+            .mocks
+            .iter()
+            .map(|mock| mock.inner.to_string())
+            .collect()
+    }
+
+    ///
+    /// Returns a summary of every mock registered on the server, in the order they were
+    /// created: its description, the number of hits and the expected minimum and maximum
+    /// number of hits, if any. Useful for building test reports.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// s.mock("GET", "/hello").expect_at_least(2).create();
+    ///
+    /// for (mock, hits, min, max) in s.mock_report() {
+    ///     println!("{} was hit {} times (expected {:?}..={:?})", mock, hits, min, max);
+    /// }
+    /// ```
+    ///
+    pub fn mock_report(&self) -> Vec<(String, usize, Option<usize>, Option<usize>)> {
+        let state = self.state.read().unwrap();
+
+        state
+            .mocks
+            .iter()
+            .map(|mock| {
+                (
+                    mock.inner.to_string(),
+                    mock.inner.hits,
+                    mock.inner.expected_hits_at_least,
+                    mock.inner.expected_hits_at_most,
+                )
+            })
+            .collect()
+    }
+
+    ///
+    /// Asserts that the mocks registered on this server were hit in the order they were created.
+    ///
+    /// A mock may be hit multiple times in a row, but once a mock was hit, none of the mocks
+    /// created before it may be hit again. Mocks that were never hit are ignored.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// let _login = s.mock("POST", "/login").create();
+    /// let _logout = s.mock("POST", "/logout").create();
+    ///
+    /// // Calls to POST /login followed by POST /logout will pass this assertion,
+    /// // while the reverse order would panic
+    /// s.assert_mocks_hit_in_order();
+    /// ```
+    ///
+    #[track_caller]
+    pub fn assert_mocks_hit_in_order(&self) {
+        let state = self.state.read().unwrap();
+        let mut last_index: Option<usize> = None;
+
+        for mock_id in state.requests.iter().filter_map(|r| r.mock_id.as_ref()) {
+            let Some(index) = state.creation_index(mock_id) else {
+                continue;
+            };
+
+            if let Some(last_index) = last_index {
+                if index < last_index {
+                    panic!(
+                        "\n> Expected mocks to be hit in the order they were created, but:\n{}\n...was hit after:\n{}\n",
+                        state.mocks[index].inner, state.mocks[last_index].inner
+                    );
+                }
+            }
+
+            last_index = Some(index);
+        }
+    }
+
+    ///
+    /// Returns copies of the requests matched by the given mock, in the order they were
+    /// received, including their bodies.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// let m = s.mock("POST", "/users").create();
+    ///
+    /// // After performing the requests
+    /// for request in s.requests_for(&m) {
+    ///     println!("{:?}", request.utf8_lossy_body());
+    /// }
+    /// ```
+    ///
+    pub fn requests_for(&self, mock: &Mock) -> Vec<Request> {
+        let state = self.state.read().unwrap();
+
+        state
+            .requests
+            .iter()
+            .filter(|received| received.mock_id.as_deref() == Some(mock.id()))
+            .map(|received| received.request.clone())
+            .collect()
+    }
+
+    ///
+    /// Asserts that the given mocks were first hit in the given order.
+    ///
+    /// Only the first hit of every mock is taken into account, so mocks may be hit again later on.
+    /// Panics if any of the mocks was never hit.
+    ///
+    /// ## Example
+    ///
+    /// ```no_run
+    /// let mut s = mockito::Server::new();
+    ///
+    /// let auth = s.mock("POST", "/auth").create();
+    /// let fetch = s.mock("GET", "/data").create();
+    /// let logout = s.mock("POST", "/logout").create();
+    ///
+    /// // After performing the requests
+    /// s.assert_sequence(&[&auth, &fetch, &logout]);
+    /// ```
+    ///
+    #[track_caller]
+    pub fn assert_sequence(&self, mocks: &[&Mock]) {
+        let state = self.state.read().unwrap();
+        let first_hit = |mock: &Mock| {
+            state
+                .requests
+                .iter()
+                .position(|received| received.mock_id.as_deref() == Some(mock.id()))
+        };
+
+        let mut message = String::from("\n> Expected mocks to be hit in the following order:\n");
+        for mock in mocks {
+            message.push_str(&mock.to_string());
+        }
+
+        let mut last_hit = None;
+        for mock in mocks {
+            let Some(hit) = first_hit(mock) else {
+                message.push_str(&format!("\n...but this mock was never hit:\n{}\n", mock));
+                panic!("{}", message);
+            };
+
+            if last_hit.is_some_and(|last_hit| hit < last_hit) {
+                let mut observed: Vec<&&Mock> = mocks.iter().collect();
+                observed.sort_by_key(|mock| first_hit(mock));
+
+                message.push_str("\n...but they were hit in this order:\n");
+                for mock in observed {
+                    message.push_str(&mock.to_string());
+                }
+                panic!("{}", message);
+            }
+
+            last_hit = Some(hit);
+        }
+    }
+
+    ///
+    /// Asserts that the server didn't receive the same request more than once.
+    ///
+    /// Two requests are considered duplicates when their method, path, query, headers and body
+    /// are identical. Use this to verify that clients don't resend requests.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// let mut s = mockito::Server::new();
+    ///
+    /// s.mock("POST", "/payments").create();
+    ///
+    /// // Sending the same payment twice will make this assertion panic
+    /// s.assert_no_duplicate_requests();
+    /// ```
+    ///
+    #[track_caller]
+    pub fn assert_no_duplicate_requests(&self) {
+        let state = self.state.read().unwrap();
+
+        for (index, received) in state.requests.iter().enumerate() {
+            let duplicate = state.requests[index + 1..]
+                .iter()
+                .any(|other| received.request.is_identical(&other.request));
+
+            if duplicate {
+                panic!(
+                    "\n> Expected no duplicate requests, but the following request was received more than once:\n{}\n",
+                    received.request.formatted()
+                );
+            }
+        }
+    }
+
+    ///
+    /// Asserts that at least one of the requests received by the server has a body matching
+    /// the given matcher, regardless of the mock that handled it.
+    ///
+    /// ## Example
+    ///
+    /// ```no_run
+    /// use mockito::Matcher;
+    /// use serde_json::json;
+    ///
+    /// let mut s = mockito::Server::new();
+    ///
+    /// s.mock("POST", "/users").create();
+    ///
+    /// // Will panic unless one of the requests had a JSON body including this object
+    /// s.assert_any_request_matches(Matcher::PartialJson(json!({"name": "alice"})));
+    /// ```
+    ///
+    #[track_caller]
+    pub fn assert_any_request_matches(&self, matcher: Matcher) {
+        let state = self.state.read().unwrap();
+
+        let matched = state.requests.iter().any(|received| {
+            received
+                .request
+                .body()
+                .map(|body| matcher.matches_body(body, state.max_matcher_depth))
+                .unwrap_or(false)
+        });
+
+        if !matched {
+            let mut message = format!(
+                "\n> Expected a request with a body matching:\n{}\n\n...but received:\n",
+                matcher
+            );
+            for received in state.requests.iter() {
+                message.push_str(&received.request.formatted());
+                message.push('\n');
+            }
+            panic!("{}", message);
+        }
+    }
+
+    ///
+    /// Same as `Server::reset` but async.
+    ///
+    /// When a request currently holds the server state, waiting for it happens on a separate
+    /// thread, which wakes up the returned future once the state was cleared. The current
+    /// thread keeps running other tasks meanwhile, which makes it safe to call from within a
+    /// single-threaded runtime (e.g. `#[tokio::test]`). Works with any async executor.
+    ///
+    pub async fn reset_async(&mut self) {
+        if let Ok(mut state) = self.state.try_write() {
+            return state.clear();
+        }
+
+        let state = self.state.clone();
+        let (cleared_sender, cleared_receiver) = tokio::sync::oneshot::channel();
+        thread::spawn(move || {
+            state.write().unwrap().clear();
+            let _ = cleared_sender.send(());
+        });
+
+        cleared_receiver
+            .await
+            .map_err(|_| {
+                Error::new_with_context(ErrorKind::Deadlock, "the server state is poisoned")
+            })
+            .unwrap();
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.reset();
+    }
+}
+
+impl fmt::Display for Server {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&format!("server {}", self.host_with_port()))
+    }
+}
+
+type BoxError = Box<dyn StdError + Send + Sync>;
+
+enum Body {
+    Once(Option<Bytes>),
+    Wrap(http_body_util::combinators::UnsyncBoxBody<Bytes, BoxError>),
+    Delayed(Option<Pin<Box<Sleep>>>, Box<Body>),
+    Trailers(Box<Body>, Option<HeaderMap>),
+    InFlight(Box<Body>, Option<InFlightRequest>),
+}
+
+impl Body {
+    fn empty() -> Self {
+        Self::Once(None)
+    }
+
+    fn from_data_stream<S>(stream: S) -> Self
+    where
+        S: TryStream<Ok = Bytes> + Send + 'static,
+        S::Error: Into<BoxError>,
+    {
+        let body = StreamBody::new(stream.map_ok(Frame::data).map_err(Into::into)).boxed_unsync();
+        Self::Wrap(body)
+    }
+
+    fn chunked(bytes: Bytes) -> Self {
+        Self::from_data_stream(futures_util::stream::iter([Ok::<_, BoxError>(bytes)]))
+    }
+
+    /// Holds back the first frame of the body until the given delay has passed
+    fn delay_first_frame(self, delay: Duration) -> Self {
+        Self::Delayed(Some(Box::pin(tokio::time::sleep(delay))), Box::new(self))
+    }
+
+    /// Paces the data frames of the body to roughly the given amount of bytes per second
+    fn throttle(self, bytes_per_second: usize) -> Self {
+        let bytes_per_second = bytes_per_second.max(1);
+        // small enough chunks for the pace to look steady
+        let chunk_size = (bytes_per_second / 10).max(1);
+
+        let stream = futures_util::stream::unfold(
+            (self, Bytes::new()),
+            move |(mut body, mut pending)| async move {
+                while pending.is_empty() {
+                    match body.frame().await? {
+                        Ok(frame) => pending = frame.into_data().unwrap_or_default(),
+                        Err(err) => return Some((Err(err), (body, pending))),
+                    }
+                }
+
+                let chunk = pending.split_to(chunk_size.min(pending.len()));
+                let pause = chunk.len() as f64 / bytes_per_second as f64;
+                tokio::time::sleep(Duration::from_secs_f64(pause)).await;
+
+                Some((Ok(chunk), (body, pending)))
+            },
+        );
+
+        Self::from_data_stream(stream)
+    }
+
+    /// Sends the given trailers once the body is done
+    fn with_trailers(self, trailers: HeaderMap) -> Self {
+        Self::Trailers(Box::new(self), Some(trailers))
+    }
+
+    /// Holds on to the given request until the body is done
+    fn in_flight(self, request: InFlightRequest) -> Self {
+        Self::InFlight(Box::new(self), Some(request))
+    }
+
+    /// The body contents, unless they are streamed
+    fn bytes(&self) -> Option<Bytes> {
+        match self {
+            Self::Once(bytes) => Some(bytes.clone().unwrap_or_default()),
+            Self::Wrap(_) => None,
+            Self::Delayed(_, body) => body.bytes(),
+            Self::Trailers(body, _) => body.bytes(),
+            Self::InFlight(body, _) => body.bytes(),
+        }
+    }
+}
+
+impl From<Bytes> for Body {
+    fn from(bytes: Bytes) -> Self {
+        if bytes.is_empty() {
+            Self::empty()
+        } else {
+            Self::Once(Some(bytes))
+        }
+    }
+}
+
+impl HttpBody for Body {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        match self.as_mut().get_mut() {
+            Self::Once(val) => Poll::Ready(Ok(val.take().map(Frame::data)).transpose()),
+            Self::Wrap(body) => Poll::Ready(ready!(Pin::new(body).poll_frame(cx))),
+            Self::Delayed(delay, body) => {
+                if let Some(sleep) = delay {
+                    ready!(sleep.as_mut().poll(cx));
+                    *delay = None;
+                }
+                Pin::new(body.as_mut()).poll_frame(cx)
+            }
+            Self::Trailers(body, trailers) => {
+                match ready!(Pin::new(body.as_mut()).poll_frame(cx)) {
+                    None => Poll::Ready(
+                        trailers
+                            .take()
+                            .map(|trailers| Ok(Frame::trailers(trailers))),
+                    ),
+                    frame => Poll::Ready(frame),
+                }
+            }
+            Self::InFlight(body, request) => {
+                let frame = ready!(Pin::new(body.as_mut()).poll_frame(cx));
+                if frame.is_none() {
+                    request.take();
+                }
+                Poll::Ready(frame)
+            }
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match self {
+            Self::Once(None) => SizeHint::with_exact(0),
+            Self::Once(Some(bytes)) => SizeHint::with_exact(bytes.len() as u64),
+            Self::Wrap(body) => body.size_hint(),
+            Self::Delayed(_, body) => body.size_hint(),
+            Self::Trailers(body, _) => body.size_hint(),
+            Self::InFlight(body, _) => body.size_hint(),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match self {
             Self::Once(None) => true,
             Self::Once(Some(bytes)) => bytes.is_empty(),
             Self::Wrap(body) => body.is_end_stream(),
+            Self::Delayed(_, body) => body.is_end_stream(),
+            Self::Trailers(body, trailers) => body.is_end_stream() && trailers.is_none(),
+            Self::InFlight(body, _) => body.is_end_stream(),
         }
     }
 }
@@ -521,17 +1727,99 @@ impl HttpBody for Body {
 async fn handle_request(
     hyper_request: HttpRequest<Incoming>,
     state: Arc<RwLock<State>>,
+    connection: Connection,
+    opts: Arc<ServerOpts>,
 ) -> Result<Response<Body>, Error> {
-    let mut request = Request::new(hyper_request);
-    request.read_body().await;
+    let mut request = Request::new(
+        hyper_request,
+        connection.local_addr(),
+        connection.remote_addr(),
+    );
+    request.connection_request_index = connection.next_request_index();
+    if opts
+        .max_headers
+        .is_some_and(|max| request.header_count() > max)
+        || opts
+            .max_header_bytes
+            .is_some_and(|max| request.header_bytes() > max)
+    {
+        // recorded as unmatched, so the rejected request shows up in failed assertions
+        let response = respond_with_header_fields_too_large();
+        state.write().unwrap().requests.push(ReceivedRequest {
+            request,
+            mock_id: None,
+            response: Some(SentResponse {
+                status: StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+                body: Some(Bytes::new()),
+            }),
+        });
+        return response;
+    }
+    match opts.request_timeout {
+        Some(timeout) => {
+            if tokio::time::timeout(timeout, request.read_body())
+                .await
+                .is_err()
+            {
+                // recorded as unmatched, without the partially received body
+                let response = respond_with_request_timeout();
+                state.write().unwrap().requests.push(ReceivedRequest {
+                    request,
+                    mock_id: None,
+                    response: Some(SentResponse {
+                        status: StatusCode::REQUEST_TIMEOUT,
+                        body: Some(Bytes::new()),
+                    }),
+                });
+                return response;
+            }
+        }
+        None => {
+            request.read_body().await;
+        }
+    }
+    if opts.decode_request_body {
+        request.decode_body();
+    }
+    request.elapsed_since_connect = connection.elapsed();
+
+    let request_rewriter = state.read().unwrap().request_rewriter.clone();
+    if let Some(RequestRewriter(rewrite)) = request_rewriter {
+        rewrite(&mut request);
+    }
+
     log::debug!("Request received: {}", request.formatted());
 
+    let request_hook = state.read().unwrap().request_hook.clone();
+    if let Some(RequestHook(hook)) = request_hook {
+        hook(&request);
+    }
+
+    let fault = state.read().unwrap().fault.clone().filter(Fault::triggers);
+    if let Some(fault) = fault {
+        log::debug!("Fault injected");
+        // recorded as unmatched, since faults apply before matching
+        let response = respond_with_fault(&fault);
+        state.write().unwrap().requests.push(ReceivedRequest {
+            request,
+            mock_id: None,
+            response: Some(SentResponse {
+                status: fault.status,
+                body: Some(Bytes::new()),
+            }),
+        });
+        return response;
+    }
+
+    let default_headers = state.read().unwrap().default_headers.clone();
+
     let mutex = state.clone();
     let mut state = mutex.write().unwrap();
     let mut matching_mocks: Vec<&mut RemoteMock> = vec![];
+    let max_depth = state.max_matcher_depth;
 
     for mock in state.mocks.iter_mut() {
-        if mock.matches(&mut request) {
+        if mock.matches(&mut request, max_depth) {
             matching_mocks.push(mock);
         }
     }
@@ -546,48 +1834,245 @@ async fn handle_request(
     if let Some(mock) = mock {
         log::debug!("Mock found");
         mock.inner.hits += 1;
-        respond_with_mock(request, mock)
+        let mock_id = mock.inner.id.clone();
+        request.path_params = mock.inner.path.path_params(request.path_and_query());
+        if mock.inner.response.reset_connection {
+            connection.reset();
+        }
+        if let Some(early_hints) = &mock.inner.response.early_hints {
+            send_early_hints(&request, &connection, early_hints);
+        }
+        let response = match mock.inner.response.websocket.clone() {
+            Some(handler) => respond_with_websocket(&mut request, handler),
+            None => respond_with_mock(&request, mock, &default_headers),
+        };
+        let sent_response = response.as_ref().ok().map(|response| SentResponse {
+            status: response.status(),
+            body: response.body().bytes(),
+        });
+        if let (Some(ResponseHook(hook)), Some(sent_response)) =
+            (&state.response_hook, &sent_response)
+        {
+            hook(&request, sent_response.status.as_u16() as usize);
+        }
+        state.requests.push(ReceivedRequest {
+            request,
+            mock_id: Some(mock_id),
+            response: sent_response,
+        });
+        response
     } else {
         log::debug!("Mock not found");
-        state.unmatched_requests.push(request);
+        state.requests.push(ReceivedRequest {
+            request,
+            mock_id: None,
+            response: None,
+        });
         respond_with_mock_not_found()
     }
 }
 
-fn respond_with_mock(request: Request, mock: &RemoteMock) -> Result<Response<Body>, Error> {
-    let status: StatusCode = mock.inner.response.status;
+fn respond_with_mock(
+    request: &Request,
+    mock: &RemoteMock,
+    default_headers: &HeaderMap<Header>,
+) -> Result<Response<Body>, Error> {
+    if mock.inner.response.reset_connection {
+        return Err(Error::new_with_context(
+            ErrorKind::ResponseFailure,
+            "connection reset",
+        ));
+    }
+
+    let status: StatusCode = match (
+        &mock.inner.response.status_fn,
+        &mock.inner.response.random_status,
+    ) {
+        (Some(status_fn), _) => status_fn.status(request)?,
+        (None, Some(random_status)) => random_status.next(),
+        (None, None) => mock.inner.response.status,
+    };
     let mut response = Response::builder().status(status);
 
-    for (name, value) in mock.inner.response.headers.iter() {
+    let defaults = default_headers
+        .iter()
+        .filter(|(name, _)| !mock.inner.response.headers.contains_key(*name));
+    for (name, value) in mock.inner.response.headers.iter().chain(defaults) {
         match value {
             Header::String(value) => response = response.header(name, value),
             Header::FnWithRequest(header_fn) => {
-                response = response.header(name, header_fn(&request))
+                response = response.header(name, header_fn(request))
             }
         }
     }
 
+    if !mock.inner.response.trailers.is_empty() {
+        let names: Vec<&str> = mock
+            .inner
+            .response
+            .trailers
+            .keys()
+            .map(|name| name.as_str())
+            .collect();
+        response = response.header(TRAILER, names.join(", "));
+    }
+
+    if !mock.inner.response.headers.contains_key(CONNECTION) {
+        if let Some(connection) = connection_header(request) {
+            response = response.header(CONNECTION, connection);
+        }
+    }
+
+    let throttle = mock.inner.response.throttle;
+    let transfer_encoding = match throttle {
+        Some(_) => Some(TransferEncoding::Chunked),
+        None => mock.inner.response.transfer_encoding,
+    };
+    let content_encoding = if mock.inner.response.auto_encoding
+        && !mock.inner.response.headers.contains_key(CONTENT_ENCODING)
+    {
+        response = response.header(VARY, "accept-encoding");
+        request
+            .header(ACCEPT_ENCODING)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .find_map(ContentEncoding::negotiate)
+    } else {
+        None
+    };
     let body = if request.method() != "HEAD" {
         match &mock.inner.response.body {
             ResponseBody::Bytes(bytes) => {
-                if !request.has_header("content-length") {
-                    response = response.header("content-length", bytes.len());
-                }
-                Body::from(bytes.to_owned())
+                let (builder, body) = static_body(
+                    response,
+                    request,
+                    bytes.to_owned(),
+                    transfer_encoding,
+                    content_encoding,
+                )?;
+                response = builder;
+                body
+            }
+            ResponseBody::Lazy(lazy_body) => {
+                let (builder, body) = static_body(
+                    response,
+                    request,
+                    lazy_body.bytes(),
+                    transfer_encoding,
+                    content_encoding,
+                )?;
+                response = builder;
+                body
+            }
+            ResponseBody::Sequence(sequence) => {
+                let (builder, body) = static_body(
+                    response,
+                    request,
+                    sequence.next(),
+                    transfer_encoding,
+                    content_encoding,
+                )?;
+                response = builder;
+                body
             }
             ResponseBody::FnWithWriter(body_fn) => {
-                let stream = ChunkedStream::new(Arc::clone(body_fn))?;
+                if transfer_encoding == Some(TransferEncoding::Identity) {
+                    let mut buffer = vec![];
+                    body_fn(&mut buffer).map_err(|err| {
+                        Error::new_with_context(ErrorKind::ResponseBodyFailure, err)
+                    })?;
+                    response = response.header("content-length", buffer.len());
+                    Body::from(Bytes::from(buffer))
+                } else {
+                    let stream = ChunkedStream::new(Arc::clone(body_fn))?;
+                    Body::from_data_stream(stream)
+                }
+            }
+            ResponseBody::File(path) => {
+                let metadata = std::fs::metadata(path)
+                    .map_err(|err| Error::new_with_context(ErrorKind::FileNotFound, err))?;
+                if transfer_encoding != Some(TransferEncoding::Chunked) {
+                    response = response.header("content-length", metadata.len());
+                }
+
+                let path = path.clone();
+                let stream = ChunkedStream::new(Arc::new(move |writer| {
+                    let mut file = std::fs::File::open(&path)?;
+                    io::copy(&mut file, writer).map(|_| ())
+                }))?;
+                Body::from_data_stream(stream)
+            }
+            ResponseBody::Partial(bytes, then_close) => {
+                let partial = futures_util::stream::iter([Ok::<_, BoxError>(bytes.to_owned())]);
+                if *then_close {
+                    // yield once so that the partial body gets flushed before aborting
+                    let abort = futures_util::stream::once(async {
+                        tokio::task::yield_now().await;
+                        Err(io::Error::new(io::ErrorKind::ConnectionAborted, "partial body").into())
+                    });
+                    Body::from_data_stream(partial.chain(abort))
+                } else {
+                    Body::from_data_stream(partial.chain(futures_util::stream::pending()))
+                }
+            }
+            ResponseBody::Backpressure(backpressure) => {
+                if transfer_encoding == Some(TransferEncoding::Identity) {
+                    response = response.header("content-length", backpressure.len());
+                }
+
+                // the next chunk is only requested once the connection is done writing the
+                // previous one, so a slow client holds back the whole stream
+                backpressure.reset();
+                let backpressure = Arc::clone(backpressure);
+                let stream = futures_util::stream::unfold(0, move |index| {
+                    let chunk = backpressure.chunk(index);
+                    async move { chunk.map(|chunk| (Ok::<_, BoxError>(chunk), index + 1)) }
+                });
                 Body::from_data_stream(stream)
             }
             ResponseBody::FnWithRequest(body_fn) => {
-                let bytes = body_fn(&request);
-                Body::from(bytes)
+                let bytes = body_fn(request);
+                if transfer_encoding == Some(TransferEncoding::Chunked) {
+                    Body::chunked(bytes)
+                } else {
+                    Body::from(bytes)
+                }
+            }
+            ResponseBody::FnWithRequestAsync(body_fn) => {
+                // awaited as part of the body stream, so the state lock isn't held meanwhile
+                let future = body_fn(request);
+                let stream =
+                    futures_util::stream::once(async move { Ok::<_, BoxError>(future.await) });
+                Body::from_data_stream(stream)
             }
         }
     } else {
         Body::empty()
     };
 
+    let body = match throttle {
+        Some(bytes_per_second) => body.throttle(bytes_per_second),
+        None => body,
+    };
+
+    let body = match mock.inner.response.first_byte_delay {
+        Some(delay) => body.delay_first_frame(delay),
+        None => body,
+    };
+
+    let trailers = &mock.inner.response.trailers;
+    let body = if trailers.is_empty() {
+        body
+    } else {
+        let mut values = HeaderMap::new();
+        for (name, value) in trailers.iter() {
+            let value = HeaderValue::from_str(value)
+                .map_err(|err| Error::new_with_context(ErrorKind::ResponseFailure, err))?;
+            values.append(name, value);
+        }
+        body.with_trailers(values)
+    };
+
     let response = response
         .body(body)
         .map_err(|err| Error::new_with_context(ErrorKind::ResponseFailure, err))?;
@@ -595,6 +2080,145 @@ fn respond_with_mock(request: Request, mock: &RemoteMock) -> Result<Response<Bod
     Ok(response)
 }
 
+fn respond_with_websocket(
+    request: &mut Request,
+    handler: WebSocketHandler,
+) -> Result<Response<Body>, Error> {
+    let is_upgrade = request.header(UPGRADE).iter().any(|value| {
+        value
+            .to_str()
+            .is_ok_and(|value| value.eq_ignore_ascii_case("websocket"))
+    });
+    let key = request
+        .header(SEC_WEBSOCKET_KEY)
+        .first()
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    let (true, Some(key)) = (is_upgrade, key) else {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::empty())
+            .map_err(|err| Error::new_with_context(ErrorKind::ResponseFailure, err));
+    };
+
+    let on_upgrade = request.upgrade();
+    spawn_local(async move {
+        match on_upgrade.await {
+            Ok(upgraded) => {
+                if let Err(err) = websocket::serve(TokioIo::new(upgraded), handler).await {
+                    log::debug!("WebSocket connection failed: {}", err);
+                }
+            }
+            Err(err) => log::debug!("WebSocket upgrade failed: {}", err),
+        }
+    });
+
+    Response::builder()
+        .status(StatusCode::SWITCHING_PROTOCOLS)
+        .header(UPGRADE, "websocket")
+        .header(CONNECTION, "Upgrade")
+        .header(SEC_WEBSOCKET_ACCEPT, websocket::accept_key(&key))
+        .body(Body::empty())
+        .map_err(|err| Error::new_with_context(ErrorKind::ResponseFailure, err))
+}
+
+fn send_early_hints(request: &Request, connection: &Connection, headers: &HeaderMap<String>) {
+    if request.version() != Version::HTTP_11 {
+        return;
+    }
+
+    let mut interim = String::from("HTTP/1.1 103 Early Hints\r\n");
+    for (name, value) in headers {
+        interim.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    interim.push_str("\r\n");
+
+    connection.write_interim(interim.as_bytes());
+}
+
+fn static_body(
+    mut response: ResponseBuilder,
+    request: &Request,
+    bytes: Bytes,
+    transfer_encoding: Option<TransferEncoding>,
+    content_encoding: Option<ContentEncoding>,
+) -> Result<(ResponseBuilder, Body), Error> {
+    let bytes = match content_encoding {
+        Some(encoding) => {
+            response = response.header(CONTENT_ENCODING, encoding.name());
+            encoding
+                .encode(&bytes)
+                .map(Bytes::from)
+                .map_err(|err| Error::new_with_context(ErrorKind::ResponseBodyFailure, err))?
+        }
+        None => bytes,
+    };
+
+    if transfer_encoding == Some(TransferEncoding::Chunked) {
+        return Ok((response, Body::chunked(bytes)));
+    }
+
+    if !request.has_header("content-length") {
+        response = response.header("content-length", bytes.len());
+    }
+
+    Ok((response, Body::from(bytes)))
+}
+
+/// Picks the `Connection` header according to the HTTP version of the request: HTTP/1.0
+/// connections are closed unless the client asks for keep-alive, while HTTP/1.1 connections
+/// are kept alive unless the client asks for them to be closed
+fn connection_header(request: &Request) -> Option<&'static str> {
+    let requested = |token: &str| {
+        request.header(CONNECTION).iter().any(|value| {
+            value.to_str().is_ok_and(|value| {
+                value
+                    .split(',')
+                    .any(|option| option.trim().eq_ignore_ascii_case(token))
+            })
+        })
+    };
+
+    match request.version() {
+        Version::HTTP_10 if requested("keep-alive") => Some("keep-alive"),
+        Version::HTTP_10 => Some("close"),
+        Version::HTTP_11 if requested("close") => Some("close"),
+        _ => None,
+    }
+}
+
+fn respond_with_request_timeout() -> Result<Response<Body>, Error> {
+    let response = Response::builder()
+        .status(StatusCode::REQUEST_TIMEOUT)
+        .header(CONNECTION, "close")
+        .body(Body::empty())
+        .map_err(|err| Error::new_with_context(ErrorKind::ResponseFailure, err))?;
+
+    Ok(response)
+}
+
+fn respond_with_fault(fault: &Fault) -> Result<Response<Body>, Error> {
+    let mut response = Response::builder().status(fault.status);
+    for (name, value) in fault.headers.iter() {
+        response = response.header(name, value);
+    }
+
+    response
+        .body(Body::empty())
+        .map_err(|err| Error::new_with_context(ErrorKind::ResponseFailure, err))
+}
+
+fn respond_with_header_fields_too_large() -> Result<Response<Body>, Error> {
+    let response = Response::builder()
+        .status(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE)
+        .header(CONNECTION, "close")
+        .body(Body::empty())
+        .map_err(|err| Error::new_with_context(ErrorKind::ResponseFailure, err))?;
+
+    Ok(response)
+}
+
 fn respond_with_mock_not_found() -> Result<Response<Body>, Error> {
     let response = Response::builder()
         .status(StatusCode::NOT_IMPLEMENTED)
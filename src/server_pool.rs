@@ -1,17 +1,142 @@
+use crate::server::State;
 use crate::{Error, ErrorKind};
 use crate::{Server, ServerOpts};
 use std::collections::VecDeque;
 use std::ops::{Deref, DerefMut, Drop};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex, OnceLock, RwLock, Weak};
 use tokio::sync::{Semaphore, SemaphorePermit};
 
 // macOS has small default ulimits. Sync it with test_server_pool()
 const DEFAULT_POOL_SIZE: usize = if cfg!(target_os = "macos") { 20 } else { 50 };
-pub(crate) static SERVER_POOL: ServerPool = ServerPool::new(DEFAULT_POOL_SIZE);
+const DEFAULT_POOL_HOST: &str = "127.0.0.1";
+static SERVER_POOL: OnceLock<ServerPool> = OnceLock::new();
+// the configuration is only read once, when the pool is first used
+static POOL_CONFIG: Mutex<PoolConfig> = Mutex::new(PoolConfig {
+    size: DEFAULT_POOL_SIZE,
+    host: DEFAULT_POOL_HOST,
+});
+
+struct PoolConfig {
+    size: usize,
+    host: &'static str,
+}
+
+pub(crate) fn server_pool() -> &'static ServerPool {
+    SERVER_POOL.get_or_init(|| {
+        let config = POOL_CONFIG.lock().unwrap();
+        ServerPool::new(config.size, config.host)
+    })
+}
+
+fn configure_pool(configure: impl FnOnce(&mut PoolConfig)) -> Result<(), Error> {
+    let mut config = POOL_CONFIG.lock().unwrap();
+    if SERVER_POOL.get().is_some() {
+        return Err(Error::new_with_context(
+            ErrorKind::InvalidPoolConfiguration,
+            "the server pool is already in use",
+        ));
+    }
+
+    configure(&mut config);
+    Ok(())
+}
+
+///
+/// Sets the maximum number of servers kept by the server pool, which otherwise defaults
+/// to 50 (or 20 on macOS). Once the limit is reached, `Server::new` blocks until one of the
+/// servers in use is dropped.
+///
+/// This needs to be called **before the first call** to `Server::new` or `Server::new_async`.
+/// Once the pool is in use, its size can't be changed anymore and an error is returned.
+///
+/// ## Example
+///
+/// ```
+/// mockito::set_pool_size(100).unwrap();
+///
+/// let server = mockito::Server::new();
+/// ```
+///
+pub fn set_pool_size(size: usize) -> Result<(), Error> {
+    if size == 0 {
+        return Err(Error::new_with_context(
+            ErrorKind::InvalidPoolConfiguration,
+            "the pool size should be greater than zero",
+        ));
+    }
+
+    configure_pool(|config| config.size = size)
+}
+
+///
+/// Sets the host the pooled servers bind to, which otherwise defaults to `127.0.0.1`.
+/// Useful e.g. when the servers need to be reachable from other containers (`0.0.0.0`).
+///
+/// This needs to be called **before the first call** to `Server::new` or `Server::new_async`.
+/// Once the pool is in use, its host can't be changed anymore and an error is returned.
+///
+/// ## Example
+///
+/// ```
+/// mockito::set_pool_host("0.0.0.0").unwrap();
+///
+/// let server = mockito::Server::new();
+/// ```
+///
+pub fn set_pool_host(host: &'static str) -> Result<(), Error> {
+    configure_pool(|config| config.host = host)
+}
+
+///
+/// Discards all the idle servers stored in the server pool, so that subsequent calls to
+/// `Server::new` start fresh servers.
+///
+/// Servers which are currently in use are not affected and will be returned to the pool
+/// once their `ServerGuard` is dropped.
+///
+/// ## Example
+///
+/// ```
+/// let server = mockito::Server::new();
+/// drop(server);
+///
+/// mockito::reset_pool();
+///
+/// // This server was not used before
+/// let server = mockito::Server::new();
+/// ```
+///
+pub fn reset_pool() {
+    server_pool().reset();
+}
+
+///
+/// Removes the mocks and recorded requests from every server created by the server pool,
+/// including the servers which are currently in use. Useful as a global cleanup step, e.g.
+/// between test modules.
+///
+/// Unlike `reset_pool`, the servers keep running and keep their addresses. Mocks removed
+/// from servers in use will fail their assertions.
+///
+/// ## Example
+///
+/// ```
+/// let mut server = mockito::Server::new();
+/// server.mock("GET", "/").create();
+///
+/// mockito::reset_all_pooled_servers();
+///
+/// assert!(server.dump_mocks().is_empty());
+/// ```
+///
+pub fn reset_all_pooled_servers() {
+    server_pool().reset_all();
+}
 
 ///
 /// A handle around a pooled `Server` object which dereferences to `Server`.
 ///
+#[derive(Debug)]
 pub struct ServerGuard {
     server: Option<Server>,
     _permit: SemaphorePermit<'static>,
@@ -45,7 +170,7 @@ impl Drop for ServerGuard {
         if let Some(server) = self.server.take() {
             // the permit is still held when recycling,
             // so the next acquire will already see the recycled server
-            SERVER_POOL.recycle(server);
+            server_pool().recycle(server);
         }
     }
 }
@@ -53,13 +178,18 @@ impl Drop for ServerGuard {
 pub(crate) struct ServerPool {
     semaphore: Semaphore,
     free_list: Mutex<VecDeque<Server>>,
+    // the state of every server created by the pool, in use or not
+    states: Mutex<Vec<Weak<RwLock<State>>>>,
+    host: &'static str,
 }
 
 impl ServerPool {
-    const fn new(max_size: usize) -> ServerPool {
+    fn new(max_size: usize, host: &'static str) -> ServerPool {
         ServerPool {
-            semaphore: Semaphore::const_new(max_size),
+            semaphore: Semaphore::new(max_size),
             free_list: Mutex::new(VecDeque::new()),
+            states: Mutex::new(vec![]),
+            host,
         }
     }
 
@@ -75,13 +205,47 @@ impl ServerPool {
         let recycled = self.free_list.lock().unwrap().pop_front();
         let server = match recycled {
             Some(server) => server,
-            None => Server::try_new_with_opts_async(ServerOpts::default()).await?,
+            None => {
+                let opts = ServerOpts {
+                    host: self.host,
+                    ..Default::default()
+                };
+                let server = Server::try_new_with_opts_async(opts).await?;
+                self.states
+                    .lock()
+                    .unwrap()
+                    .push(Arc::downgrade(server.state()));
+                server
+            }
         };
 
         Ok(ServerGuard::new(server, permit))
     }
 
+    fn reset(&self) {
+        // be careful not to stop the servers while holding the lock
+        let servers: Vec<Server> = self.free_list.lock().unwrap().drain(..).collect();
+        for mut server in servers {
+            server.stop();
+        }
+    }
+
+    fn reset_all(&self) {
+        let mut states = self.states.lock().unwrap();
+        // forget the servers stopped by `reset`
+        states.retain(|state| state.strong_count() > 0);
+
+        for state in states.iter().filter_map(Weak::upgrade) {
+            state.write().unwrap().clear();
+        }
+    }
+
     fn recycle(&self, mut server: Server) {
+        if server.is_stopped() {
+            return;
+        }
+
+        server.drain();
         server.reset();
         self.free_list.lock().unwrap().push_back(server);
     }
@@ -0,0 +1,197 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use sha1::{Digest, Sha1};
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+const CLOSE_MESSAGE_TOO_BIG: u16 = 1009;
+
+/// The maximum size of a message received from the client, across all of its frames
+const MAX_MESSAGE_SIZE: usize = 16 << 20;
+
+///
+/// A WebSocket message, as received from or sent to the client. See `Mock::with_websocket`.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WebSocketMessage {
+    /// A text message
+    Text(String),
+    /// A binary message
+    Binary(Vec<u8>),
+}
+
+type WebSocketHandlerFn = dyn Fn(WebSocketMessage) -> Option<WebSocketMessage> + Send + Sync;
+
+#[derive(Clone)]
+pub(crate) struct WebSocketHandler(Arc<WebSocketHandlerFn>);
+
+impl WebSocketHandler {
+    pub(crate) fn new(
+        handler: impl Fn(WebSocketMessage) -> Option<WebSocketMessage> + Send + Sync + 'static,
+    ) -> Self {
+        Self(Arc::new(handler))
+    }
+}
+
+impl fmt::Debug for WebSocketHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<callback>")
+    }
+}
+
+impl PartialEq for WebSocketHandler {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Computes the `Sec-WebSocket-Accept` header value for the given `Sec-WebSocket-Key`
+pub(crate) fn accept_key(key: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(key.trim().as_bytes());
+    hasher.update(ACCEPT_GUID.as_bytes());
+
+    STANDARD.encode(hasher.finalize())
+}
+
+/// Exchanges messages with the client until the connection is closed
+pub(crate) async fn serve<S>(mut stream: S, handler: WebSocketHandler) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut message: Option<(u8, Vec<u8>)> = None;
+
+    loop {
+        let buffered = message.as_ref().map_or(0, |(_, data)| data.len());
+        let (fin, opcode, payload) =
+            match read_frame(&mut stream, MAX_MESSAGE_SIZE - buffered).await {
+                Ok(Some(frame)) => frame,
+                Ok(None) => {
+                    let code = CLOSE_MESSAGE_TOO_BIG.to_be_bytes();
+                    write_frame(&mut stream, OPCODE_CLOSE, &code).await?;
+                    return stream.shutdown().await;
+                }
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(err) => return Err(err),
+            };
+
+        match opcode {
+            OPCODE_CLOSE => {
+                write_frame(&mut stream, OPCODE_CLOSE, &payload).await?;
+                return stream.shutdown().await;
+            }
+            OPCODE_PING => write_frame(&mut stream, OPCODE_PONG, &payload).await?,
+            OPCODE_PONG => {}
+            OPCODE_TEXT | OPCODE_BINARY => message = Some((opcode, payload)),
+            OPCODE_CONTINUATION => {
+                if let Some((_, ref mut data)) = message {
+                    data.extend_from_slice(&payload);
+                }
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "unknown websocket opcode",
+                ))
+            }
+        }
+
+        if !fin || !matches!(opcode, OPCODE_TEXT | OPCODE_BINARY | OPCODE_CONTINUATION) {
+            continue;
+        }
+
+        let Some((opcode, data)) = message.take() else {
+            continue;
+        };
+
+        let received = if opcode == OPCODE_TEXT {
+            WebSocketMessage::Text(String::from_utf8_lossy(&data).into_owned())
+        } else {
+            WebSocketMessage::Binary(data)
+        };
+
+        match (handler.0)(received) {
+            Some(WebSocketMessage::Text(text)) => {
+                write_frame(&mut stream, OPCODE_TEXT, text.as_bytes()).await?
+            }
+            Some(WebSocketMessage::Binary(data)) => {
+                write_frame(&mut stream, OPCODE_BINARY, &data).await?
+            }
+            None => {}
+        }
+    }
+}
+
+/// Reads the next frame from the client, or returns `None` without reading the payload if it
+/// is longer than `max_len`
+async fn read_frame<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    max_len: usize,
+) -> io::Result<Option<(bool, u8, Vec<u8>)>> {
+    let mut header = [0; 2];
+    stream.read_exact(&mut header).await?;
+
+    let fin = header[0] & 0x80 != 0;
+    let opcode = header[0] & 0x0F;
+    let masked = header[1] & 0x80 != 0;
+    let len = match header[1] & 0x7F {
+        126 => stream.read_u16().await? as u64,
+        127 => stream.read_u64().await?,
+        len => len as u64,
+    };
+
+    if len > max_len as u64 {
+        return Ok(None);
+    }
+
+    let mut mask = [0; 4];
+    if masked {
+        stream.read_exact(&mut mask).await?;
+    }
+
+    let mut payload = vec![0; len as usize];
+    stream.read_exact(&mut payload).await?;
+
+    if masked {
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+    }
+
+    Ok(Some((fin, opcode, payload)))
+}
+
+async fn write_frame<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    opcode: u8,
+    payload: &[u8],
+) -> io::Result<()> {
+    // server frames are never masked
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+
+    stream.write_all(&frame).await?;
+    stream.flush().await
+}
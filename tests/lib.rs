@@ -1,17 +1,21 @@
 #[macro_use]
 extern crate serde_json;
 
-use mockito::{Matcher, Server, ServerOpts};
+use mockito::{
+    CookieBuilder, CorsConfig, FaultConfig, Matcher, MatcherFn, MockResponse, MockSpec, SameSite,
+    Server, ServerOpts, TransferEncoding, WebSocketMessage,
+};
 use rand::distributions::Alphanumeric;
 use rand::Rng;
 use std::fmt::Display;
 use std::fs;
 use std::io::{BufRead, BufReader, Read, Write};
-use std::net::TcpStream;
+use std::net::{IpAddr, TcpStream};
 use std::path::Path;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Duration;
 
 type Binary = Vec<u8>;
 
@@ -152,6 +156,21 @@ fn test_simple_route_mock() {
     assert_eq!("world", body);
 }
 
+#[test]
+fn test_mock_method() {
+    let mut s = Server::new();
+    let from_str = s.mock("POST", "/hello").with_body("world");
+    let from_method = s
+        .mock_method(http::Method::POST, "/hello")
+        .with_body("world")
+        .create();
+    assert_eq!(from_str.to_string(), from_method.to_string());
+
+    let (status_line, _, body) = request(s.host_with_port(), "POST /hello", "");
+    assert_eq!("HTTP/1.1 200 OK\r\n", status_line);
+    assert_eq!("world", body);
+}
+
 #[test]
 fn test_two_route_mocks() {
     let mut s = Server::new();
@@ -199,6 +218,31 @@ fn test_match_header() {
     assert_eq!("hello", body_text);
 }
 
+#[test]
+fn test_match_expect() {
+    let mut s = Server::new();
+    s.mock("PUT", "/upload")
+        .match_expect("100-continue")
+        .with_body("uploaded")
+        .create();
+
+    let (status, _, _) = request_with_body(s.host_with_port(), "PUT /upload", "", "data");
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status);
+
+    let mut stream = request_stream(
+        "1.1",
+        s.host_with_port(),
+        "PUT /upload",
+        "Expect: 100-continue\r\nconnection: close\r\ncontent-length: 4\r\n",
+        "data",
+    );
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+
+    assert!(response.contains("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with("uploaded"));
+}
+
 #[test]
 fn test_match_header_is_case_insensitive_on_the_field_name() {
     let mut s = Server::new();
@@ -296,6 +340,35 @@ fn test_match_header_missing_not_matching_even_when_empty() {
     assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status);
 }
 
+#[test]
+fn test_match_header_empty() {
+    let mut s = Server::new();
+    s.mock("GET", "/")
+        .match_header("X-Flag", Matcher::Empty)
+        .create();
+
+    let (status, _, _) = request(s.host_with_port(), "GET /", "X-Flag:\r\n");
+    assert_eq!("HTTP/1.1 200 OK\r\n", status);
+
+    let (status, _, _) = request(s.host_with_port(), "GET /", "");
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status);
+
+    let (status, _, _) = request(s.host_with_port(), "GET /", "X-Flag: on\r\n");
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status);
+}
+
+#[test]
+fn test_match_body_empty() {
+    let mut s = Server::new();
+    s.mock("POST", "/").match_body(Matcher::Empty).create();
+
+    let (status, _, _) = request(s.host_with_port(), "POST /", "content-length: 0\r\n");
+    assert_eq!("HTTP/1.1 200 OK\r\n", status);
+
+    let (status, _, _) = request_with_body(s.host_with_port(), "POST /", "", "hello");
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status);
+}
+
 #[test]
 fn test_match_multiple_header_conditions_matching() {
     let mut s = Server::new();
@@ -375,6 +448,34 @@ fn test_match_binary_body() {
     assert_eq!("HTTP/1.1 200 OK\r\n", status);
 }
 
+#[test]
+fn test_match_body_by_sniffed_type() {
+    let mut s = Server::new();
+    s.mock("POST", "/")
+        .match_body(Matcher::SniffedType("image/png".to_string()))
+        .create();
+
+    let png = fs::read("./tests/files/pixel.png").unwrap();
+    let content_length_header = format!("Content-Length: {}\r\n", png.len());
+    let (status, _, _) = binary_request(s.host_with_port(), "POST /", &content_length_header, png);
+    assert_eq!("HTTP/1.1 200 OK\r\n", status);
+
+    let (status, _, _) = request_with_body(s.host_with_port(), "POST /", "", "%PDF-1.7");
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status);
+}
+
+#[test]
+fn test_match_body_by_sniffed_bmp_type() {
+    let mut s = Server::new();
+    let m = s
+        .mock("POST", "/")
+        .match_body(Matcher::SniffedType("image/bmp".to_string()));
+
+    assert!(m.would_body_match(b"BM\x3a\0\0\0\0\0\0\0\x36\0\0\0"));
+    assert!(!m.would_body_match(b"BM"));
+    assert!(!m.would_body_match(b"BMW is a car brand"));
+}
+
 #[test]
 fn test_does_not_match_binary_body() {
     let mut s = Server::new();
@@ -431,6 +532,69 @@ fn test_match_body_with_json() {
     assert_eq!("HTTP/1.1 200 OK\r\n", status);
 }
 
+#[cfg(feature = "gzip")]
+#[test]
+fn test_match_body_with_gzipped_json() {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    let opts = ServerOpts {
+        decode_request_body: true,
+        ..Default::default()
+    };
+    let mut s = Server::new_with_opts(opts);
+    s.mock("POST", "/")
+        .match_body(Matcher::Json(json!({"hello":"world", "foo": "bar"})))
+        .create();
+
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    encoder
+        .write_all(br#"{"hello":"world", "foo": "bar"}"#)
+        .unwrap();
+    let body = encoder.finish().unwrap();
+
+    let headers = format!(
+        "content-encoding: gzip\r\ncontent-length: {}\r\n",
+        body.len()
+    );
+    let (status, _, _) = binary_request(s.host_with_port(), "POST /", &headers, body);
+    assert_eq!("HTTP/1.1 200 OK\r\n", status);
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn test_match_encoded_body() {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    let mut s = Server::new();
+    s.mock("POST", "/")
+        .match_encoded_body("gzip", Matcher::Json(json!({"hello": "world"})))
+        .create();
+
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    encoder.write_all(br#"{"hello":"world"}"#).unwrap();
+    let body = encoder.finish().unwrap();
+
+    let headers = format!(
+        "content-encoding: gzip\r\ncontent-length: {}\r\n",
+        body.len()
+    );
+    let (status, _, _) = binary_request(s.host_with_port(), "POST /", &headers, body);
+    assert_eq!("HTTP/1.1 200 OK\r\n", status);
+
+    let headers = "content-encoding: identity\r\ncontent-length: 17\r\n";
+    let (status, _, _) = request_with_body(
+        s.host_with_port(),
+        "POST /",
+        headers,
+        r#"{"hello":"world"}"#,
+    );
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status);
+}
+
 #[test]
 fn test_match_body_with_more_headers_with_json() {
     let mut s = Server::new();
@@ -474,29 +638,35 @@ fn test_match_body_with_json_order() {
 }
 
 #[test]
-fn test_match_body_with_json_string() {
+fn test_match_body_with_unordered_json() {
     let mut s = Server::new();
     s.mock("POST", "/")
-        .match_body(Matcher::JsonString(
-            "{\"hello\":\"world\", \"foo\": \"bar\"}".to_string(),
-        ))
+        .match_body(Matcher::JsonUnordered(json!({"ids": [3, 2, 1]})))
         .create();
 
-    let (status, _, _) = request_with_body(
-        s.host_with_port(),
-        "POST /",
-        "",
-        r#"{"hello":"world", "foo": "bar"}"#,
-    );
+    let (status, _, _) =
+        request_with_body(s.host_with_port(), "POST /", "", r#"{"ids": [1, 2, 3]}"#);
+    assert_eq!("HTTP/1.1 200 OK\r\n", status);
+
+    let (status, _, _) =
+        request_with_body(s.host_with_port(), "POST /", "", r#"{"ids": [1, 2, 2]}"#);
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status);
+
+    let (status, _, _) = request_with_body(s.host_with_port(), "POST /", "", "ids=1,2,3");
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status);
+
+    let (status, _, _) =
+        request_with_body(s.host_with_port(), "POST /", "", r#"{"ids": [1, 2, 3]}"#);
     assert_eq!("HTTP/1.1 200 OK\r\n", status);
 }
 
 #[test]
-fn test_match_body_with_json_string_order() {
+fn test_match_body_with_json_contains_array_items() {
     let mut s = Server::new();
     s.mock("POST", "/")
-        .match_body(Matcher::JsonString(
-            "{\"foo\": \"bar\", \"hello\": \"world\"}".to_string(),
+        .match_body(Matcher::JsonContainsArrayItems(
+            "/data/tags".to_string(),
+            vec![json!("b"), json!({"id": 1})],
         ))
         .create();
 
@@ -504,45 +674,48 @@ fn test_match_body_with_json_string_order() {
         s.host_with_port(),
         "POST /",
         "",
-        r#"{"hello":"world", "foo": "bar"}"#,
+        r#"{"data": {"tags": [{"id": 1}, "c", "b", "a"]}}"#,
     );
     assert_eq!("HTTP/1.1 200 OK\r\n", status);
-}
-
-#[test]
-fn test_match_body_with_partial_json() {
-    let mut s = Server::new();
-    s.mock("POST", "/")
-        .match_body(Matcher::PartialJson(json!({"hello":"world"})))
-        .create();
 
     let (status, _, _) = request_with_body(
         s.host_with_port(),
         "POST /",
         "",
-        r#"{"hello":"world", "foo": "bar"}"#,
+        r#"{"data": {"tags": ["a", "b"]}}"#,
     );
-    assert_eq!("HTTP/1.1 200 OK\r\n", status);
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status);
+
+    let (status, _, _) = request_with_body(s.host_with_port(), "POST /", "", "tags=b");
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status);
 }
 
 #[test]
-fn test_match_body_with_partial_json_and_extra_fields() {
+fn test_match_body_with_relaxed_json() {
     let mut s = Server::new();
     s.mock("POST", "/")
-        .match_body(Matcher::PartialJson(json!({"hello":"world", "foo": "bar"})))
+        .match_body(Matcher::JsonRelaxed(json!({"a": 1, "b": 100})))
         .create();
 
     let (status, _, _) =
-        request_with_body(s.host_with_port(), "POST /", "", r#"{"hello":"world"}"#);
+        request_with_body(s.host_with_port(), "POST /", "", r#"{"a": 1.0, "b": 1e2}"#);
+    assert_eq!("HTTP/1.1 200 OK\r\n", status);
+
+    let (status, _, _) =
+        request_with_body(s.host_with_port(), "POST /", "", r#"{"a": 1.5, "b": 100}"#);
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status);
+
+    let (status, _, _) = request_with_body(s.host_with_port(), "POST /", "", "a=1&b=100");
     assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status);
 }
 
 #[test]
-fn test_match_body_with_partial_json_string() {
+fn test_match_body_with_json_ignoring_fields() {
     let mut s = Server::new();
     s.mock("POST", "/")
-        .match_body(Matcher::PartialJsonString(
-            "{\"hello\": \"world\"}".to_string(),
+        .match_body(Matcher::JsonIgnoring(
+            json!({"name": "mockito", "timestamp": 1, "meta": {"id": "a"}}),
+            vec!["timestamp".to_string(), "/meta/id".to_string()],
         ))
         .create();
 
@@ -550,98 +723,1305 @@ fn test_match_body_with_partial_json_string() {
         s.host_with_port(),
         "POST /",
         "",
-        r#"{"hello":"world", "foo": "bar"}"#,
+        r#"{"name": "mockito", "timestamp": 1700000000, "meta": {"id": "b"}}"#,
     );
     assert_eq!("HTTP/1.1 200 OK\r\n", status);
+
+    let (status, _, _) = request_with_body(
+        s.host_with_port(),
+        "POST /",
+        "",
+        r#"{"name": "other", "timestamp": 1, "meta": {"id": "a"}}"#,
+    );
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status);
+
+    let (status, _, _) = request_with_body(s.host_with_port(), "POST /", "", "name=mockito");
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status);
 }
 
 #[test]
-fn test_match_body_with_partial_json_string_and_extra_fields() {
+fn test_match_body_size() {
+    let mut s = Server::new();
+    s.mock("POST", "/").match_body_size(3..=5).create();
+
+    for (body, expected) in [
+        ("ab", "HTTP/1.1 501 Not Implemented\r\n"),
+        ("abc", "HTTP/1.1 200 OK\r\n"),
+        ("abcde", "HTTP/1.1 200 OK\r\n"),
+        ("abcdef", "HTTP/1.1 501 Not Implemented\r\n"),
+    ] {
+        let (status, _, _) = request_with_body(s.host_with_port(), "POST /", "", body);
+        assert_eq!(expected, status, "body: {}", body);
+    }
+}
+
+#[test]
+fn test_match_body_with_toml() {
+    let mut expected = toml::Table::new();
+    expected.insert("name".to_string(), toml::Value::from("mockito"));
+    expected.insert("workers".to_string(), toml::Value::from(4));
+
     let mut s = Server::new();
     s.mock("POST", "/")
-        .match_body(Matcher::PartialJsonString(
-            "{\"foo\": \"bar\", \"hello\": \"world\"}".to_string(),
-        ))
+        .match_body(Matcher::Toml(toml::Value::Table(expected)))
         .create();
 
+    let (status, _, _) = request_with_body(
+        s.host_with_port(),
+        "POST /",
+        "",
+        "workers = 4\nname = \"mockito\"\n",
+    );
+    assert_eq!("HTTP/1.1 200 OK\r\n", status);
+
     let (status, _, _) =
-        request_with_body(s.host_with_port(), "POST /", "", r#"{"hello":"world"}"#);
+        request_with_body(s.host_with_port(), "POST /", "", "name = \"mockito\"\n");
     assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status);
 }
 
 #[test]
-fn test_mock_with_status() {
+fn test_match_body_with_xml() {
     let mut s = Server::new();
-    s.mock("GET", "/").with_status(204).with_body("").create();
-
-    let (status_line, _, _) = request(s.host_with_port(), "GET /", "");
-    assert_eq!("HTTP/1.1 204 No Content\r\n", status_line);
-}
+    s.mock("POST", "/")
+        .match_body(Matcher::Xml(
+            r#"<user id="1" role="admin"><name>mockito</name></user>"#.to_string(),
+        ))
+        .create();
 
-#[test]
-fn test_mock_with_custom_status() {
-    let mut s = Server::new();
-    s.mock("GET", "/").with_status(499).with_body("").create();
+    let (status, _, _) = request_with_body(
+        s.host_with_port(),
+        "POST /",
+        "",
+        "<?xml version=\"1.0\"?>\n<user role='admin'  id=\"1\">\n  <name>mockito</name>\n</user>\n",
+    );
+    assert_eq!("HTTP/1.1 200 OK\r\n", status);
 
-    let (status_line, _, _) = request(s.host_with_port(), "GET /", "");
-    assert_eq!("HTTP/1.1 499 <none>\r\n", status_line);
+    let (status, _, _) = request_with_body(
+        s.host_with_port(),
+        "POST /",
+        "",
+        r#"<user id="1" role="admin"><name>mockito</name><age>5</age></user>"#,
+    );
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status);
 }
 
 #[test]
-fn test_mock_with_body() {
+fn test_match_body_with_partial_xml() {
     let mut s = Server::new();
-    s.mock("GET", "/").with_body("hello").create();
+    s.mock("POST", "/")
+        .match_body(Matcher::PartialXml(
+            r#"<GetUser><id>1</id></GetUser>"#.to_string(),
+        ))
+        .create();
 
-    let (_, _, body) = request(s.host_with_port(), "GET /", "");
-    assert_eq!("hello", body);
+    let (status, _, _) = request_with_body(
+        s.host_with_port(),
+        "POST /",
+        "",
+        r#"<Envelope><Body><GetUser lang="en"><id>1</id><fields>all</fields></GetUser></Body></Envelope>"#,
+    );
+    assert_eq!("HTTP/1.1 200 OK\r\n", status);
+
+    let (status, _, _) = request_with_body(
+        s.host_with_port(),
+        "POST /",
+        "",
+        r#"<Envelope><Body><GetUser><id>2</id></GetUser></Body></Envelope>"#,
+    );
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status);
 }
 
 #[test]
-fn test_mock_with_fn_body() {
+fn test_match_header_in_list() {
     let mut s = Server::new();
     s.mock("GET", "/")
-        .with_chunked_body(|w| {
-            w.write_all(b"hel")?;
-            w.write_all(b"lo")
-        })
+        .match_header("accept", Matcher::InList("b".to_string()))
         .create();
 
-    let (_, _, body) = request(s.host_with_port(), "GET /", "");
-    assert_eq!("hello", body);
+    let (status, _, _) = request(s.host_with_port(), "GET /", "accept: a, b, c\r\n");
+    assert_eq!("HTTP/1.1 200 OK\r\n", status);
+
+    let (status, _, _) = request(s.host_with_port(), "GET /", "accept: a, bc\r\n");
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status);
 }
 
 #[test]
-fn test_mock_with_fn_body_streamed_forever() {
+fn test_match_header_numeric_range() {
     let mut s = Server::new();
     s.mock("GET", "/")
-        .with_chunked_body(|w| loop {
-            w.write_all(b"spam")?
-        })
+        .match_header("content-length", Matcher::NumericRange(1.0, 10.0))
         .create();
 
-    let stream = request_stream("1.1", s.host_with_port(), "GET /", "", "");
-    let (status_line, _, _) = parse_stream(stream, true);
-    assert_eq!("HTTP/1.1 200 OK\r\n", status_line);
+    let (status, _, _) = request_with_body(s.host_with_port(), "GET /", "", "hello");
+    assert_eq!("HTTP/1.1 200 OK\r\n", status);
+
+    let (status, _, _) = request_with_body(s.host_with_port(), "GET /", "", "hello world");
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status);
+
+    assert_eq!(
+        "[1, 10] (numeric range)",
+        Matcher::NumericRange(1.0, 10.0).to_string()
+    );
 }
 
 #[test]
-fn test_mock_with_body_from_request() {
+fn test_match_header_regex_with_flags() {
+    let matcher = Matcher::RegexWith {
+        pattern: "^text/.+$".to_string(),
+        case_insensitive: true,
+        multi_line: false,
+        dot_matches_new_line: false,
+    };
+
     let mut s = Server::new();
-    s.mock("GET", Matcher::Any)
-        .with_body_from_request(|request| {
-            if request.path() == "/world" {
-                "hello world".into()
-            } else {
-                "just hello".into()
-            }
-        })
+    s.mock("GET", "/")
+        .match_header("content-type", matcher.clone())
         .create();
 
-    let (_, _, body) = request(s.host_with_port(), "GET /world", "");
-    assert_eq!("hello world", body);
+    let (status, _, _) = request(s.host_with_port(), "GET /", "content-type: TEXT/Plain\r\n");
+    assert_eq!("HTTP/1.1 200 OK\r\n", status);
 
-    let (_, _, body) = request(s.host_with_port(), "GET /", "");
-    assert_eq!("just hello", body);
+    let (status, _, _) = request(s.host_with_port(), "GET /", "content-type: image/png\r\n");
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status);
+
+    assert_eq!("^text/.+$ (regex, case-insensitive)", matcher.to_string());
+}
+
+#[test]
+fn test_match_header_beyond_max_matcher_depth() {
+    let nested = |depth| (0..depth).fold(Matcher::Any, |matcher, _| Matcher::AnyOf(vec![matcher]));
+
+    let opts = ServerOpts {
+        max_matcher_depth: 8,
+        ..Default::default()
+    };
+    let mut s = Server::new_with_opts(opts);
+    s.mock("GET", "/shallow")
+        .match_header("x-flag", nested(8))
+        .create();
+    s.mock("GET", "/deep")
+        .match_header("x-flag", nested(9))
+        .create();
+
+    let (status, _, _) = request(s.host_with_port(), "GET /shallow", "x-flag: 1\r\n");
+    assert_eq!("HTTP/1.1 200 OK\r\n", status);
+
+    let (status, _, _) = request(s.host_with_port(), "GET /deep", "x-flag: 1\r\n");
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status);
+
+    // the limit also applies outside of the server thread
+    assert!(s
+        .mock("POST", "/")
+        .match_body(nested(8))
+        .would_body_match(b"1"));
+    assert!(!s
+        .mock("POST", "/")
+        .match_body(nested(9))
+        .would_body_match(b"1"));
+}
+
+#[test]
+fn test_match_header_with_fn() {
+    let mut s = Server::new();
+    s.mock("GET", "/")
+        .match_header(
+            "x-count",
+            Matcher::Fn(MatcherFn::new(|value| {
+                value.parse::<u64>().is_ok_and(|count| count > 100)
+            })),
+        )
+        .create();
+
+    let (status, _, _) = request(s.host_with_port(), "GET /", "x-count: 101\r\n");
+    assert_eq!("HTTP/1.1 200 OK\r\n", status);
+
+    let (status, _, _) = request(s.host_with_port(), "GET /", "x-count: 100\r\n");
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status);
+
+    let (status, _, _) = request(s.host_with_port(), "GET /", "x-count: many\r\n");
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status);
+}
+
+#[test]
+fn test_match_header_with_any_of_short_circuits() {
+    let calls = Arc::new(Mutex::new(0));
+    let counted = calls.clone();
+
+    let mut s = Server::new();
+    s.mock("GET", "/")
+        .match_header(
+            "x-flag",
+            Matcher::AnyOf(vec![
+                Matcher::Exact("on".to_string()),
+                Matcher::Fn(MatcherFn::new(move |_| {
+                    *counted.lock().unwrap() += 1;
+                    true
+                })),
+            ]),
+        )
+        .create();
+
+    request(s.host_with_port(), "GET /", "x-flag: on\r\n");
+    assert_eq!(0, *calls.lock().unwrap());
+
+    request(s.host_with_port(), "GET /", "x-flag: off\r\n");
+    assert_eq!(1, *calls.lock().unwrap());
+}
+
+#[test]
+fn test_match_body_captures() {
+    let mut s = Server::new();
+    s.mock("POST", "/")
+        .match_body_captures(
+            r#""id":\s*"(?P<id>[^"]*)""#,
+            vec![("id", Matcher::Regex("^[0-9]+$".to_string()))],
+        )
+        .create();
+
+    let (status, _, _) = request_with_body(s.host_with_port(), "POST /", "", r#"{"id": "1234"}"#);
+    assert_eq!("HTTP/1.1 200 OK\r\n", status);
+
+    let (status, _, _) = request_with_body(s.host_with_port(), "POST /", "", r#"{"id": "abcd"}"#);
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status);
+
+    let (status, _, _) = request_with_body(s.host_with_port(), "POST /", "", r#"{"name": "x"}"#);
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status);
+}
+
+#[test]
+fn test_match_body_contains() {
+    let mut s = Server::new();
+    s.mock("POST", "/")
+        .match_body(Matcher::Contains("a.b".to_string()))
+        .create();
+
+    let (status, _, _) = request_with_body(s.host_with_port(), "POST /", "", "xx a.b xx");
+    assert_eq!("HTTP/1.1 200 OK\r\n", status);
+
+    let (status, _, _) = request_with_body(s.host_with_port(), "POST /", "", "xx axb xx");
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status);
+}
+
+#[test]
+fn test_would_body_match() {
+    let mut s = Server::new();
+    let m = s
+        .mock("POST", "/")
+        .match_body(Matcher::Json(json!({"hello": "world", "count": 1})))
+        .create();
+
+    assert!(m.would_body_match(br#"{"count": 1, "hello": "world"}"#));
+    assert!(!m.would_body_match(br#"{"hello": "world"}"#));
+}
+
+#[test]
+fn test_would_body_match_non_json_body() {
+    let mut s = Server::new();
+    let matchers = vec![
+        Matcher::Json(json!({"hello": "world"})),
+        Matcher::JsonString(r#"{"hello": "world"}"#.to_string()),
+        Matcher::PartialJson(json!({"hello": "world"})),
+        Matcher::JsonUnordered(json!({"hello": "world"})),
+    ];
+
+    for matcher in matchers {
+        let m = s.mock("POST", "/").match_body(matcher);
+        assert!(m.would_body_match(br#"{"hello": "world"}"#));
+        assert!(!m.would_body_match(b"not json"));
+        assert!(!m.would_body_match(b""));
+    }
+}
+
+#[test]
+fn test_match_body_with_strict_json_numbers() {
+    let mut s = Server::new();
+    s.mock("POST", "/")
+        .match_body(Matcher::Json(json!({"a": 1})))
+        .create();
+
+    let (status, _, _) = request_with_body(s.host_with_port(), "POST /", "", r#"{"a": 1.0}"#);
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status);
+}
+
+#[test]
+fn test_match_body_with_json_string() {
+    let mut s = Server::new();
+    s.mock("POST", "/")
+        .match_body(Matcher::JsonString(
+            "{\"hello\":\"world\", \"foo\": \"bar\"}".to_string(),
+        ))
+        .create();
+
+    let (status, _, _) = request_with_body(
+        s.host_with_port(),
+        "POST /",
+        "",
+        r#"{"hello":"world", "foo": "bar"}"#,
+    );
+    assert_eq!("HTTP/1.1 200 OK\r\n", status);
+}
+
+#[test]
+fn test_match_body_with_json_string_order() {
+    let mut s = Server::new();
+    s.mock("POST", "/")
+        .match_body(Matcher::JsonString(
+            "{\"foo\": \"bar\", \"hello\": \"world\"}".to_string(),
+        ))
+        .create();
+
+    let (status, _, _) = request_with_body(
+        s.host_with_port(),
+        "POST /",
+        "",
+        r#"{"hello":"world", "foo": "bar"}"#,
+    );
+    assert_eq!("HTTP/1.1 200 OK\r\n", status);
+}
+
+#[test]
+fn test_match_body_with_partial_json() {
+    let mut s = Server::new();
+    s.mock("POST", "/")
+        .match_body(Matcher::PartialJson(json!({"hello":"world"})))
+        .create();
+
+    let (status, _, _) = request_with_body(
+        s.host_with_port(),
+        "POST /",
+        "",
+        r#"{"hello":"world", "foo": "bar"}"#,
+    );
+    assert_eq!("HTTP/1.1 200 OK\r\n", status);
+}
+
+#[test]
+fn test_match_body_with_partial_json_and_extra_fields() {
+    let mut s = Server::new();
+    s.mock("POST", "/")
+        .match_body(Matcher::PartialJson(json!({"hello":"world", "foo": "bar"})))
+        .create();
+
+    let (status, _, _) =
+        request_with_body(s.host_with_port(), "POST /", "", r#"{"hello":"world"}"#);
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status);
+}
+
+#[test]
+fn test_match_body_with_partial_json_string() {
+    let mut s = Server::new();
+    s.mock("POST", "/")
+        .match_body(Matcher::PartialJsonString(
+            "{\"hello\": \"world\"}".to_string(),
+        ))
+        .create();
+
+    let (status, _, _) = request_with_body(
+        s.host_with_port(),
+        "POST /",
+        "",
+        r#"{"hello":"world", "foo": "bar"}"#,
+    );
+    assert_eq!("HTTP/1.1 200 OK\r\n", status);
+}
+
+#[test]
+fn test_match_body_with_partial_json_string_and_extra_fields() {
+    let mut s = Server::new();
+    s.mock("POST", "/")
+        .match_body(Matcher::PartialJsonString(
+            "{\"foo\": \"bar\", \"hello\": \"world\"}".to_string(),
+        ))
+        .create();
+
+    let (status, _, _) =
+        request_with_body(s.host_with_port(), "POST /", "", r#"{"hello":"world"}"#);
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status);
+}
+
+#[test]
+fn test_mock_with_status() {
+    let mut s = Server::new();
+    s.mock("GET", "/").with_status(204).with_body("").create();
+
+    let (status_line, _, _) = request(s.host_with_port(), "GET /", "");
+    assert_eq!("HTTP/1.1 204 No Content\r\n", status_line);
+}
+
+#[test]
+fn test_mock_with_custom_status() {
+    let mut s = Server::new();
+    s.mock("GET", "/").with_status(499).with_body("").create();
+
+    let (status_line, _, _) = request(s.host_with_port(), "GET /", "");
+    assert_eq!("HTTP/1.1 499 <none>\r\n", status_line);
+}
+
+#[test]
+fn test_mock_with_random_status_in() {
+    let statuses = || {
+        let mut s = Server::new();
+        s.mock("GET", "/")
+            .with_random_status_in(500..=504, 7)
+            .expect(10)
+            .create();
+
+        (0..10)
+            .map(|_| {
+                let (status_line, _, _) = request(s.host_with_port(), "GET /", "");
+                status_line
+                    .split(' ')
+                    .nth(1)
+                    .unwrap()
+                    .parse::<u16>()
+                    .unwrap()
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let first = statuses();
+    assert!(first.iter().all(|status| (500..=504).contains(status)));
+    assert!(first.iter().any(|status| *status != first[0]));
+    assert_eq!(first, statuses());
+}
+
+#[test]
+fn test_mock_with_status_from_request() {
+    let mut s = Server::new();
+    s.mock("GET", Matcher::Regex("^/users".to_string()))
+        .with_status_from_request(|request| {
+            if request.path_and_query().ends_with("valid=true") {
+                200
+            } else {
+                400
+            }
+        })
+        .create();
+
+    let (status_line, _, _) = request(s.host_with_port(), "GET /users?valid=true", "");
+    assert_eq!("HTTP/1.1 200 OK\r\n", status_line);
+
+    let (status_line, _, _) = request(s.host_with_port(), "GET /users?valid=false", "");
+    assert_eq!("HTTP/1.1 400 Bad Request\r\n", status_line);
+}
+
+#[test]
+fn test_mock_with_header_map() {
+    use http::header::{HeaderMap, HeaderValue, CACHE_CONTROL, SET_COOKIE};
+
+    let mut headers = HeaderMap::new();
+    headers.append(SET_COOKIE, HeaderValue::from_static("a=1"));
+    headers.append(SET_COOKIE, HeaderValue::from_static("b=2"));
+    headers.append(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+
+    let mut s = Server::new();
+    s.mock("GET", "/").with_header_map(headers).create();
+
+    let (_, headers, _) = request(s.host_with_port(), "GET /", "");
+    let cookies: Vec<&String> = headers
+        .iter()
+        .filter(|header| header.starts_with("set-cookie:"))
+        .collect();
+    assert_eq!(vec!["set-cookie: a=1", "set-cookie: b=2"], cookies);
+    assert!(headers.contains(&"cache-control: no-cache".to_string()));
+}
+
+#[test]
+fn test_server_with_default_headers() {
+    let mut s = Server::new();
+    s.with_default_headers(vec![
+        ("server".to_string(), "mockito".to_string()),
+        ("x-env".to_string(), "test".to_string()),
+    ]);
+    s.mock("GET", "/a").create();
+    s.mock("GET", "/b")
+        .with_header("x-env", "override")
+        .create();
+
+    let (_, headers, _) = request(s.host_with_port(), "GET /a", "");
+    assert!(headers.contains(&"server: mockito".to_string()));
+    assert!(headers.contains(&"x-env: test".to_string()));
+
+    let (_, headers, _) = request(s.host_with_port(), "GET /b", "");
+    assert!(headers.contains(&"server: mockito".to_string()));
+    assert!(headers.contains(&"x-env: override".to_string()));
+    assert!(!headers.contains(&"x-env: test".to_string()));
+}
+
+#[test]
+fn test_server_with_cors() {
+    let mut s = Server::new();
+    s.enable_cors(CorsConfig {
+        allow_origins: vec!["https://example.com".to_string()],
+        allow_methods: vec!["GET".to_string(), "POST".to_string()],
+        allow_headers: vec!["content-type".to_string()],
+        max_age: Some(Duration::from_secs(600)),
+    });
+    s.mock("GET", "/hello").create();
+
+    let (status, headers, _) = request(
+        s.host_with_port(),
+        "OPTIONS /hello",
+        "origin: https://example.com\r\naccess-control-request-method: GET\r\n",
+    );
+    assert_eq!("HTTP/1.1 204 No Content\r\n", status);
+    assert!(headers.contains(&"access-control-allow-origin: https://example.com".to_string()));
+    assert!(headers.contains(&"access-control-allow-methods: GET, POST".to_string()));
+    assert!(headers.contains(&"access-control-allow-headers: content-type".to_string()));
+    assert!(headers.contains(&"access-control-max-age: 600".to_string()));
+
+    let (status, headers, _) = request(
+        s.host_with_port(),
+        "GET /hello",
+        "origin: https://example.com\r\n",
+    );
+    assert_eq!("HTTP/1.1 200 OK\r\n", status);
+    assert!(headers.contains(&"access-control-allow-origin: https://example.com".to_string()));
+    assert!(headers.contains(&"vary: origin".to_string()));
+}
+
+#[test]
+fn test_mock_with_cookie() {
+    let mut s = Server::new();
+    s.mock("GET", "/")
+        .with_cookie("theme", "dark")
+        .with_cookie_builder(
+            CookieBuilder::new("session", "abc123")
+                .same_site(SameSite::Strict)
+                .http_only(true)
+                .secure(true)
+                .max_age(Duration::from_secs(3600))
+                .domain("example.com")
+                .path("/"),
+        )
+        .create();
+
+    let (_, headers, _) = request(s.host_with_port(), "GET /", "");
+    let cookies: Vec<&String> = headers
+        .iter()
+        .filter(|header| header.starts_with("set-cookie:"))
+        .collect();
+    assert_eq!(
+        vec![
+            "set-cookie: theme=dark",
+            "set-cookie: session=abc123; Path=/; Domain=example.com; Max-Age=3600; Secure; HttpOnly; SameSite=Strict",
+        ],
+        cookies
+    );
+}
+
+#[test]
+fn test_mock_with() {
+    let mut s = Server::new();
+    let m = s.mock_with("GET", "/hello", 201, "world");
+
+    let (status_line, _, body) = request(s.host_with_port(), "GET /hello", "");
+    assert_eq!("HTTP/1.1 201 Created\r\n", status_line);
+    assert_eq!("world", body);
+
+    m.assert();
+}
+
+#[test]
+#[should_panic(expected = "is not a redirect")]
+fn test_mock_with_redirect_panics_with_non_redirect_status() {
+    let mut s = Server::new();
+    s.mock("GET", "/").with_redirect(200, "/new");
+}
+
+#[test]
+fn test_mock_async_job() {
+    let mut s = Server::new();
+    let (submit, in_progress, completed) = s.mock_async_job("/jobs", "/jobs/1", 2);
+
+    let (status_line, headers, _) = request(s.host_with_port(), "POST /jobs", "");
+    assert_eq!("HTTP/1.1 202 Accepted\r\n", status_line);
+    assert!(headers.contains(&"location: /jobs/1".to_string()));
+
+    for _ in 0..2 {
+        let (status_line, _, _) = request(s.host_with_port(), "GET /jobs/1", "");
+        assert_eq!("HTTP/1.1 202 Accepted\r\n", status_line);
+    }
+
+    let (status_line, _, _) = request(s.host_with_port(), "GET /jobs/1", "");
+    assert_eq!("HTTP/1.1 200 OK\r\n", status_line);
+
+    submit.assert();
+    in_progress.assert();
+    completed.assert();
+}
+
+#[test]
+fn test_mock_with_websocket() {
+    let mut s = Server::new();
+    s.mock("GET", "/ws")
+        .with_websocket(|message| match message {
+            WebSocketMessage::Text(text) => Some(WebSocketMessage::Text(text.to_uppercase())),
+            WebSocketMessage::Binary(_) => None,
+        })
+        .create();
+
+    let mut stream = request_stream(
+        "1.1",
+        s.host_with_port(),
+        "GET /ws",
+        "upgrade: websocket\r\nconnection: Upgrade\r\nsec-websocket-version: 13\r\nsec-websocket-key: dGhlIHNhbXBsZSBub25jZQ==\r\n",
+        "",
+    );
+
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut handshake = vec![];
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if line == "\r\n" {
+            break;
+        }
+        handshake.push(line.to_lowercase());
+    }
+    assert_eq!("http/1.1 101 switching protocols\r\n", handshake[0]);
+    assert!(
+        handshake.contains(&"sec-websocket-accept: s3pplmbitxaq9kygzzhzrbk+xoo=\r\n".to_string())
+    );
+
+    // a masked text frame, as sent by clients
+    let mask = [1, 2, 3, 4];
+    let mut frame = vec![0x81, 0x80 | 5];
+    frame.extend_from_slice(&mask);
+    frame.extend(b"hello".iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+    stream.write_all(&frame).unwrap();
+
+    let mut reply = [0; 7];
+    reader.read_exact(&mut reply).unwrap();
+    assert_eq!([0x81, 5], reply[..2]);
+    assert_eq!(b"HELLO", &reply[2..]);
+}
+
+#[test]
+fn test_mock_with_websocket_rejects_huge_frames() {
+    let mut s = Server::new();
+    s.mock("GET", "/ws").with_websocket(Some).create();
+
+    let mut stream = request_stream(
+        "1.1",
+        s.host_with_port(),
+        "GET /ws",
+        "upgrade: websocket\r\nconnection: Upgrade\r\nsec-websocket-version: 13\r\nsec-websocket-key: dGhlIHNhbXBsZSBub25jZQ==\r\n",
+        "",
+    );
+
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if line == "\r\n" {
+            break;
+        }
+    }
+
+    // a masked binary frame announcing a 2^63 byte payload
+    let mut frame = vec![0x82, 0x80 | 127];
+    frame.extend_from_slice(&(1u64 << 63).to_be_bytes());
+    frame.extend_from_slice(&[1, 2, 3, 4]);
+    stream.write_all(&frame).unwrap();
+
+    // a close frame with status 1009 (message too big)
+    let mut reply = vec![];
+    reader.read_to_end(&mut reply).unwrap();
+    assert_eq!(vec![0x88, 2, 0x03, 0xF1], reply);
+}
+
+#[test]
+fn test_register_mock_spec() {
+    let spec = MockSpec::builder("GET", "/hello")
+        .with_status(201)
+        .with_body("world")
+        .into_spec();
+
+    let mut s1 = Server::new();
+    let mut s2 = Server::new();
+    let m1 = s1.register(&spec);
+    let m2 = s2.register(&spec);
+
+    for s in [&s1, &s2] {
+        let (status_line, _, body) = request(s.host_with_port(), "GET /hello", "");
+        assert_eq!("HTTP/1.1 201 Created\r\n", status_line);
+        assert_eq!("world", body);
+    }
+
+    m1.assert();
+    m2.assert();
+}
+
+#[test]
+fn test_register_mock_spec_with_body_sequence() {
+    let spec = MockSpec::builder("GET", "/")
+        .with_body_sequence(vec!["first", "second"])
+        .expect(2)
+        .into_spec();
+
+    let mut s1 = Server::new();
+    let mut s2 = Server::new();
+    let m1 = s1.register(&spec);
+    let m2 = s2.register(&spec);
+
+    for s in [&s1, &s2] {
+        let (_, _, body) = request(s.host_with_port(), "GET /", "");
+        assert_eq!("first", body);
+    }
+    for s in [&s1, &s2] {
+        let (_, _, body) = request(s.host_with_port(), "GET /", "");
+        assert_eq!("second", body);
+    }
+
+    m1.assert();
+    m2.assert();
+}
+
+#[test]
+fn test_match_grpc_method() {
+    let mut s = Server::new();
+    let m = s
+        .mock("POST", "/")
+        .match_grpc_method("helloworld.Greeter", "SayHello")
+        .with_header("content-type", "application/grpc")
+        .create();
+
+    let (status_line, _, _) = request(s.host_with_port(), "POST /helloworld.Greeter/SayHello", "");
+    assert_eq!("HTTP/1.1 200 OK\r\n", status_line);
+
+    let (status_line, _, _) = request(s.host_with_port(), "POST /helloworld.Greeter/SayBye", "");
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status_line);
+
+    m.assert();
+}
+
+#[test]
+#[should_panic(expected = "invalid request path")]
+fn test_match_grpc_method_rejects_invalid_service() {
+    let mut s = Server::new();
+    s.mock("POST", "/")
+        .match_grpc_method("hello/world", "SayHello");
+}
+
+#[test]
+#[should_panic(expected = "InvalidRegex")]
+fn test_match_body_rejects_invalid_regex() {
+    let mut s = Server::new();
+    s.mock("POST", "/").match_body(Matcher::Regex("(".into()));
+}
+
+#[test]
+#[should_panic(expected = "InvalidRegex")]
+fn test_mock_rejects_invalid_path_regex() {
+    let mut s = Server::new();
+    s.mock(
+        "GET",
+        Matcher::AnyOf(vec!["/".into(), Matcher::Regex("[".into())]),
+    );
+}
+
+#[test]
+fn test_mock_with_body_from_file_typed() {
+    let mut s = Server::new();
+    s.mock("GET", "/text")
+        .with_body_from_file_typed("tests/files/simple.http")
+        .create();
+    s.mock("GET", "/json")
+        .with_body_from_file_typed("tests/files/simple.json")
+        .create();
+    s.mock("GET", "/custom")
+        .with_header("content-type", "application/vnd.api+json")
+        .with_body_from_file_typed("tests/files/simple.json")
+        .create();
+
+    let (_, headers, _) = request(s.host_with_port(), "GET /text", "");
+    assert!(headers.contains(&"content-type: text/plain".to_string()));
+
+    let (_, headers, body) = request(s.host_with_port(), "GET /json", "");
+    assert!(headers.contains(&"content-type: application/json".to_string()));
+    assert_eq!("{\"hello\":\"world\"}\n", body);
+
+    let (_, headers, _) = request(s.host_with_port(), "GET /custom", "");
+    assert!(headers.contains(&"content-type: application/vnd.api+json".to_string()));
+}
+
+#[test]
+fn test_mock_with_backpressure_body() {
+    let chunk = vec![b'x'; 1 << 20];
+    let mut s = Server::new();
+    let m = s
+        .mock("GET", "/")
+        .with_backpressure_body(vec![chunk; 32])
+        .create();
+
+    let mut stream = request_stream(
+        "1.1",
+        s.host_with_port(),
+        "GET /",
+        "connection: close\r\n",
+        "",
+    );
+
+    // the socket buffers can't hold the whole body, so the server has to wait for the client
+    let mut buffer = vec![0; 1 << 20];
+    stream.read_exact(&mut buffer).unwrap();
+    assert!(m.chunks_sent_at().len() < 32);
+
+    let mut received = buffer.len();
+    loop {
+        match stream.read(&mut buffer).unwrap() {
+            0 => break,
+            n => received += n,
+        }
+    }
+
+    assert!(received > 32 << 20);
+    assert_eq!(32, m.chunks_sent_at().len());
+}
+
+#[test]
+fn test_mock_expire_after() {
+    let mut s = Server::new();
+    s.mock("GET", "/")
+        .expire_after(Duration::from_millis(300))
+        .create();
+
+    let (status_line, _, _) = request(s.host_with_port(), "GET /", "");
+    assert_eq!("HTTP/1.1 200 OK\r\n", status_line);
+
+    thread::sleep(Duration::from_millis(400));
+
+    let (status_line, _, _) = request(s.host_with_port(), "GET /", "");
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status_line);
+}
+
+#[test]
+fn test_mock_with_throttle() {
+    let mut s = Server::new();
+    s.mock("GET", "/")
+        .with_body(vec![b'x'; 100 * 1024])
+        .with_throttle(50 * 1024)
+        .create();
+
+    let start = std::time::Instant::now();
+    let (_, headers, body) = binary_request(s.host_with_port(), "GET /", "", "");
+    let elapsed = start.elapsed();
+
+    assert!(headers.contains(&"transfer-encoding: chunked".to_string()));
+    assert_eq!(100 * 1024, body.len());
+    assert!(elapsed >= Duration::from_millis(1900), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_secs(4), "{:?}", elapsed);
+}
+
+#[test]
+fn test_mock_with_first_byte_delay() {
+    let mut s = Server::new();
+    s.mock("GET", "/")
+        .with_body("hello world")
+        .with_first_byte_delay(Duration::from_millis(500))
+        .create();
+
+    let start = std::time::Instant::now();
+    let stream = request_stream(
+        "1.1",
+        s.host_with_port(),
+        "GET /",
+        "connection: close\r\n",
+        "",
+    );
+    let mut reader = BufReader::new(stream);
+
+    let mut line = String::new();
+    while line != "\r\n" {
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+    }
+    assert!(start.elapsed() < Duration::from_millis(500));
+
+    let mut first_byte = [0; 1];
+    reader.read_exact(&mut first_byte).unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(500));
+
+    let mut rest = String::new();
+    reader.read_to_string(&mut rest).unwrap();
+    assert_eq!("ello world", rest);
+}
+
+#[test]
+fn test_mock_with_body() {
+    let mut s = Server::new();
+    s.mock("GET", "/").with_body("hello").create();
+
+    let (_, _, body) = request(s.host_with_port(), "GET /", "");
+    assert_eq!("hello", body);
+}
+
+#[test]
+fn test_mock_with_body_sequence() {
+    let mut s = Server::new();
+    s.mock("GET", "/")
+        .with_body_sequence(vec!["one", "two", "three"])
+        .expect(4)
+        .create();
+
+    for expected in ["one", "two", "three", "three"] {
+        let (_, _, body) = request(s.host_with_port(), "GET /", "");
+        assert_eq!(expected, body);
+    }
+}
+
+#[test]
+fn test_mock_with_cycling_body_sequence() {
+    let mut s = Server::new();
+    s.mock("GET", "/")
+        .with_cycling_body_sequence(vec!["one", "two"])
+        .expect(3)
+        .create();
+
+    for expected in ["one", "two", "one"] {
+        let (_, _, body) = request(s.host_with_port(), "GET /", "");
+        assert_eq!(expected, body);
+    }
+}
+
+#[test]
+fn test_mock_with_fn_body() {
+    let mut s = Server::new();
+    s.mock("GET", "/")
+        .with_chunked_body(|w| {
+            w.write_all(b"hel")?;
+            w.write_all(b"lo")
+        })
+        .create();
+
+    let (_, _, body) = request(s.host_with_port(), "GET /", "");
+    assert_eq!("hello", body);
+}
+
+#[test]
+fn test_mock_with_body_from_reader() {
+    let content: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+
+    let mut s = Server::new();
+    s.mock("GET", "/")
+        .with_body_from_reader(std::io::Cursor::new(content.clone()))
+        .create();
+
+    let (_, headers, body) = binary_request(s.host_with_port(), "GET /", "", "");
+    assert!(headers.contains(&"transfer-encoding: chunked".to_string()));
+    assert_eq!(content, body);
+
+    let (_, _, body) = request(s.host_with_port(), "GET /", "");
+    assert_eq!("", body);
+}
+
+#[test]
+fn test_mock_with_fn_body_streamed_forever() {
+    let mut s = Server::new();
+    s.mock("GET", "/")
+        .with_chunked_body(|w| loop {
+            w.write_all(b"spam")?
+        })
+        .create();
+
+    let stream = request_stream("1.1", s.host_with_port(), "GET /", "", "");
+    let (status_line, _, _) = parse_stream(stream, true);
+    assert_eq!("HTTP/1.1 200 OK\r\n", status_line);
+}
+
+#[test]
+fn test_mock_with_body_from_request() {
+    let mut s = Server::new();
+    s.mock("GET", Matcher::Any)
+        .with_body_from_request(|request| {
+            if request.path() == "/world" {
+                "hello world".into()
+            } else {
+                "just hello".into()
+            }
+        })
+        .create();
+
+    let (_, _, body) = request(s.host_with_port(), "GET /world", "");
+    assert_eq!("hello world", body);
+
+    let (_, _, body) = request(s.host_with_port(), "GET /", "");
+    assert_eq!("just hello", body);
+}
+
+#[test]
+fn test_mock_with_body_from_request_async() {
+    let mut s = Server::new();
+    s.mock("GET", Matcher::Any)
+        .with_body_from_request_async(|request| {
+            let path = request.path().to_string();
+            async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                format!("hello from {}", path).into_bytes()
+            }
+        })
+        .create();
+
+    let (status_line, headers, body) = request(s.host_with_port(), "GET /world", "");
+    assert_eq!("HTTP/1.1 200 OK\r\n", status_line);
+    assert!(headers.contains(&"transfer-encoding: chunked".to_string()));
+    assert_eq!("hello from /world", body);
+}
+
+#[cfg(all(feature = "gzip", feature = "deflate"))]
+#[test]
+fn test_mock_with_body_encoded() {
+    use flate2::read::{GzDecoder, ZlibDecoder};
+    use mockito::ContentEncoding;
+
+    let mut s = Server::new();
+    s.mock("GET", "/gzip")
+        .with_body_encoded(ContentEncoding::Gzip, "hello gzip")
+        .create();
+    s.mock("GET", "/deflate")
+        .with_body_encoded(ContentEncoding::Deflate, "hello deflate")
+        .create();
+
+    let (_, headers, body) = binary_request(s.host_with_port(), "GET /gzip", "", "");
+    assert!(headers.contains(&"content-encoding: gzip".to_string()));
+    let mut decoded = String::new();
+    GzDecoder::new(body.as_slice())
+        .read_to_string(&mut decoded)
+        .unwrap();
+    assert_eq!("hello gzip", decoded);
+
+    let (_, headers, body) = binary_request(s.host_with_port(), "GET /deflate", "", "");
+    assert!(headers.contains(&"content-encoding: deflate".to_string()));
+    let mut decoded = String::new();
+    ZlibDecoder::new(body.as_slice())
+        .read_to_string(&mut decoded)
+        .unwrap();
+    assert_eq!("hello deflate", decoded);
+}
+
+#[test]
+fn test_mock_with_body_from_json_pretty() {
+    let mut s = Server::new();
+    s.mock("GET", "/")
+        .with_body_from_json_pretty(json!({"hello": "world", "list": [1, 2]}))
+        .create();
+
+    let (_, headers, body) = request(s.host_with_port(), "GET /", "");
+    assert!(headers.contains(&"content-type: application/json".to_string()));
+    assert_eq!(
+        "{\n  \"hello\": \"world\",\n  \"list\": [\n    1,\n    2\n  ]\n}",
+        body
+    );
+}
+
+#[cfg(all(feature = "gzip", feature = "deflate"))]
+#[test]
+fn test_mock_with_auto_encoding() {
+    use flate2::read::{GzDecoder, ZlibDecoder};
+
+    let mut s = Server::new();
+    s.mock("GET", "/")
+        .with_body("hello world")
+        .with_auto_encoding()
+        .create();
+
+    let (_, headers, body) =
+        binary_request(s.host_with_port(), "GET /", "accept-encoding: gzip\r\n", "");
+    assert!(headers.contains(&"content-encoding: gzip".to_string()));
+    let mut decoded = String::new();
+    GzDecoder::new(body.as_slice())
+        .read_to_string(&mut decoded)
+        .unwrap();
+    assert_eq!("hello world", decoded);
+
+    let (_, headers, body) = binary_request(
+        s.host_with_port(),
+        "GET /",
+        "accept-encoding: gzip;q=0.5, deflate\r\n",
+        "",
+    );
+    assert!(headers.contains(&"content-encoding: deflate".to_string()));
+    let mut decoded = String::new();
+    ZlibDecoder::new(body.as_slice())
+        .read_to_string(&mut decoded)
+        .unwrap();
+    assert_eq!("hello world", decoded);
+
+    let (_, headers, body) = request(
+        s.host_with_port(),
+        "GET /",
+        "accept-encoding: identity, gzip;q=0\r\n",
+    );
+    assert!(!headers
+        .iter()
+        .any(|header| header.starts_with("content-encoding:")));
+    assert_eq!("hello world", body);
+}
+
+#[cfg(feature = "brotli")]
+#[test]
+fn test_mock_with_body_encoded_brotli() {
+    use mockito::ContentEncoding;
+
+    let mut s = Server::new();
+    s.mock("GET", "/")
+        .with_body_encoded(ContentEncoding::Brotli, "hello brotli")
+        .create();
+
+    let (_, headers, body) = binary_request(s.host_with_port(), "GET /", "", "");
+    assert!(headers.contains(&"content-encoding: br".to_string()));
+    let mut decoded = String::new();
+    brotli::Decompressor::new(body.as_slice(), 4096)
+        .read_to_string(&mut decoded)
+        .unwrap();
+    assert_eq!("hello brotli", decoded);
+}
+
+#[test]
+fn test_mock_with_body_from_request_content_type_and_length() {
+    let mut s = Server::new();
+    s.mock("POST", "/")
+        .with_body_from_request(|request| {
+            format!(
+                "{:?} {:?}",
+                request.content_type(),
+                request.content_length()
+            )
+            .into()
+        })
+        .create();
+
+    let (_, _, body) = request_with_body(
+        s.host_with_port(),
+        "POST /",
+        "content-type: application/json\r\n",
+        "{}",
+    );
+    assert_eq!(r#"Some("application/json") Some(2)"#, body);
+
+    let (_, _, body) = request(s.host_with_port(), "POST /", "");
+    assert_eq!("None None", body);
+
+    // invalid lengths are rejected before they reach the mock
+    let (status, _, body) = request(s.host_with_port(), "POST /", "content-length: abc\r\n");
+    assert_eq!("HTTP/1.1 400 Bad Request\r\n", status);
+    assert_eq!("", body);
+}
+
+#[test]
+fn test_match_path_template() {
+    let mut s = Server::new();
+    s.mock(
+        "GET",
+        Matcher::PathTemplate("/users/{id}/posts/{post_id}".to_string()),
+    )
+    .with_body_from_request(|request| {
+        format!(
+            "user {} post {}",
+            request.path_param("id").unwrap(),
+            request.path_param("post_id").unwrap()
+        )
+        .into()
+    })
+    .create();
+
+    let (_, _, body) = request(s.host_with_port(), "GET /users/42/posts/7?page=1", "");
+    assert_eq!("user 42 post 7", body);
+
+    let (status, _, _) = request(s.host_with_port(), "GET /users/42/posts", "");
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status);
+
+    let (status, _, _) = request(s.host_with_port(), "GET /users//posts/7", "");
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status);
+}
+
+#[test]
+fn test_mock_with_body_from_request_addresses() {
+    let mut s = Server::new();
+    s.mock("GET", "/")
+        .match_remote_addr(|addr| addr.ip().is_loopback())
+        .with_body_from_request(|request| {
+            format!(
+                "{} {}",
+                request.remote_addr().unwrap(),
+                request.local_addr().unwrap()
+            )
+            .into()
+        })
+        .create();
+    s.mock("GET", "/none")
+        .match_remote_addr(|addr| !addr.ip().is_loopback())
+        .create();
+
+    let stream = request_stream("1.1", s.host_with_port(), "GET /", "", "");
+    let client_addr = stream.local_addr().unwrap();
+    let (_, _, body) = parse_stream(stream, false);
+    assert_eq!(
+        format!("{} {}", client_addr, s.host_with_port()),
+        String::from_utf8(body).unwrap()
+    );
+
+    let (status_line, _, _) = request(s.host_with_port(), "GET /none", "");
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status_line);
+}
+
+#[test]
+fn test_assert_last_response() {
+    let mut s = Server::new();
+    let m = s
+        .mock("GET", Matcher::Any)
+        .with_status(201)
+        .with_body_from_request(|request| format!("id: {}", request.path().len()).into())
+        .expect(2)
+        .create();
+
+    request(s.host_with_port(), "GET /a", "");
+    request(s.host_with_port(), "GET /abcdefghij", "");
+
+    m.assert_last_response(201, Matcher::Regex(r"^id: \d{2}$".to_string()));
+}
+
+#[test]
+#[should_panic(expected = "...but it had the body:\nid: 2")]
+fn test_assert_last_response_panics_with_mismatched_body() {
+    let mut s = Server::new();
+    let m = s
+        .mock("GET", Matcher::Any)
+        .with_body_from_request(|request| format!("id: {}", request.path().len()).into())
+        .create();
+
+    request(s.host_with_port(), "GET /a", "");
+
+    m.assert_last_response(200, Matcher::Regex(r"^id: \d{2}$".to_string()));
+}
+
+#[test]
+fn test_assert_last_request_body() {
+    let mut s = Server::new();
+    let m = s.mock("POST", "/").expect(2).create();
+
+    assert!(m.last_request().is_none());
+
+    request_with_body(s.host_with_port(), "POST /", "", "first");
+    request_with_body(s.host_with_port(), "POST /", "", "second");
+
+    assert_eq!(
+        "second",
+        m.last_request().unwrap().utf8_lossy_body().unwrap()
+    );
+    m.assert_last_request_body("second");
+}
+
+#[test]
+#[should_panic(expected = "...but it had the body:\nfirst")]
+fn test_assert_last_request_body_panics_with_mismatched_body() {
+    let mut s = Server::new();
+    let m = s.mock("POST", "/").create();
+
+    request_with_body(s.host_with_port(), "POST /", "", "first");
+
+    m.assert_last_request_body("second");
 }
 
 #[test]
@@ -665,6 +2045,108 @@ fn test_mock_with_body_from_request_body() {
     assert_eq!("not a test", body);
 }
 
+#[test]
+fn test_mock_with_lazy_body() {
+    let mut s = Server::new();
+    let calls = Arc::new(Mutex::new(0));
+    let calls_clone = calls.clone();
+
+    s.mock("GET", "/")
+        .with_lazy_body(move || {
+            *calls_clone.lock().unwrap() += 1;
+            b"expensive".to_vec()
+        })
+        .expect(3)
+        .create();
+
+    for _ in 0..3 {
+        let (_, headers, body) = request(s.host_with_port(), "GET /", "");
+        assert!(headers.contains(&"content-length: 9".to_string()));
+        assert_eq!("expensive", body);
+    }
+
+    assert_eq!(1, *calls.lock().unwrap());
+}
+
+#[test]
+fn test_mock_with_connection_reset() {
+    let mut s = Server::new();
+    s.mock("GET", "/")
+        .with_body("hello")
+        .with_connection_reset()
+        .create();
+
+    let mut stream = request_stream("1.1", s.host_with_port(), "GET /", "", "");
+    let mut response = vec![];
+    let _ = stream.read_to_end(&mut response);
+
+    assert!(response.is_empty());
+}
+
+#[test]
+fn test_mock_with_connection_reset_for_path() {
+    let mut s = Server::new();
+    s.mock("GET", "/blocked").with_connection_reset().create();
+    s.mock("GET", "/ok").with_body("ok").create();
+
+    let mut stream = request_stream("1.1", s.host_with_port(), "GET /blocked", "", "");
+    let mut response = vec![];
+    let error = stream.read_to_end(&mut response).unwrap_err();
+    assert_eq!(std::io::ErrorKind::ConnectionReset, error.kind());
+
+    let (status, _, body) = request(s.host_with_port(), "GET /ok", "");
+    assert_eq!("HTTP/1.1 200 OK\r\n", status);
+    assert_eq!("ok", body);
+}
+
+#[test]
+fn test_mock_with_partial_body_then_close() {
+    let mut s = Server::new();
+    s.mock("GET", "/")
+        .with_partial_body("hello wor", true)
+        .create();
+
+    let mut stream = request_stream("1.1", s.host_with_port(), "GET /", "", "");
+    let mut response = vec![];
+    let _ = stream.read_to_end(&mut response);
+    let response = String::from_utf8_lossy(&response);
+
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("transfer-encoding: chunked\r\n"));
+    assert!(response.ends_with("\r\n\r\n9\r\nhello wor\r\n"));
+}
+
+#[test]
+fn test_mock_with_response() {
+    let mut s = Server::new();
+    let response = MockResponse::new()
+        .with_status(201)
+        .with_header("x-api-key", "1234")
+        .with_body("created");
+
+    s.mock("POST", "/").with_response(response).create();
+
+    let (status, headers, body) = request(s.host_with_port(), "POST /", "");
+    assert_eq!("HTTP/1.1 201 Created\r\n", status);
+    assert!(headers.contains(&"x-api-key: 1234".to_string()));
+    assert_eq!("created", body);
+}
+
+#[test]
+fn test_mock_with_response_with_first_byte_delay() {
+    let mut s = Server::new();
+    let response = MockResponse::new()
+        .with_body("slow")
+        .with_first_byte_delay(Duration::from_millis(200));
+
+    s.mock("GET", "/").with_response(response).create();
+
+    let start = std::time::Instant::now();
+    let (_, _, body) = request(s.host_with_port(), "GET /", "");
+    assert!(start.elapsed() >= Duration::from_millis(200));
+    assert_eq!("slow", body);
+}
+
 #[test]
 fn test_mock_with_header() {
     let mut s = Server::new();
@@ -680,20 +2162,66 @@ fn test_mock_with_header() {
 #[test]
 fn test_mock_with_header_from_request() {
     let mut s = Server::new();
-    s.mock("GET", Matcher::Any)
-        .with_header_from_request("x-user", |req| {
-            if req.path() == "/alice" {
-                "alice".into()
-            } else {
-                "everyone".into()
-            }
-        })
+    s.mock("GET", Matcher::Any)
+        .with_header_from_request("x-user", |req| {
+            if req.path() == "/alice" {
+                "alice".into()
+            } else {
+                "everyone".into()
+            }
+        })
+        .create();
+
+    let (_, headers, _) = request(s.host_with_port(), "GET /alice", "");
+    assert!(headers.contains(&"x-user: alice".to_string()));
+    let (_, headers, _) = request(s.host_with_port(), "GET /anyone-else", "");
+    assert!(headers.contains(&"x-user: everyone".to_string()));
+}
+
+#[test]
+fn test_mock_with_early_hints() {
+    let mut s = Server::new();
+    s.mock("GET", "/")
+        .with_early_hints(vec![("link", "</style.css>; rel=preload")])
+        .with_body("hello")
+        .create();
+
+    let mut stream = request_stream(
+        "1.1",
+        s.host_with_port(),
+        "GET /",
+        "connection: close\r\n",
+        "",
+    );
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+
+    assert!(response.starts_with(
+        "HTTP/1.1 103 Early Hints\r\nlink: </style.css>; rel=preload\r\n\r\nHTTP/1.1 200 OK\r\n"
+    ));
+    assert!(response.ends_with("\r\n\r\nhello"));
+}
+
+#[test]
+fn test_mock_with_trailer() {
+    let mut s = Server::new();
+    s.mock("GET", "/")
+        .with_chunked_body(|w| w.write_all(b"hello"))
+        .with_trailer("x-checksum", "abc123")
         .create();
 
-    let (_, headers, _) = request(s.host_with_port(), "GET /alice", "");
-    assert!(headers.contains(&"x-user: alice".to_string()));
-    let (_, headers, _) = request(s.host_with_port(), "GET /anyone-else", "");
-    assert!(headers.contains(&"x-user: everyone".to_string()));
+    let mut stream = request_stream(
+        "1.1",
+        s.host_with_port(),
+        "GET /",
+        "connection: close\r\nte: trailers\r\n",
+        "",
+    );
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+
+    assert!(response.contains("\r\ntrailer: x-checksum\r\n"));
+    assert!(response.ends_with("\r\n5\r\nhello\r\n0\r\nx-checksum: abc123\r\n\r\n"));
 }
 
 #[test]
@@ -758,11 +2286,7 @@ fn test_unpooled_server_going_out_of_context_removes_all_mocks() {
     let address;
 
     {
-        let opts = ServerOpts {
-            port: 0,
-            ..Default::default()
-        };
-        let mut s = Server::new_with_opts(opts);
+        let mut s = Server::new_unpooled();
         address = s.host_with_port();
 
         s.mock("GET", "/reset").create();
@@ -775,6 +2299,163 @@ fn test_unpooled_server_going_out_of_context_removes_all_mocks() {
     assert_eq!("HTTP/1.1 501 Not Implemented\r\n", reset_status_line);
 }
 
+#[test]
+fn test_stopped_server_refuses_connections() {
+    let mut s = Server::new();
+    let address = s.host_with_port();
+    s.mock("GET", "/").create();
+
+    let (status_line, _, _) = request(&address, "GET /", "");
+    assert_eq!("HTTP/1.1 200 OK\r\n", status_line);
+
+    s.stop();
+    s.stop();
+
+    assert!(TcpStream::connect(&address).is_err());
+}
+
+#[test]
+fn test_server_with_keep_alive_timeout_closes_idle_connections() {
+    let opts = ServerOpts {
+        keep_alive_timeout: Some(Duration::from_millis(100)),
+        ..Default::default()
+    };
+    let s = Server::new_with_opts(opts);
+
+    let mut stream = TcpStream::connect(s.host_with_port()).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+
+    thread::sleep(Duration::from_millis(300));
+
+    // the server closed the idle connection
+    let mut buf = [0; 1];
+    assert_eq!(0, stream.read(&mut buf).unwrap());
+}
+
+#[test]
+fn test_server_with_keep_alive_timeout_waits_for_slow_responses() {
+    let opts = ServerOpts {
+        keep_alive_timeout: Some(Duration::from_millis(100)),
+        ..Default::default()
+    };
+    let mut s = Server::new_with_opts(opts);
+    s.mock("GET", "/")
+        .with_body("slow")
+        .with_first_byte_delay(Duration::from_millis(300))
+        .create();
+
+    let (status, _, body) = request(s.host_with_port(), "GET /", "");
+    assert_eq!("HTTP/1.1 200 OK\r\n", status);
+    assert_eq!("slow", body);
+}
+
+#[test]
+fn test_server_with_max_headers() {
+    let opts = ServerOpts {
+        max_headers: Some(3),
+        ..Default::default()
+    };
+    let mut s = Server::new_with_opts(opts);
+    let mock = s.mock("GET", "/").expect(1).create();
+
+    let (status, _, _) = request(s.host_with_port(), "GET /", "x-a: 1\r\nx-b: 2\r\n");
+    assert_eq!("HTTP/1.1 200 OK\r\n", status);
+
+    let (status, headers, _) = request(
+        s.host_with_port(),
+        "GET /",
+        "x-a: 1\r\nx-b: 2\r\nx-c: 3\r\nx-d: 4\r\n",
+    );
+    assert_eq!("HTTP/1.1 431 Request Header Fields Too Large\r\n", status);
+    assert!(headers.contains(&"connection: close".to_string()));
+
+    mock.assert();
+}
+
+#[test]
+fn test_server_with_max_headers_records_rejected_requests() {
+    let opts = ServerOpts {
+        max_headers: Some(1),
+        ..Default::default()
+    };
+    let mut s = Server::new_with_opts(opts);
+    let mock = s.mock("GET", "/").create();
+
+    request(s.host_with_port(), "GET /", "x-a: 1\r\nx-b: 2\r\n");
+
+    let err = mock.try_assert().unwrap_err();
+    assert_eq!(Some(0), err.actual_hits);
+    let last_unmatched_request = err.last_unmatched_request.unwrap();
+    assert!(last_unmatched_request.contains("x-a: 1\r\n"));
+    assert!(last_unmatched_request.contains("x-b: 2\r\n"));
+}
+
+#[test]
+fn test_server_with_max_header_bytes() {
+    let opts = ServerOpts {
+        max_header_bytes: Some(64),
+        ..Default::default()
+    };
+    let mut s = Server::new_with_opts(opts);
+    s.mock("GET", "/").create();
+
+    let (status, _, _) = request(s.host_with_port(), "GET /", "x-a: 1\r\n");
+    assert_eq!("HTTP/1.1 200 OK\r\n", status);
+
+    let header = format!("x-a: {}\r\n", "a".repeat(64));
+    let (status, _, _) = request(s.host_with_port(), "GET /", &header);
+    assert_eq!("HTTP/1.1 431 Request Header Fields Too Large\r\n", status);
+}
+
+#[test]
+fn test_server_with_request_timeout_closes_connections_with_partial_headers() {
+    let opts = ServerOpts {
+        request_timeout: Some(Duration::from_millis(100)),
+        ..Default::default()
+    };
+    let s = Server::new_with_opts(opts);
+
+    let mut stream = TcpStream::connect(s.host_with_port()).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream.write_all(b"GET / HTTP/1.1\r\nhost: ").unwrap();
+
+    let mut response = vec![];
+    stream.read_to_end(&mut response).unwrap();
+    assert!(response.is_empty());
+}
+
+#[test]
+fn test_server_with_request_timeout_responds_to_partial_bodies() {
+    let opts = ServerOpts {
+        request_timeout: Some(Duration::from_millis(100)),
+        ..Default::default()
+    };
+    let mut s = Server::new_with_opts(opts);
+    let mock = s.mock("POST", "/").create();
+
+    let mut stream = TcpStream::connect(s.host_with_port()).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+        .write_all(b"POST / HTTP/1.1\r\ncontent-length: 10\r\n\r\nhel")
+        .unwrap();
+
+    let (status_line, _, _) = parse_stream(stream, false);
+    assert_eq!("HTTP/1.1 408 Request Timeout\r\n", status_line);
+
+    let err = mock.try_assert().unwrap_err();
+    assert_eq!(Some(0), err.actual_hits);
+    assert!(err
+        .last_unmatched_request
+        .unwrap()
+        .contains("content-length: 10\r\n"));
+}
+
 #[test]
 fn test_remove_a_single_mock() {
     let mut s = Server::new();
@@ -809,6 +2490,270 @@ fn test_regex_match_path() {
     assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status_line);
 }
 
+#[test]
+fn test_regex_match_path_with_many_patterns() {
+    let mut s = Server::new();
+    for i in 0..300 {
+        s.mock("GET", Matcher::Regex(format!(r"^/{}/\d+$", i)))
+            .with_body(i.to_string())
+            .create();
+    }
+
+    let (_, _, body) = request(s.host_with_port(), "GET /7/123", "");
+    assert_eq!("7", body);
+
+    let (_, _, body) = request(s.host_with_port(), "GET /299/1", "");
+    assert_eq!("299", body);
+}
+
+#[test]
+fn test_exact_ignore_case_match_path() {
+    let mut s = Server::new();
+    s.mock("GET", Matcher::ExactIgnoreCase("/Hello/World".to_string()))
+        .with_body("hi")
+        .create();
+
+    let (_, _, body) = request(s.host_with_port(), "GET /hELLo/wORLD", "");
+    assert_eq!("hi", body);
+
+    let (status_line, _, _) = request(s.host_with_port(), "GET /hello/world/1", "");
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status_line);
+}
+
+#[tokio::test]
+async fn test_reset_async() {
+    let mut s = Server::new_async().await;
+    s.mock("GET", "/hello").create_async().await;
+
+    s.reset_async().await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/hello", s.url()))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(501, response.status());
+}
+
+#[test]
+fn test_reset_async_without_tokio() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    let mut s = Server::new();
+    let handling = Arc::new(AtomicBool::new(false));
+    let flag = handling.clone();
+    s.mock("GET", "/slow")
+        .with_body_from_request(move |_| {
+            flag.store(true, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(200));
+            "slow".into()
+        })
+        .create();
+
+    let host = s.host_with_port();
+    let slow = thread::spawn(move || request(host, "GET /slow", "").2);
+    while !handling.load(Ordering::SeqCst) {
+        thread::sleep(Duration::from_millis(5));
+    }
+
+    futures::executor::block_on(s.reset_async());
+    assert_eq!("slow", slow.join().unwrap());
+
+    let (status_line, _, _) = request(s.host_with_port(), "GET /slow", "");
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status_line);
+
+    futures::executor::block_on(s.reset_async());
+}
+
+#[tokio::test]
+async fn test_reset_async_while_handling_a_request() {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    let mut s = Server::new_async().await;
+    let handling = Arc::new(AtomicBool::new(false));
+    let flag = handling.clone();
+    // holds the server state for a while, like a slow response would
+    s.mock("GET", "/slow")
+        .with_body_from_request(move |_| {
+            flag.store(true, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(300));
+            "slow".into()
+        })
+        .create_async()
+        .await;
+
+    let url = s.url();
+    let slow = tokio::spawn(async move {
+        reqwest::get(format!("{}/slow", url))
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap()
+    });
+    while !handling.load(Ordering::SeqCst) {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+
+    // the runtime keeps making progress while the reset waits for the server state
+    let ticks = Arc::new(AtomicUsize::new(0));
+    let counter = ticks.clone();
+    let ticker = tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            counter.fetch_add(1, Ordering::SeqCst);
+        }
+    });
+    s.reset_async().await;
+    ticker.abort();
+
+    assert!(ticks.load(Ordering::SeqCst) > 5);
+    assert_eq!("slow", slow.await.unwrap());
+
+    let response = reqwest::get(format!("{}/slow", s.url())).await.unwrap();
+    assert_eq!(501, response.status());
+}
+
+#[test]
+fn test_mock_count() {
+    let mut s = Server::new();
+    assert!(s.is_empty());
+
+    let m = s.mock("GET", "/a").create();
+    s.mock("GET", "/b").create();
+    assert_eq!(2, s.mock_count());
+    assert!(!s.is_empty());
+
+    m.remove();
+    assert_eq!(1, s.mock_count());
+
+    s.reset();
+    assert!(s.is_empty());
+}
+
+#[test]
+fn test_server_with_fault() {
+    let mut s = Server::new();
+    let mock = s.mock("GET", "/").with_body("ok").expect(1).create();
+
+    s.set_fault(Some(FaultConfig {
+        headers: vec![("retry-after".to_string(), "1".to_string())],
+        ..Default::default()
+    }));
+
+    for _ in 0..3 {
+        let (status, headers, _) = request(s.host_with_port(), "GET /", "");
+        assert_eq!("HTTP/1.1 503 Service Unavailable\r\n", status);
+        assert!(headers.contains(&"retry-after: 1".to_string()));
+    }
+
+    s.set_fault(None);
+
+    let (status, _, body) = request(s.host_with_port(), "GET /", "");
+    assert_eq!("HTTP/1.1 200 OK\r\n", status);
+    assert_eq!("ok", body);
+
+    s.set_busy(true);
+    let (status, _, _) = request(s.host_with_port(), "GET /", "");
+    assert_eq!("HTTP/1.1 503 Service Unavailable\r\n", status);
+
+    mock.assert();
+}
+
+#[test]
+fn test_server_with_fault_records_requests() {
+    let mut s = Server::new();
+    let mock = s.mock("POST", "/").with_body("ok").create();
+
+    s.set_busy(true);
+    for _ in 0..3 {
+        request_with_body(s.host_with_port(), "POST /", "", "retried");
+    }
+    s.set_busy(false);
+    request(s.host_with_port(), "POST /", "content-length: 0\r\n");
+
+    s.assert_any_request_matches(Matcher::Exact("retried".to_string()));
+    assert_eq!(1, s.requests_for(&mock).len());
+    mock.assert();
+}
+
+#[test]
+#[should_panic(expected = "InvalidStatusCode")]
+fn test_server_with_fault_rejects_overflowing_status() {
+    let mut s = Server::new();
+    s.set_fault(Some(FaultConfig {
+        status: 65536 + 200,
+        ..Default::default()
+    }));
+}
+
+#[test]
+#[should_panic(expected = "invalid probability")]
+fn test_server_with_fault_rejects_nan_probability() {
+    let mut s = Server::new();
+    s.set_fault(Some(FaultConfig {
+        probability: f64::NAN,
+        ..Default::default()
+    }));
+}
+
+#[test]
+fn test_request_rewriter() {
+    let mut s = Server::new();
+    s.with_request_rewriter(|request| {
+        if let Some(path) = request.path_and_query().strip_prefix("/api/v1") {
+            let path = path.to_string();
+            request.set_path_and_query(&path).unwrap();
+        }
+    });
+    s.mock("GET", "/users?page=2").with_body("users").create();
+
+    let (_, _, body) = request(s.host_with_port(), "GET /api/v1/users?page=2", "");
+    assert_eq!("users", body);
+
+    s.reset();
+    s.mock("GET", "/users?page=2").with_body("users").create();
+
+    let (status_line, _, _) = request(s.host_with_port(), "GET /api/v1/users?page=2", "");
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status_line);
+}
+
+#[test]
+fn test_prefix_match_path() {
+    let mut s = Server::new();
+    s.mock("GET", Matcher::Prefix("/api/".to_string()))
+        .with_body("api")
+        .create();
+
+    let (_, _, body) = request(s.host_with_port(), "GET /api/users?page=2", "");
+    assert_eq!("api", body);
+
+    let (status_line, _, _) = request(s.host_with_port(), "GET /apiary", "");
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status_line);
+}
+
+#[test]
+fn test_suffix_match_header() {
+    let mut s = Server::new();
+    s.mock("GET", "/")
+        .match_header("content-type", Matcher::Suffix("+json".to_string()))
+        .create();
+
+    let (status_line, _, _) = request(
+        s.host_with_port(),
+        "GET /",
+        "content-type: application/problem+json\r\n",
+    );
+    assert_eq!("HTTP/1.1 200 OK\r\n", status_line);
+
+    let (status_line, _, _) = request(
+        s.host_with_port(),
+        "GET /",
+        "content-type: application/json\r\n",
+    );
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status_line);
+}
+
 #[test]
 fn test_regex_match_header() {
     let mut s = Server::new();
@@ -1015,6 +2960,7 @@ fn test_all_of_missing_match_header() {
 }
 
 #[test]
+#[allow(clippy::useless_conversion)]
 fn test_large_utf8_body() {
     let mut s = Server::new();
     let mock_body: String = rand::thread_rng()
@@ -1034,11 +2980,31 @@ fn test_large_utf8_body() {
 fn test_body_from_file() {
     let mut s = Server::new();
     s.mock("GET", "/")
-        .with_body_from_file("tests/files/simple.http")
+        .with_body_from_file("tests/files/simple.http")
+        .create();
+    let (status_line, _, body) = request(s.host_with_port(), "GET /", "");
+    assert_eq!("HTTP/1.1 200 OK\r\n", status_line);
+    assert_eq!("test body\n", body);
+}
+
+#[test]
+fn test_body_from_file_stream() {
+    let content: Vec<u8> = (0..4 * 1024 * 1024).map(|_| rand::random::<u8>()).collect();
+    let mut path = std::env::temp_dir();
+    path.push(format!("mockito_stream_{}.bin", rand::random::<u32>()));
+    fs::write(&path, &content).unwrap();
+
+    let mut s = Server::new();
+    s.mock("GET", "/")
+        .with_body_from_file_stream(&path)
         .create();
-    let (status_line, _, body) = request(s.host_with_port(), "GET /", "");
+
+    let (status_line, headers, body) = binary_request(s.host_with_port(), "GET /", "", "");
+    fs::remove_file(&path).unwrap();
+
     assert_eq!("HTTP/1.1 200 OK\r\n", status_line);
-    assert_eq!("test body\n", body);
+    assert!(headers.contains(&format!("content-length: {}", content.len())));
+    assert_eq!(content, body);
 }
 
 #[test]
@@ -1057,6 +3023,45 @@ fn test_display_mock_matching_regex_path() {
     assert_eq!("\r\nGET ^/hello/\\d+$ (regex)\r\n", format!("{}", mock));
 }
 
+#[test]
+fn test_display_mock_matching_exact_ignore_case_path() {
+    let mut s = Server::new();
+    let mock = s.mock("GET", Matcher::ExactIgnoreCase("/Hello".to_string()));
+
+    assert_eq!(
+        "\r\nGET /Hello (exact, case-insensitive)\r\n",
+        format!("{}", mock)
+    );
+}
+
+#[test]
+fn test_display_mock_matching_prefix_path() {
+    let mut s = Server::new();
+    let mock = s.mock("GET", Matcher::Prefix("/api/".to_string()));
+
+    assert_eq!("\r\nGET /api/ (prefix)\r\n", format!("{}", mock));
+}
+
+#[test]
+fn test_dump_mocks() {
+    let mut s = Server::new();
+    let m1 = s.mock("GET", "/hello").create();
+    let m2 = s
+        .mock("POST", "/hello")
+        .match_header("content-type", "text/plain")
+        .match_body("world")
+        .create();
+
+    assert_eq!(
+        vec![
+            "\r\nGET /hello\r\n".to_string(),
+            "\r\nPOST /hello\r\ncontent-type: text/plain\r\nworld\r\n".to_string()
+        ],
+        s.dump_mocks()
+    );
+    assert_eq!(vec![m1.to_string(), m2.to_string()], s.dump_mocks());
+}
+
 #[test]
 fn test_display_mock_matching_any_path() {
     let mut s = Server::new();
@@ -1267,6 +3272,23 @@ fn test_expect_at_least_and_at_most() {
     mock.assert();
 }
 
+#[test]
+fn test_expect_approx() {
+    let mut s = Server::new();
+    let host = s.host_with_port();
+
+    for (hits, expected) in [(3, false), (4, true), (6, true), (7, false)] {
+        let mock = s.mock("GET", "/hello").expect_approx(5, 1).create();
+
+        for _ in 0..hits {
+            request(&host, "GET /hello", "");
+        }
+
+        assert_eq!(expected, mock.try_assert().is_ok(), "{} hits", hits);
+        mock.remove();
+    }
+}
+
 #[test]
 fn test_expect_at_least() {
     let mut s = Server::new();
@@ -1498,6 +3520,129 @@ fn test_assert_panics_with_too_many_requests() {
     mock.assert();
 }
 
+#[test]
+fn test_try_assert() {
+    let mut s = Server::new();
+    let host = s.host_with_port();
+    let mock = s.mock("GET", "/hello").create();
+
+    let message = mock.try_assert().unwrap_err().to_string();
+    assert!(message.contains("> Expected 1 request(s) to:"));
+    assert!(message.contains("...but received 0"));
+
+    request(host, "GET /hello", "");
+
+    assert_eq!(Ok(()), mock.try_assert());
+}
+
+#[test]
+fn test_try_assert_error_fields() {
+    let mut s = Server::new();
+    let host = s.host_with_port();
+    let mock = s.mock("GET", "/hello").expect_at_least(2).create();
+
+    request(&host, "GET /hello", "");
+    request(&host, "GET /bye", "");
+
+    let err = mock.try_assert().unwrap_err();
+    assert_eq!(Some(2), err.expected_min);
+    assert_eq!(None, err.expected_max);
+    assert_eq!(Some(1), err.actual_hits);
+    assert_eq!(mock.to_string(), err.mock);
+    assert!(err.last_unmatched_request.unwrap().contains("GET /bye"));
+
+    mock.remove();
+    let err = mock.try_assert().unwrap_err();
+    assert_eq!(None, err.actual_hits);
+    assert_eq!(
+        "could not retrieve enough information about the remote mock",
+        err.to_string()
+    );
+}
+
+#[test]
+fn test_assert_request_header_order() {
+    let mut s = Server::new();
+    let m = s.mock("GET", "/").create();
+
+    request(
+        s.host_with_port(),
+        "GET /",
+        "date: today\r\nx-other: 1\r\nAuthorization: secret\r\n",
+    );
+
+    m.assert_request_header_order(&["date", "authorization"]);
+}
+
+#[test]
+#[should_panic(expected = "in this order")]
+fn test_assert_request_header_order_reordered() {
+    let mut s = Server::new();
+    let m = s.mock("GET", "/").create();
+
+    request(
+        s.host_with_port(),
+        "GET /",
+        "authorization: secret\r\ndate: today\r\n",
+    );
+
+    m.assert_request_header_order(&["date", "authorization"]);
+}
+
+#[test]
+fn test_on_request_and_on_response() {
+    let requests = Arc::new(Mutex::new(vec![]));
+    let responses = Arc::new(Mutex::new(vec![]));
+
+    let mut s = Server::new();
+    let requests_clone = requests.clone();
+    s.on_request(move |request| {
+        requests_clone
+            .lock()
+            .unwrap()
+            .push(request.path().to_string())
+    });
+    let responses_clone = responses.clone();
+    s.on_response(move |request, status| {
+        responses_clone
+            .lock()
+            .unwrap()
+            .push((request.path().to_string(), status))
+    });
+    s.mock("GET", "/hello").with_status(201).create();
+
+    request(s.host_with_port(), "GET /hello", "");
+    request(s.host_with_port(), "GET /missing", "");
+
+    assert_eq!(vec!["/hello", "/missing"], *requests.lock().unwrap());
+    assert_eq!(
+        vec![("/hello".to_string(), 201)],
+        *responses.lock().unwrap()
+    );
+}
+
+#[test]
+fn test_mock_report() {
+    let mut s = Server::new();
+    let m1 = s.mock("GET", "/a").create();
+    let m2 = s.mock("GET", "/b").expect_at_least(2).create();
+    let m3 = s.mock("GET", "/c").expect_approx(5, 1).create();
+
+    request(s.host_with_port(), "GET /b", "");
+    request(s.host_with_port(), "GET /c", "");
+    request(s.host_with_port(), "GET /c", "");
+
+    let report = s.mock_report();
+    assert_eq!(
+        vec![
+            (m1.to_string(), 0, None, None),
+            (m2.to_string(), 1, Some(2), None),
+            (m3.to_string(), 2, Some(4), Some(6)),
+        ],
+        report
+    );
+}
+
 #[test]
 fn test_expect_zero() {
     let mut s = Server::new();
@@ -1625,6 +3770,65 @@ fn test_assert_with_last_unmatched_request_and_body() {
     mock.assert();
 }
 
+#[test]
+fn test_request_as_curl() {
+    let mut s = Server::new();
+    let host = s.host_with_port();
+    let mock = s.mock("POST", Matcher::Any).create();
+
+    request_with_body(
+        &host,
+        "POST /hello?x=1",
+        "x-name: it's me\r\n",
+        "{\"a\": 1}",
+    );
+
+    assert_eq!(
+        format!(
+            "curl -X POST 'http://{}/hello?x=1' -H 'x-name: it'\\''s me' -H 'content-length: 8' --data-binary '{{\"a\": 1}}'",
+            host
+        ),
+        mock.last_request().unwrap().as_curl()
+    );
+}
+
+#[test]
+#[should_panic(expected = "> Replay the last unmatched request with:\ncurl -X GET")]
+fn test_assert_with_curl() {
+    let opts = ServerOpts {
+        assert_with_curl: true,
+        ..Default::default()
+    };
+    let mut s = Server::new_with_opts(opts);
+    let mock = s.mock("GET", "/hello").create();
+
+    request(s.host_with_port(), "GET /bye", "");
+
+    mock.assert();
+}
+
+#[test]
+fn test_requests_for() {
+    let mut s = Server::new();
+    let host = s.host_with_port();
+    let users = s.mock("POST", "/users").expect(2).create();
+    let posts = s.mock("POST", "/posts").create();
+
+    request_with_body(&host, "POST /users", "", "alice");
+    request_with_body(&host, "POST /posts", "", "hello");
+    request_with_body(&host, "POST /users", "", "bob");
+    request(&host, "GET /unmatched", "");
+
+    let bodies = |requests: Vec<mockito::Request>| -> Vec<String> {
+        requests
+            .iter()
+            .map(|request| request.utf8_lossy_body().unwrap().into_owned())
+            .collect()
+    };
+    assert_eq!(vec!["alice", "bob"], bodies(s.requests_for(&users)));
+    assert_eq!(vec!["hello"], bodies(s.requests_for(&posts)));
+}
+
 #[test]
 fn test_request_from_thread() {
     let mut s = Server::new();
@@ -1675,10 +3879,7 @@ fn test_head_request_with_overridden_content_length() {
 
     let (_, headers, _) = request(host, "HEAD /", "");
 
-    assert_eq!(
-        vec!["connection: close", "content-length: 100"],
-        headers[0..=1]
-    );
+    assert_eq!("content-length: 100", headers[0]);
 }
 
 #[test]
@@ -1694,70 +3895,273 @@ fn test_propagate_protocol_to_response() {
 }
 
 #[test]
-fn test_large_body_without_content_length() {
+fn test_http_1_1_keeps_connections_alive_by_default() {
+    let mut s = Server::new();
+    s.mock("GET", "/").expect(2).create();
+
+    let stream = request_stream("1.1", s.host_with_port(), "GET /", "", "");
+    let mut reused = stream.try_clone().unwrap();
+    let (status_line, headers, _) = parse_stream(stream, false);
+    assert_eq!("HTTP/1.1 200 OK\r\n", status_line);
+    assert!(!headers
+        .iter()
+        .any(|header| header.starts_with("connection:")));
+
+    reused.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+    let (status_line, _, _) = parse_stream(reused, false);
+    assert_eq!("HTTP/1.1 200 OK\r\n", status_line);
+}
+
+#[test]
+fn test_http_1_1_closes_connections_on_request() {
+    let mut s = Server::new();
+    s.mock("GET", "/").create();
+
+    let mut stream = request_stream(
+        "1.1",
+        s.host_with_port(),
+        "GET /",
+        "connection: close\r\n",
+        "",
+    );
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("connection: close\r\n"));
+}
+
+#[test]
+fn test_http_1_0_closes_connections_by_default() {
+    let mut s = Server::new();
+    s.mock("GET", "/").create();
+
+    let mut stream = request_stream("1.0", s.host_with_port(), "GET /", "", "");
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.0 200 OK\r\n"));
+    assert!(response.contains("connection: close\r\n"));
+}
+
+#[test]
+fn test_http_1_0_keeps_connections_alive_on_request() {
+    let mut s = Server::new();
+    s.mock("GET", "/").expect(2).create();
+
+    let stream = request_stream(
+        "1.0",
+        s.host_with_port(),
+        "GET /",
+        "connection: keep-alive\r\n",
+        "",
+    );
+    let mut reused = stream.try_clone().unwrap();
+    let (status_line, headers, _) = parse_stream(stream, false);
+    assert_eq!("HTTP/1.0 200 OK\r\n", status_line);
+    assert!(headers.contains(&"connection: keep-alive".to_string()));
+
+    reused
+        .write_all(b"GET / HTTP/1.0\r\nconnection: keep-alive\r\n\r\n")
+        .unwrap();
+    let (status_line, _, _) = parse_stream(reused, false);
+    assert_eq!("HTTP/1.0 200 OK\r\n", status_line);
+}
+
+#[test]
+fn test_large_body_without_content_length() {
+    let mut s = Server::new();
+    let host = s.host_with_port();
+    let body = "123".repeat(2048);
+
+    s.mock("POST", "/").match_body(body.as_str()).create();
+
+    let headers = format!("content-length: {}\r\n", body.len());
+    let stream = request_stream("1.0", host, "POST /", &headers, &body);
+
+    let (status_line, _, _) = parse_stream(stream, false);
+    assert_eq!("HTTP/1.0 200 OK\r\n", status_line);
+}
+
+#[test]
+fn test_transfer_encoding_chunked() {
+    let mut s = Server::new();
+    let host = s.host_with_port();
+    s.mock("POST", "/")
+        .match_body("Hello, chunked world!")
+        .create();
+
+    let body = "3\r\nHel\r\n5\r\nlo, c\r\nD\r\nhunked world!\r\n0\r\n\r\n";
+
+    let (status, _, _) = parse_stream(
+        request_stream(
+            "1.1",
+            host,
+            "POST /",
+            "Transfer-Encoding: chunked\r\n",
+            body,
+        ),
+        false,
+    );
+
+    assert_eq!("HTTP/1.1 200 OK\r\n", status);
+}
+
+#[test]
+fn test_mock_with_forced_chunked_transfer_encoding() {
+    let mut s = Server::new();
+    s.mock("GET", "/")
+        .with_body("hello")
+        .with_transfer_encoding(TransferEncoding::Chunked)
+        .create();
+
+    let (status, headers, body) = request(s.host_with_port(), "GET /", "");
+    assert_eq!("HTTP/1.1 200 OK\r\n", status);
+    assert!(headers.contains(&"transfer-encoding: chunked".to_string()));
+    assert!(!headers.iter().any(|h| h.starts_with("content-length:")));
+    assert_eq!("hello", body);
+}
+
+#[test]
+fn test_mock_with_forced_identity_transfer_encoding() {
+    let mut s = Server::new();
+    s.mock("GET", "/")
+        .with_chunked_body(|w| {
+            w.write_all(b"hello")?;
+            w.write_all(b" world")
+        })
+        .with_transfer_encoding(TransferEncoding::Identity)
+        .create();
+
+    let (status, headers, body) = request(s.host_with_port(), "GET /", "");
+    assert_eq!("HTTP/1.1 200 OK\r\n", status);
+    assert!(headers.contains(&"content-length: 11".to_string()));
+    assert!(!headers.iter().any(|h| h.starts_with("transfer-encoding:")));
+    assert_eq!("hello world", body);
+}
+
+#[test]
+fn test_match_exact_query() {
+    let mut s = Server::new();
+    let host = s.host_with_port();
+    s.mock("GET", "/hello")
+        .match_query(Matcher::Exact("number=one".to_string()))
+        .create();
+
+    let (status_line, _, _) = request(&host, "GET /hello?number=one", "");
+    assert_eq!("HTTP/1.1 200 OK\r\n", status_line);
+
+    let (status_line, _, _) = request(&host, "GET /hello?number=two", "");
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status_line);
+}
+
+#[test]
+fn test_match_exact_query_via_path() {
+    let mut s = Server::new();
+    let host = s.host_with_port();
+    s.mock("GET", "/hello?number=one").create();
+
+    let (status_line, _, _) = request(&host, "GET /hello?number=one", "");
+    assert_eq!("HTTP/1.1 200 OK\r\n", status_line);
+
+    let (status_line, _, _) = request(&host, "GET /hello?number=two", "");
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status_line);
+}
+
+#[test]
+fn test_match_query_param_greater_than() {
     let mut s = Server::new();
-    let host = s.host_with_port();
-    let body = "123".repeat(2048);
+    s.mock("GET", "/users")
+        .match_query_param("page", Matcher::GreaterThan(0))
+        .create();
 
-    s.mock("POST", "/").match_body(body.as_str()).create();
+    let (status_line, _, _) = request(s.host_with_port(), "GET /users?sort=name&page=3", "");
+    assert_eq!("HTTP/1.1 200 OK\r\n", status_line);
 
-    let headers = format!("content-length: {}\r\n", body.len());
-    let stream = request_stream("1.0", host, "POST /", &headers, &body);
+    let (status_line, _, _) = request(s.host_with_port(), "GET /users?page=abc", "");
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status_line);
 
-    let (status_line, _, _) = parse_stream(stream, false);
-    assert_eq!("HTTP/1.0 200 OK\r\n", status_line);
+    let (status_line, _, _) = request(s.host_with_port(), "GET /users?page=0", "");
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status_line);
+
+    let (status_line, _, _) = request(s.host_with_port(), "GET /users", "");
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status_line);
 }
 
 #[test]
-fn test_transfer_encoding_chunked() {
+fn test_match_query_param_count() {
     let mut s = Server::new();
-    let host = s.host_with_port();
-    s.mock("POST", "/")
-        .match_body("Hello, chunked world!")
+    s.mock("GET", "/test")
+        .match_query(Matcher::AllOf(vec![
+            Matcher::UrlEncoded("hello".into(), "world".into()),
+            Matcher::UrlEncoded("greeting".into(), "good day".into()),
+            Matcher::QueryParamCount(2),
+        ]))
         .create();
 
-    let body = "3\r\nHel\r\n5\r\nlo, c\r\nD\r\nhunked world!\r\n0\r\n\r\n";
-
-    let (status, _, _) = parse_stream(
-        request_stream(
-            "1.1",
-            host,
-            "POST /",
-            "Transfer-Encoding: chunked\r\n",
-            body,
-        ),
-        false,
+    let (status_line, _, _) = request(
+        s.host_with_port(),
+        "GET /test?hello=world&greeting=good%20day",
+        "",
     );
+    assert_eq!("HTTP/1.1 200 OK\r\n", status_line);
 
-    assert_eq!("HTTP/1.1 200 OK\r\n", status);
+    let (status_line, _, _) = request(
+        s.host_with_port(),
+        "GET /test?hello=world&greeting=good%20day&extra=1",
+        "",
+    );
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status_line);
 }
 
 #[test]
-fn test_match_exact_query() {
+fn test_match_query_urlencoded_all() {
     let mut s = Server::new();
-    let host = s.host_with_port();
-    s.mock("GET", "/hello")
-        .match_query(Matcher::Exact("number=one".to_string()))
+    s.mock("GET", "/test")
+        .match_query(Matcher::UrlEncodedAll(vec![
+            ("tag".into(), "a".into()),
+            ("tag".into(), "b".into()),
+            ("greeting".into(), "good day".into()),
+        ]))
         .create();
 
-    let (status_line, _, _) = request(&host, "GET /hello?number=one", "");
+    let (status_line, _, _) = request(
+        s.host_with_port(),
+        "GET /test?greeting=good%20day&tag=b&tag=a",
+        "",
+    );
     assert_eq!("HTTP/1.1 200 OK\r\n", status_line);
 
-    let (status_line, _, _) = request(&host, "GET /hello?number=two", "");
+    let (status_line, _, _) = request(
+        s.host_with_port(),
+        "GET /test?greeting=good%20day&tag=b&tag=a&extra=1",
+        "",
+    );
+    assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status_line);
+
+    let (status_line, _, _) = request(
+        s.host_with_port(),
+        "GET /test?greeting=good%20day&tag=a",
+        "",
+    );
     assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status_line);
 }
 
 #[test]
-fn test_match_exact_query_via_path() {
+fn test_match_query_exact_ordered() {
+    let matcher = Matcher::QueryExactOrdered(vec![
+        ("b".into(), "2".into()),
+        ("a".into(), "hello world".into()),
+    ]);
+
     let mut s = Server::new();
-    let host = s.host_with_port();
-    s.mock("GET", "/hello?number=one").create();
+    s.mock("GET", "/test").match_query(matcher.clone()).create();
 
-    let (status_line, _, _) = request(&host, "GET /hello?number=one", "");
+    let (status_line, _, _) = request(s.host_with_port(), "GET /test?b=2&a=hello%20world", "");
     assert_eq!("HTTP/1.1 200 OK\r\n", status_line);
 
-    let (status_line, _, _) = request(&host, "GET /hello?number=two", "");
+    let (status_line, _, _) = request(s.host_with_port(), "GET /test?a=hello%20world&b=2", "");
     assert_eq!("HTTP/1.1 501 Not Implemented\r\n", status_line);
+
+    assert_eq!("b=2&a=hello world (ordered query)", matcher.to_string());
 }
 
 #[test]
@@ -1961,6 +4365,27 @@ fn test_request_matcher_body() {
     m.assert();
 }
 
+#[test]
+fn test_request_elapsed_since_connect() {
+    let mut s = Server::new();
+    s.mock("POST", "/")
+        .match_request(|request| request.elapsed_since_connect() >= Duration::from_millis(100))
+        .create();
+
+    let mut stream = request_stream(
+        "1.1",
+        s.host_with_port(),
+        "POST /",
+        "content-length: 5\r\n",
+        "",
+    );
+    thread::sleep(Duration::from_millis(100));
+    stream.write_all(b"hello").unwrap();
+
+    let (status, _, _) = parse_stream(stream, false);
+    assert_eq!("HTTP/1.1 200 OK\r\n", status);
+}
+
 #[test]
 fn test_default_headers() {
     let mut s = Server::new();
@@ -1968,12 +4393,9 @@ fn test_default_headers() {
     s.mock("GET", "/").create();
 
     let (_, headers, _) = request(host, "GET /", "");
-    assert_eq!(3, headers.len());
-    assert_eq!(
-        vec!["connection: close", "content-length: 0"],
-        headers[0..=1]
-    );
-    let date_parts: Vec<&str> = headers[2].split(':').collect();
+    assert_eq!(2, headers.len());
+    assert_eq!("content-length: 0", headers[0]);
+    let date_parts: Vec<&str> = headers[1].split(':').collect();
     assert_eq!("date", date_parts[0]);
 }
 
@@ -2021,6 +4443,33 @@ fn test_missing_create_good() {
     });
 }
 
+#[test]
+fn test_warn_on_duplicate_mocks() {
+    testing_logger::setup();
+
+    let opts = ServerOpts {
+        warn_on_duplicate_mocks: true,
+        ..Default::default()
+    };
+    let mut s = Server::new_with_opts(opts);
+    let _m1 = s.mock("GET", "/hello").with_status(200).create();
+    let _m2 = s.mock("GET", "/hello").with_status(404).create();
+    let _m3 = s.mock("GET", "/world").create();
+
+    testing_logger::validate(|captured_logs| {
+        let warnings = captured_logs
+            .iter()
+            .filter(|c| c.level == log::Level::Warn)
+            .collect::<Vec<&testing_logger::CapturedLog>>();
+
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            warnings[0].body,
+            "Duplicate mock, an existing mock has the same matchers \r\nGET /hello\r\n"
+        );
+    });
+}
+
 #[test]
 fn test_same_endpoint_different_responses() {
     let mut s = Server::new();
@@ -2069,6 +4518,150 @@ fn test_same_endpoint_different_responses_last_one_forever() {
     assert_eq!(response_500_3.0, "HTTP/1.1 500 Internal Server Error\r\n");
 }
 
+#[test]
+fn test_assert_mocks_hit_in_order() {
+    let mut s = Server::new();
+    let host = s.host_with_port();
+
+    let _login = s.mock("POST", "/login").create();
+    let _fetch = s.mock("GET", "/data").expect(2).create();
+    let _logout = s.mock("POST", "/logout").create();
+
+    request(&host, "POST /login", "");
+    request(&host, "GET /data", "");
+    request(&host, "GET /data", "");
+    request(&host, "POST /logout", "");
+
+    s.assert_mocks_hit_in_order();
+}
+
+#[test]
+#[should_panic(expected = "> Expected mocks to be hit in the order they were created, but:")]
+fn test_assert_mocks_hit_in_order_panics_when_out_of_order() {
+    let mut s = Server::new();
+    let host = s.host_with_port();
+
+    let _login = s.mock("POST", "/login").create();
+    let _logout = s.mock("POST", "/logout").create();
+
+    request(&host, "POST /logout", "");
+    request(&host, "POST /login", "");
+
+    s.assert_mocks_hit_in_order();
+}
+
+#[test]
+fn test_assert_sequence() {
+    let mut s = Server::new();
+    let host = s.host_with_port();
+
+    let auth = s.mock("POST", "/auth").create();
+    let fetch = s.mock("GET", "/data").expect(2).create();
+    let logout = s.mock("POST", "/logout").create();
+
+    request(&host, "POST /auth", "");
+    request(&host, "GET /data", "");
+    request(&host, "POST /logout", "");
+    request(&host, "GET /data", "");
+
+    s.assert_sequence(&[&auth, &fetch, &logout]);
+}
+
+#[test]
+#[should_panic(
+    expected = "...but they were hit in this order:\n\r\nGET /data\r\n\r\nPOST /auth\r\n"
+)]
+fn test_assert_sequence_panics_when_out_of_order() {
+    let mut s = Server::new();
+    let host = s.host_with_port();
+
+    let auth = s.mock("POST", "/auth").create();
+    let fetch = s.mock("GET", "/data").create();
+
+    request(&host, "GET /data", "");
+    request(&host, "POST /auth", "");
+
+    s.assert_sequence(&[&auth, &fetch]);
+}
+
+#[test]
+fn test_assert_no_duplicate_requests() {
+    let mut s = Server::new();
+    let host = s.host_with_port();
+    s.mock("POST", "/payments").expect(2).create();
+
+    request_with_body(&host, "POST /payments", "", "amount=1");
+    request_with_body(&host, "POST /payments", "", "amount=2");
+
+    s.assert_no_duplicate_requests();
+}
+
+#[test]
+#[should_panic(
+    expected = "> Expected no duplicate requests, but the following request was received more than once:"
+)]
+fn test_assert_no_duplicate_requests_panics_with_duplicates() {
+    let mut s = Server::new();
+    let host = s.host_with_port();
+    s.mock("POST", "/payments").expect(2).create();
+
+    request_with_body(&host, "POST /payments", "", "amount=1");
+    request_with_body(&host, "POST /payments", "", "amount=1");
+
+    s.assert_no_duplicate_requests();
+}
+
+#[test]
+fn test_assert_any_request_matches() {
+    let mut s = Server::new();
+    let host = s.host_with_port();
+    s.mock("POST", "/users").expect(2).create();
+
+    request_with_body(&host, "POST /users", "", r#"{"name": "bob"}"#);
+    request_with_body(&host, "POST /users", "", r#"{"name": "alice", "age": 30}"#);
+
+    s.assert_any_request_matches(Matcher::PartialJson(json!({"name": "alice"})));
+}
+
+#[test]
+fn test_assert_any_request_matches_skips_non_json_bodies() {
+    let mut s = Server::new();
+    let host = s.host_with_port();
+    s.mock("GET", "/").create();
+    s.mock("POST", "/users").expect(2).create();
+
+    request(&host, "GET /", "");
+    request_with_body(&host, "POST /users", "", "name=bob");
+    request_with_body(&host, "POST /users", "", r#"{"name": "alice", "age": 30}"#);
+
+    s.assert_any_request_matches(Matcher::PartialJson(json!({"name": "alice"})));
+    s.assert_any_request_matches(Matcher::Json(json!({"name": "alice", "age": 30})));
+}
+
+#[test]
+#[should_panic(expected = "> Expected a request with a body matching:")]
+fn test_assert_any_request_matches_panics_without_json_bodies() {
+    let mut s = Server::new();
+    let host = s.host_with_port();
+    s.mock("POST", "/users").create();
+
+    request_with_body(&host, "POST /users", "", "name=alice");
+
+    s.assert_any_request_matches(Matcher::PartialJson(json!({"name": "alice"})));
+}
+
+#[test]
+#[should_panic(expected = "> Expected a request with a body matching:")]
+fn test_assert_any_request_matches_panics_without_matches() {
+    let mut s = Server::new();
+    let host = s.host_with_port();
+    s.mock("POST", "/users").create();
+
+    request_with_body(&host, "POST /users", "", r#"{"name": "bob"}"#);
+
+    s.assert_any_request_matches(Matcher::PartialJson(json!({"name": "alice"})));
+}
+
 #[test]
 fn test_matched_bool() {
     let mut s = Server::new();
@@ -2093,6 +4686,32 @@ fn test_invalid_header_field_name() {
     assert_eq!("HTTP/1.1 400 Bad Request\r\n", uppercase_status_line);
 }
 
+#[test]
+fn test_server_port_and_host() {
+    let s = Server::new();
+
+    assert_eq!(s.socket_address().port(), s.port());
+    assert_eq!(IpAddr::from([127, 0, 0, 1]), s.host());
+    assert_eq!(format!("{}:{}", s.host(), s.port()), s.host_with_port());
+}
+
+#[test]
+fn test_server_with_ipv6_host() {
+    let opts = ServerOpts {
+        host: "::1",
+        ..Default::default()
+    };
+    let mut s = Server::new_with_opts(opts);
+    s.mock("GET", "/").with_body("ipv6").create();
+
+    assert_eq!(format!("http://[::1]:{}", s.port()), s.url());
+    assert_eq!(format!("[::1]:{}", s.port()), s.host_with_port());
+
+    let (status, _, body) = request(s.host_with_port(), "GET /", "");
+    assert_eq!("HTTP/1.1 200 OK\r\n", status);
+    assert_eq!("ipv6", body);
+}
+
 #[test]
 fn test_running_multiple_servers() {
     let mut s1 = Server::new();
@@ -2118,6 +4737,7 @@ static SERIAL_POOL_TESTS: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new
 const DEFAULT_POOL_SIZE: usize = if cfg!(target_os = "macos") { 20 } else { 50 };
 
 #[test]
+#[allow(clippy::needless_borrows_for_generic_args)]
 fn test_server_pool() {
     // two tests can't monopolize the pool at the same time
     let _lock = SERIAL_POOL_TESTS.blocking_lock();
@@ -2137,7 +4757,24 @@ fn test_server_pool() {
     }
 }
 
+#[test]
+fn test_reset_pool() {
+    let _lock = SERIAL_POOL_TESTS.blocking_lock();
+
+    let s = Server::new();
+    let address = s.socket_address();
+    drop(s);
+
+    mockito::reset_pool();
+
+    assert!(TcpStream::connect(address).is_err());
+
+    let s = Server::new();
+    assert_ne!(address, s.socket_address());
+}
+
 #[tokio::test(flavor = "multi_thread")]
+#[allow(clippy::needless_borrows_for_generic_args)]
 async fn test_server_pool_async() {
     // two tests can't monopolize the pool at the same time
     let _lock = SERIAL_POOL_TESTS.lock().await;
@@ -2179,6 +4816,78 @@ async fn test_http2_requests_async() {
     m1.assert_async().await;
 }
 
+#[tokio::test]
+async fn test_mock_with_redirect_async() {
+    let mut s = Server::new_async().await;
+    let m1 = s
+        .mock("GET", "/old")
+        .with_temporary_redirect("/new")
+        .create_async()
+        .await;
+    let m2 = s
+        .mock("GET", "/new")
+        .with_body("moved")
+        .create_async()
+        .await;
+
+    let response = reqwest::get(format!("{}/old", s.url())).await.unwrap();
+
+    assert_eq!(200, response.status());
+    assert_eq!("moved", response.text().await.unwrap());
+
+    m1.assert_async().await;
+    m2.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_request_is_http2_async() {
+    let mut s = Server::new_async().await;
+    s.mock("GET", "/")
+        .with_body_from_request(|request| request.is_http2().to_string().into())
+        .expect(2)
+        .create_async()
+        .await;
+
+    let h2_client = reqwest::Client::builder()
+        .http2_prior_knowledge()
+        .build()
+        .unwrap();
+    let body = h2_client
+        .get(s.url())
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert_eq!("true", body);
+
+    let body = reqwest::get(s.url()).await.unwrap().text().await.unwrap();
+    assert_eq!("false", body);
+}
+
+#[tokio::test]
+async fn test_match_connection_request_index_async() {
+    let mut s = Server::new_async().await;
+    s.mock("GET", "/")
+        .match_connection_request_index(1)
+        .create_async()
+        .await;
+
+    let client = reqwest::Client::builder()
+        .http2_prior_knowledge()
+        .build()
+        .unwrap();
+
+    let mut statuses = vec![];
+    for _ in 0..3 {
+        let response = client.get(s.url()).send().await.unwrap();
+        statuses.push(response.status().as_u16());
+    }
+
+    assert_eq!(vec![501, 200, 501], statuses);
+}
+
 #[tokio::test]
 async fn test_simple_route_mock_async() {
     let mut s = Server::new_async().await;
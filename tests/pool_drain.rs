@@ -0,0 +1,42 @@
+// Relies on getting the same pooled server back, so this lives in its own test binary.
+
+use mockito::Server;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+#[test]
+fn test_recycled_server_drops_stale_connections() {
+    for _ in 0..10 {
+        let mut server = Server::new();
+        let address = server.host_with_port();
+        server.mock("GET", "/").create();
+
+        // a keep-alive connection left over from the previous user of the server
+        let mut stale = TcpStream::connect(&address).unwrap();
+        stale.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        let mut status_line = [0; 15];
+        stale.read_exact(&mut status_line).unwrap();
+        assert_eq!(b"HTTP/1.1 200 OK", &status_line);
+
+        // a connection which might not have been accepted yet
+        let mut pending = TcpStream::connect(&address).unwrap();
+
+        drop(server);
+
+        let mut server = Server::new();
+        assert_eq!(address, server.host_with_port());
+        let mock = server.mock("GET", "/").expect(0).create();
+
+        for stream in [&mut stale, &mut pending] {
+            stream
+                .set_read_timeout(Some(Duration::from_secs(1)))
+                .unwrap();
+            let _ = stream.write_all(b"GET / HTTP/1.1\r\n\r\n");
+            let mut response = vec![];
+            let _ = stream.read_to_end(&mut response);
+        }
+
+        mock.assert();
+    }
+}
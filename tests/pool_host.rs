@@ -0,0 +1,15 @@
+// The pool host is global to the process, so this lives in its own test binary.
+
+use mockito::Server;
+use std::net::{IpAddr, Ipv4Addr, TcpStream};
+
+#[test]
+fn test_set_pool_host() {
+    mockito::set_pool_host("0.0.0.0").unwrap();
+
+    let s = Server::new();
+    assert_eq!(IpAddr::V4(Ipv4Addr::UNSPECIFIED), s.host());
+    assert!(TcpStream::connect(("127.0.0.1", s.port())).is_ok());
+
+    assert!(mockito::set_pool_host("127.0.0.1").is_err());
+}
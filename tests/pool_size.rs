@@ -0,0 +1,31 @@
+// The pool size is global to the process, so this lives in its own test binary.
+
+use mockito::Server;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn test_set_pool_size() {
+    mockito::set_pool_size(1).unwrap();
+
+    let server = Server::new();
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let server = Server::new();
+        sender.send(server.host_with_port()).unwrap();
+    });
+
+    // the pool is exhausted, so the second server has to wait
+    assert!(receiver.recv_timeout(Duration::from_millis(500)).is_err());
+
+    let host_with_port = server.host_with_port();
+    drop(server);
+
+    // the recycled server is handed over
+    let recycled = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(host_with_port, recycled);
+
+    assert!(mockito::set_pool_size(10).is_err());
+}
@@ -0,0 +1,19 @@
+// The pool size is global to the process, so this lives in its own test binary.
+
+use mockito::{ErrorKind, Server};
+use std::time::Duration;
+
+#[test]
+fn test_new_with_pool_timeout() {
+    mockito::set_pool_size(1).unwrap();
+
+    let server = Server::new_with_pool_timeout(Duration::from_millis(100)).unwrap();
+
+    // the pool is exhausted
+    let err = Server::new_with_pool_timeout(Duration::from_millis(100)).unwrap_err();
+    assert!(matches!(err.kind, ErrorKind::ServerBusy));
+
+    drop(server);
+
+    assert!(Server::new_with_pool_timeout(Duration::from_millis(100)).is_ok());
+}
@@ -0,0 +1,22 @@
+// Resetting all pooled servers affects the whole process, so this lives in its own test binary.
+
+use mockito::Server;
+
+#[test]
+fn test_reset_all_pooled_servers() {
+    let mut in_use = Server::new();
+    in_use.mock("GET", "/").create();
+
+    let mut returned = Server::new();
+    returned.mock("GET", "/").create();
+    let returned_address = returned.host_with_port();
+    drop(returned);
+
+    mockito::reset_all_pooled_servers();
+
+    assert!(in_use.dump_mocks().is_empty());
+
+    let reacquired = Server::new();
+    assert_eq!(returned_address, reacquired.host_with_port());
+    assert!(reacquired.dump_mocks().is_empty());
+}